@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use serde_derive::{Serialize, Deserialize};
+
+use super::sprite::{Atlas, Region, Trim};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub region: Region,
+    // In milliseconds.
+    pub duration: u32,
+    // For frames imported from a packed sheet.
+    #[serde(default)]
+    pub trim: Option<Trim>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlayMode {
+    Once,
+    Loop,
+    PingPong,
+}
+
+// Fired when the clip enters the given frame. Useful for footstep sounds,
+// spawning a projectile on the right frame...
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameEvent {
+    pub frame: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimationClip {
+    pub name: String,
+    pub frames: Vec<Frame>,
+    pub mode: PlayMode,
+    #[serde(default)]
+    pub events: Vec<FrameEvent>,
+}
+
+impl AnimationClip {
+
+    pub fn new(name: &str, frames: Vec<Frame>, mode: PlayMode) -> AnimationClip {
+        AnimationClip { name: name.to_string(), frames, mode, events: Vec::new() }
+    }
+
+    // All the frames have the same duration. Unknown region names are skipped.
+    pub fn from_atlas(name: &str, atlas: &Atlas, regions: &[&str], duration: u32, mode: PlayMode) -> AnimationClip {
+        let frames = regions.iter()
+            .filter_map(|r| atlas.region(r).map(|region| Frame { region, duration, trim: atlas.trim(r) }))
+            .collect();
+        AnimationClip::new(name, frames, mode)
+    }
+
+    pub fn with_event(mut self, frame: usize, name: &str) -> AnimationClip {
+        self.events.push(FrameEvent { frame, name: name.to_string() });
+        self
+    }
+
+    pub fn total_duration(&self) -> u32 {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+}
+
+// Playback position in a clip.
+#[derive(Debug, Clone, Default)]
+pub struct ClipPlayer {
+    frame: usize,
+    elapsed: u32,
+    backward: bool,
+    started: bool,
+    finished: bool,
+}
+
+impl ClipPlayer {
+
+    pub fn new() -> ClipPlayer {
+        ClipPlayer::default()
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    // Only a clip played Once can finish.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn enter_frame(&mut self, clip: &AnimationClip, frame: usize, events: &mut Vec<String>) {
+        self.frame = frame;
+        for e in clip.events.iter().filter(|e| e.frame == frame) {
+            events.push(e.name.clone());
+        }
+    }
+
+    // dt in milliseconds. Names of the events fired are pushed in events.
+    pub fn update(&mut self, clip: &AnimationClip, dt: u32, events: &mut Vec<String>) {
+        let count = clip.frames.len();
+        if count == 0 {
+            return;
+        }
+
+        if !self.started {
+            self.started = true;
+            self.enter_frame(clip, 0, events);
+        }
+
+        self.elapsed += dt;
+        while !self.finished {
+            // Zero duration frames would loop forever.
+            let duration = clip.frames[self.frame].duration.max(1);
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+
+            let next = match clip.mode {
+                PlayMode::Loop => (self.frame + 1) % count,
+                PlayMode::Once => {
+                    if self.frame + 1 < count {
+                        self.frame + 1
+                    } else {
+                        self.finished = true;
+                        self.elapsed = 0;
+                        break;
+                    }
+                },
+                PlayMode::PingPong => {
+                    if count == 1 {
+                        0
+                    } else {
+                        if !self.backward && self.frame + 1 == count {
+                            self.backward = true;
+                        } else if self.backward && self.frame == 0 {
+                            self.backward = false;
+                        }
+                        if self.backward { self.frame - 1 } else { self.frame + 1 }
+                    }
+                },
+            };
+            self.enter_frame(clip, next, events);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Parameter {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+    // Like a bool that is reset once it has been used by a transition.
+    Trigger(bool),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Condition {
+    // Bool is true or trigger is set.
+    If(String),
+    IfNot(String),
+    Greater(String, f32),
+    Less(String, f32),
+    Equals(String, i32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transition {
+    // None means from any state.
+    pub from: Option<String>,
+    pub to: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    // Wait for the current clip to finish (clips played Once).
+    #[serde(default)]
+    pub on_finish: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimatorState {
+    pub name: String,
+    pub clip: AnimationClip,
+}
+
+// State machine selecting which clip plays. Transitions are checked in
+// order every update, the first one whose conditions hold is taken.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Animator {
+    pub states: Vec<AnimatorState>,
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    #[serde(default)]
+    pub parameters: HashMap<String, Parameter>,
+
+    // Index in states.
+    #[serde(default)]
+    current: usize,
+    #[serde(skip)]
+    player: ClipPlayer,
+}
+
+impl Animator {
+
+    pub fn new(states: Vec<AnimatorState>) -> Animator {
+        Animator {
+            states,
+            transitions: Vec::new(),
+            parameters: HashMap::new(),
+            current: 0,
+            player: ClipPlayer::new(),
+        }
+    }
+
+    pub fn add_state(&mut self, name: &str, clip: AnimationClip) {
+        self.states.push(AnimatorState { name: name.to_string(), clip });
+    }
+
+    pub fn add_transition(&mut self, from: Option<&str>, to: &str, conditions: Vec<Condition>) {
+        self.transitions.push(Transition {
+            from: from.map(|s| s.to_string()),
+            to: to.to_string(),
+            conditions,
+            on_finish: false,
+        });
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.parameters.insert(name.to_string(), Parameter::Bool(value));
+    }
+
+    pub fn set_int(&mut self, name: &str, value: i32) {
+        self.parameters.insert(name.to_string(), Parameter::Int(value));
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.parameters.insert(name.to_string(), Parameter::Float(value));
+    }
+
+    pub fn set_trigger(&mut self, name: &str) {
+        self.parameters.insert(name.to_string(), Parameter::Trigger(true));
+    }
+
+    pub fn current_state(&self) -> Option<&str> {
+        self.states.get(self.current).map(|s| s.name.as_str())
+    }
+
+    pub fn current_frame(&self) -> Option<&Frame> {
+        self.states.get(self.current)
+            .and_then(|s| s.clip.frames.get(self.player.frame()))
+    }
+
+    pub fn current_region(&self) -> Option<Region> {
+        self.current_frame().map(|f| f.region)
+    }
+
+    // Jumps to a state, restarting its clip.
+    pub fn play(&mut self, state: &str) {
+        if let Some(idx) = self.states.iter().position(|s| s.name == state) {
+            self.current = idx;
+            self.player = ClipPlayer::new();
+        }
+    }
+
+    fn condition_holds(&self, condition: &Condition) -> bool {
+        let param = |name: &String| self.parameters.get(name);
+        match condition {
+            Condition::If(name) => match param(name) {
+                Some(Parameter::Bool(b)) | Some(Parameter::Trigger(b)) => *b,
+                _ => false,
+            },
+            Condition::IfNot(name) => match param(name) {
+                Some(Parameter::Bool(b)) | Some(Parameter::Trigger(b)) => !*b,
+                _ => true,
+            },
+            Condition::Greater(name, value) => match param(name) {
+                Some(Parameter::Float(f)) => f > value,
+                Some(Parameter::Int(i)) => (*i as f32) > *value,
+                _ => false,
+            },
+            Condition::Less(name, value) => match param(name) {
+                Some(Parameter::Float(f)) => f < value,
+                Some(Parameter::Int(i)) => (*i as f32) < *value,
+                _ => false,
+            },
+            Condition::Equals(name, value) => match param(name) {
+                Some(Parameter::Int(i)) => i == value,
+                _ => false,
+            },
+        }
+    }
+
+    fn find_transition(&self) -> Option<usize> {
+        let current = self.current_state()?;
+        self.transitions.iter().position(|t| {
+            let from_ok = match &t.from {
+                Some(from) => from == current,
+                // Do not restart the state we are already in.
+                None => t.to != current,
+            };
+            from_ok
+                && (!t.on_finish || self.player.is_finished())
+                && t.conditions.iter().all(|c| self.condition_holds(c))
+        })
+    }
+
+    // dt in milliseconds. Returns the events fired during this update.
+    pub fn update(&mut self, dt: u32) -> Vec<String> {
+        let mut events = Vec::new();
+
+        if let Some(idx) = self.find_transition() {
+            // Consume the triggers used by the transition.
+            for c in self.transitions[idx].conditions.clone() {
+                if let Condition::If(name) = c {
+                    if let Some(Parameter::Trigger(t)) = self.parameters.get_mut(&name) {
+                        *t = false;
+                    }
+                }
+            }
+            let to = self.transitions[idx].to.clone();
+            self.play(&to);
+        }
+
+        if let Some(state) = self.states.get(self.current) {
+            self.player.update(&state.clip, dt, &mut events);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn clip(name: &str, count: usize, mode: PlayMode) -> AnimationClip {
+        let frames = (0..count)
+            .map(|i| Frame { region: Region::new(i as i32 * 16, 0, 16, 16), duration: 100, trim: None })
+            .collect();
+        AnimationClip::new(name, frames, mode)
+    }
+
+    fn frames_over_time(clip: &AnimationClip, steps: usize) -> Vec<usize> {
+        let mut player = ClipPlayer::new();
+        let mut events = Vec::new();
+        (0..steps).map(|_| {
+            let frame = player.frame();
+            player.update(clip, 100, &mut events);
+            frame
+        }).collect()
+    }
+
+    #[test]
+    fn play_modes() {
+        assert_eq!(frames_over_time(&clip("loop", 3, PlayMode::Loop), 7), vec![0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(frames_over_time(&clip("once", 3, PlayMode::Once), 6), vec![0, 1, 2, 2, 2, 2]);
+        assert_eq!(frames_over_time(&clip("pingpong", 3, PlayMode::PingPong), 8), vec![0, 1, 2, 1, 0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn large_dt_skips_frames() {
+        let clip = clip("loop", 4, PlayMode::Loop);
+        let mut player = ClipPlayer::new();
+        let mut events = Vec::new();
+        player.update(&clip, 250, &mut events);
+        assert_eq!(player.frame(), 2);
+        player.update(&clip, 60, &mut events);
+        assert_eq!(player.frame(), 3);
+    }
+
+    #[test]
+    fn frame_events() {
+        let clip = clip("attack", 3, PlayMode::Once)
+            .with_event(0, "start")
+            .with_event(2, "hit");
+        let mut player = ClipPlayer::new();
+        let mut events = Vec::new();
+        player.update(&clip, 10, &mut events);
+        assert_eq!(events, vec!["start".to_string()]);
+        player.update(&clip, 300, &mut events);
+        assert_eq!(events, vec!["start".to_string(), "hit".to_string()]);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn animator_transitions() {
+        let mut animator = Animator::new(Vec::new());
+        animator.add_state("idle", clip("idle", 2, PlayMode::Loop));
+        animator.add_state("run", clip("run", 4, PlayMode::Loop));
+        animator.add_state("jump", clip("jump", 2, PlayMode::Once));
+        animator.add_transition(Some("idle"), "run", vec![Condition::Greater("speed".to_string(), 0.1)]);
+        animator.add_transition(Some("run"), "idle", vec![Condition::Less("speed".to_string(), 0.1)]);
+        animator.add_transition(None, "jump", vec![Condition::If("jump".to_string())]);
+        animator.transitions.push(Transition {
+            from: Some("jump".to_string()),
+            to: "idle".to_string(),
+            conditions: Vec::new(),
+            on_finish: true,
+        });
+
+        animator.update(16);
+        assert_eq!(animator.current_state(), Some("idle"));
+
+        animator.set_float("speed", 2.0);
+        animator.update(16);
+        assert_eq!(animator.current_state(), Some("run"));
+        assert_eq!(animator.current_region(), Some(Region::new(0, 0, 16, 16)));
+
+        animator.set_trigger("jump");
+        animator.update(16);
+        assert_eq!(animator.current_state(), Some("jump"));
+        // The trigger was consumed.
+        assert_eq!(animator.parameters["jump"], Parameter::Trigger(false));
+
+        animator.set_float("speed", 0.0);
+        animator.update(150);
+        assert_eq!(animator.current_state(), Some("jump"));
+        animator.update(100);
+        animator.update(16);
+        assert_eq!(animator.current_state(), Some("idle"));
+    }
+}
@@ -0,0 +1,612 @@
+use sdl2::audio::AudioSpecWAV;
+use sdl2::image::LoadSurface;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::surface::{Surface, SurfaceRef};
+use sdl2::ttf::{Font, Sdl2TtfContext};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use super::core::Scene;
+use super::glyphs::{Glyph, GlyphAtlas};
+
+// Typed reference to an asset of the AssetManager. Cheap to copy. A handle
+// keeps its asset alive until it is released.
+pub struct Handle<A> {
+    id: usize,
+    // Of the slot when the handle was made, slots are reused once unloaded.
+    generation: u32,
+    marker: PhantomData<*const A>,
+}
+
+impl<A> Handle<A> {
+    fn new(id: usize, generation: u32) -> Handle<A> {
+        Handle { id, generation, marker: PhantomData }
+    }
+}
+
+// Implemented by hand, derive would require A to be Clone, PartialEq...
+impl<A> Clone for Handle<A> {
+    fn clone(&self) -> Handle<A> {
+        *self
+    }
+}
+
+impl<A> Copy for Handle<A> {}
+
+impl<A> PartialEq for Handle<A> {
+    fn eq(&self, other: &Handle<A>) -> bool {
+        self.id == other.id && self.generation == other.generation
+    }
+}
+
+impl<A> Eq for Handle<A> {}
+
+impl<A> Hash for Handle<A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<A> fmt::Debug for Handle<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({}:{})", self.id, self.generation)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssetError {
+    NotFound(PathBuf),
+    Load { path: PathBuf, reason: String },
+    // The handle was released and its asset unloaded.
+    InvalidHandle,
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::NotFound(path) => write!(f, "asset not found: {}", path.display()),
+            AssetError::Load { path, reason } => write!(f, "cannot load {}: {}", path.display(), reason),
+            AssetError::InvalidHandle => write!(f, "invalid asset handle"),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+struct Slot<A> {
+    key: String,
+    asset: Option<A>,
+    ref_count: usize,
+    error: Option<AssetError>,
+}
+
+struct Entry<A> {
+    generation: u32,
+    slot: Option<Slot<A>>,
+}
+
+// All the assets of one type, indexed by key (usually the path).
+pub struct Storage<A> {
+    entries: Vec<Entry<A>>,
+    // Entries of unloaded assets, reused by the next registrations.
+    free: Vec<usize>,
+    ids: HashMap<String, usize>,
+}
+
+impl<A> Storage<A> {
+
+    pub fn new() -> Storage<A> {
+        Storage { entries: Vec::new(), free: Vec::new(), ids: HashMap::new() }
+    }
+
+    // Registers the asset without loading it.
+    fn register(&mut self, key: &str) -> Handle<A> {
+        if let Some(id) = self.ids.get(key) {
+            return Handle::new(*id, self.entries[*id].generation);
+        }
+
+        let slot = Some(Slot { key: key.to_string(), asset: None, ref_count: 0, error: None });
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.entries[id].slot = slot;
+                id
+            },
+            None => {
+                self.entries.push(Entry { generation: 0, slot });
+                self.entries.len() - 1
+            },
+        };
+        self.ids.insert(key.to_string(), id);
+        Handle::new(id, self.entries[id].generation)
+    }
+
+    // None for the handles of unloaded assets, even if their entry was
+    // reused: the generations differ.
+    fn slot(&self, handle: Handle<A>) -> Option<&Slot<A>> {
+        self.entries.get(handle.id)
+            .filter(|entry| entry.generation == handle.generation)
+            .and_then(|entry| entry.slot.as_ref())
+    }
+
+    fn slot_mut(&mut self, handle: Handle<A>) -> Option<&mut Slot<A>> {
+        self.entries.get_mut(handle.id)
+            .filter(|entry| entry.generation == handle.generation)
+            .and_then(|entry| entry.slot.as_mut())
+    }
+
+    pub fn acquire(&mut self, key: &str) -> Handle<A> {
+        let handle = self.register(key);
+        if let Some(slot) = self.slot_mut(handle) {
+            slot.ref_count += 1;
+        }
+        handle
+    }
+
+    pub fn release(&mut self, handle: Handle<A>) {
+        if let Some(slot) = self.slot_mut(handle) {
+            slot.ref_count = slot.ref_count.saturating_sub(1);
+        }
+    }
+
+    pub fn ref_count(&self, handle: Handle<A>) -> usize {
+        self.slot(handle).map_or(0, |slot| slot.ref_count)
+    }
+
+    pub fn is_loaded(&self, handle: Handle<A>) -> bool {
+        self.slot(handle).and_then(|slot| slot.asset.as_ref()).is_some()
+    }
+
+    pub fn handle(&self, key: &str) -> Option<Handle<A>> {
+        self.ids.get(key).map(|id| Handle::new(*id, self.entries[*id].generation))
+    }
+
+    // Loads the asset the first time it is accessed. A failed load is not
+    // retried, the same error is returned every time.
+    pub fn get_or_load<F>(&mut self, handle: Handle<A>, load: F) -> Result<&mut A, AssetError>
+        where F: FnOnce(&str) -> Result<A, AssetError> {
+        let slot = match self.slot_mut(handle) {
+            Some(slot) => slot,
+            None => return Err(AssetError::InvalidHandle),
+        };
+
+        if let Some(error) = &slot.error {
+            return Err(error.clone());
+        }
+
+        if slot.asset.is_none() {
+            match load(&slot.key) {
+                Ok(asset) => slot.asset = Some(asset),
+                Err(e) => {
+                    slot.error = Some(e.clone());
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(slot.asset.as_mut().unwrap())
+    }
+
+    pub fn get(&self, handle: Handle<A>) -> Option<&A> {
+        self.slot(handle).and_then(|slot| slot.asset.as_ref())
+    }
+
+    // Drops every asset that has no handle left. Returns how many were unloaded.
+    pub fn unload_unused(&mut self) -> usize {
+        let mut unloaded = 0;
+        for (id, entry) in self.entries.iter_mut().enumerate() {
+            if matches!(&entry.slot, Some(slot) if slot.ref_count == 0) {
+                let slot = entry.slot.take().unwrap();
+                self.ids.remove(&slot.key);
+                // Invalidates the handles of the asset.
+                entry.generation = entry.generation.wrapping_add(1);
+                self.free.push(id);
+                unloaded += 1;
+            }
+        }
+        unloaded
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+}
+
+type TextureLoader<'a> = Box<dyn Fn(&SurfaceRef) -> Result<Texture<'a>, String> + 'a>;
+// Makes an RGB24 streaming texture of the given width and height.
+type StreamingLoader<'a> = Box<dyn Fn(u32, u32) -> Result<Texture<'a>, String> + 'a>;
+
+// Owns every texture, font, sound and scene loaded by the engine. Paths are
+// relative to the asset root. Assets are loaded lazily the first time they
+// are used, and unloaded when no handle refers to them anymore.
+pub struct AssetManager<'a> {
+    root: PathBuf,
+    create_texture: TextureLoader<'a>,
+    create_streaming: StreamingLoader<'a>,
+    ttf_context: &'a Sdl2TtfContext,
+
+    pub textures: Storage<Texture<'a>>,
+    pub fonts: Storage<Font<'a, 'static>>,
+    pub sounds: Storage<AudioSpecWAV>,
+    pub scenes: Storage<Scene>,
+
+    // Glyphs of the characters drawn by Text, from the first of the text
+    // fonts that has them.
+    glyphs: GlyphAtlas<'a>,
+    text_fonts: Vec<Handle<Font<'a, 'static>>>,
+    // 1x1 white texture, color modulated to draw rotated rectangles.
+    white: Option<Texture<'a>>,
+    // Updated in place by the lighting every frame, see light_map_texture.
+    light_map: Option<Texture<'a>>,
+
+    // Textures held for the current scene.
+    scene_textures: Vec<Handle<Texture<'a>>>,
+    // Errors not yet reported.
+    errors: Vec<AssetError>,
+}
+
+impl<'a> AssetManager<'a> {
+
+    pub fn new<P: AsRef<Path>, T>(root: P, texture_creator: &'a TextureCreator<T>, ttf_context: &'a Sdl2TtfContext) -> AssetManager<'a> {
+        let create_texture = Box::new(move |surface: &SurfaceRef| {
+            texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())
+        });
+        let create_streaming = Box::new(move |width, height| {
+            texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, width, height).map_err(|e| e.to_string())
+        });
+
+        AssetManager {
+            root: root.as_ref().to_path_buf(),
+            create_texture,
+            create_streaming,
+            ttf_context,
+            textures: Storage::new(),
+            fonts: Storage::new(),
+            sounds: Storage::new(),
+            scenes: Storage::new(),
+            glyphs: GlyphAtlas::new(),
+            text_fonts: Vec::new(),
+            white: None,
+            light_map: None,
+            scene_textures: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn resolve(root: &Path, key: &str) -> Result<PathBuf, AssetError> {
+        let path = root.join(key);
+        if path.exists() {
+            Ok(path)
+        } else {
+            Err(AssetError::NotFound(path))
+        }
+    }
+
+    fn report<T>(errors: &mut Vec<AssetError>, result: Result<T, AssetError>) -> Result<T, AssetError> {
+        if let Err(e) = &result {
+            errors.push(e.clone());
+        }
+        result
+    }
+
+    // Load errors that happened since the last call. Each failure is only
+    // reported once.
+    pub fn take_errors(&mut self) -> Vec<AssetError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub fn texture(&mut self, path: &str) -> Handle<Texture<'a>> {
+        self.textures.acquire(path)
+    }
+
+    pub fn get_texture(&mut self, handle: Handle<Texture<'a>>) -> Result<&mut Texture<'a>, AssetError> {
+        let root = &self.root;
+        let create_texture = &self.create_texture;
+        let mut first_load = false;
+        let result = self.textures.get_or_load(handle, |key| {
+            first_load = true;
+            let path = AssetManager::resolve(root, key)?;
+            let surface = load_image(&path)
+                .map_err(|reason| AssetError::Load { path: path.clone(), reason })?;
+            let mut texture = create_texture(&surface)
+                .map_err(|reason| AssetError::Load { path: path.clone(), reason })?;
+            texture.set_blend_mode(BlendMode::Blend);
+            Ok(texture)
+        });
+
+        if first_load {
+            AssetManager::report(&mut self.errors, result)
+        } else {
+            result
+        }
+    }
+
+    // Texture by path without taking a handle on it. It will be unloaded at
+    // the next scene change unless something holds a handle.
+    pub fn find_texture(&mut self, path: &str) -> Result<&mut Texture<'a>, AssetError> {
+        let handle = self.textures.register(path);
+        self.get_texture(handle)
+    }
+
+    // Created the first time it is needed.
+    pub fn white_texture(&mut self) -> Result<&mut Texture<'a>, AssetError> {
+        if self.white.is_none() {
+            let load_error = |reason| AssetError::Load { path: PathBuf::from("white texture"), reason };
+            let mut surface = Surface::new(1, 1, PixelFormatEnum::RGBA8888).map_err(load_error)?;
+            surface.fill_rect(None, Color::RGBA(255, 255, 255, 255)).map_err(load_error)?;
+            let mut texture = (self.create_texture)(&surface).map_err(load_error)?;
+            texture.set_blend_mode(BlendMode::Blend);
+            self.white = Some(texture);
+        }
+        Ok(self.white.as_mut().unwrap())
+    }
+
+    // RGB24 streaming texture of at least width x height, for the light map
+    // computed every frame: its top left part is updated and drawn. It is
+    // only made again when it has to grow.
+    pub fn light_map_texture(&mut self, width: u32, height: u32) -> Result<&mut Texture<'a>, AssetError> {
+        let size = self.light_map.as_ref().map_or((0, 0), |texture| {
+            let query = texture.query();
+            (query.width, query.height)
+        });
+        if self.light_map.is_none() || size.0 < width || size.1 < height {
+            let load_error = |reason| AssetError::Load { path: PathBuf::from("light map"), reason };
+            let texture = (self.create_streaming)(width.max(size.0), height.max(size.1)).map_err(load_error)?;
+            self.light_map = Some(texture);
+        }
+        Ok(self.light_map.as_mut().unwrap())
+    }
+
+    pub fn font(&mut self, path: &str, size: u16) -> Handle<Font<'a, 'static>> {
+        self.fonts.acquire(&format!("{}@{}", path, size))
+    }
+
+    pub fn get_font(&mut self, handle: Handle<Font<'a, 'static>>) -> Result<&mut Font<'a, 'static>, AssetError> {
+        let root = &self.root;
+        let ttf_context = self.ttf_context;
+        let mut first_load = false;
+        let result = self.fonts.get_or_load(handle, |key| {
+            first_load = true;
+            let (path, size) = parse_font_key(key)?;
+            let path = AssetManager::resolve(root, path)?;
+            ttf_context.load_font(&path, size)
+                .map_err(|reason| AssetError::Load { path: path.clone(), reason })
+        });
+
+        if first_load {
+            AssetManager::report(&mut self.errors, result)
+        } else {
+            result
+        }
+    }
+
+    pub fn sound(&mut self, path: &str) -> Handle<AudioSpecWAV> {
+        self.sounds.acquire(path)
+    }
+
+    pub fn get_sound(&mut self, handle: Handle<AudioSpecWAV>) -> Result<&mut AudioSpecWAV, AssetError> {
+        let root = &self.root;
+        let mut first_load = false;
+        let result = self.sounds.get_or_load(handle, |key| {
+            first_load = true;
+            let path = AssetManager::resolve(root, key)?;
+            AudioSpecWAV::load_wav(&path)
+                .map_err(|reason| AssetError::Load { path: path.clone(), reason })
+        });
+
+        if first_load {
+            AssetManager::report(&mut self.errors, result)
+        } else {
+            result
+        }
+    }
+
+    pub fn scene(&mut self, path: &str) -> Handle<Scene> {
+        self.scenes.acquire(path)
+    }
+
+    pub fn get_scene(&mut self, handle: Handle<Scene>) -> Result<&mut Scene, AssetError> {
+        let root = &self.root;
+        let mut first_load = false;
+        let result = self.scenes.get_or_load(handle, |key| {
+            first_load = true;
+            let path = AssetManager::resolve(root, key)?;
+            let data = fs::read_to_string(&path)
+                .map_err(|e| AssetError::Load { path: path.clone(), reason: e.to_string() })?;
+            serde_json::from_str(&data)
+                .map_err(|e| AssetError::Load { path: path.clone(), reason: e.to_string() })
+        });
+
+        if first_load {
+            AssetManager::report(&mut self.errors, result)
+        } else {
+            result
+        }
+    }
+
+    pub fn save_scene(&self, scene: &Scene, path: &str) -> Result<(), AssetError> {
+        let path = self.root.join(path);
+        let data = serde_json::to_string_pretty(scene)
+            .map_err(|e| AssetError::Load { path: path.clone(), reason: e.to_string() })?;
+        fs::write(&path, data).map_err(|e| AssetError::Load { path: path.clone(), reason: e.to_string() })
+    }
+
+    // Fonts of Text. Characters missing from font are looked for in the
+    // fallbacks, in order, and drawn as a box when none has them.
+    pub fn set_text_fonts(&mut self, font: Handle<Font<'a, 'static>>,
+                          fallbacks: Vec<Handle<Font<'a, 'static>>>) -> Result<(), AssetError> {
+        self.get_font(font)?;
+        self.text_fonts = std::iter::once(font).chain(fallbacks).collect();
+        self.glyphs.clear();
+        Ok(())
+    }
+
+    // Glyph of c in the atlas, rendered the first time. A glyph that fails
+    // to render is reported once and drawn as the missing glyph box.
+    pub fn glyph(&mut self, c: char) -> Result<Glyph, AssetError> {
+        if let Some(glyph) = self.glyphs.get(c) {
+            return Ok(glyph);
+        }
+
+        let load_error = |reason| AssetError::Load { path: PathBuf::from(format!("glyph '{}'", c)), reason };
+        let mut height = None;
+        let mut rendered = None;
+        for handle in self.text_fonts.clone() {
+            // Fonts failing to load are reported and skipped.
+            let font = match self.get_font(handle) {
+                Ok(font) => font,
+                Err(_) => continue,
+            };
+            height = height.or(Some(font.height().max(1) as u32));
+            // SDL_ttf only knows the characters of the basic multilingual
+            // plane.
+            if (c as u32) <= 0xFFFF && font.find_glyph(c).is_some() {
+                rendered = Some(font.render_char(c).blended(Color::RGBA(255, 255, 255, 255))
+                                .map_err(|e| load_error(e.to_string())));
+                break;
+            }
+        }
+
+        let glyph = match rendered {
+            Some(Ok(mut surface)) => self.glyphs.insert(c, &mut surface),
+            Some(Err(e)) => {
+                self.errors.push(e);
+                self.glyphs.insert_missing(c, height.unwrap_or(16))
+            },
+            None => self.glyphs.insert_missing(c, height.unwrap_or(16)),
+        };
+        glyph.map_err(load_error)
+    }
+
+    pub fn glyph_texture(&mut self, glyph: Glyph) -> Result<&mut Texture<'a>, AssetError> {
+        let create_texture = &self.create_texture;
+        self.glyphs.texture(glyph.page, |surface| create_texture(surface))
+            .map_err(|reason| AssetError::Load { path: PathBuf::from("glyph atlas"), reason })
+    }
+
+    // Holds the textures used by the new scene and unloads everything that
+    // was only used by the previous one.
+    pub fn change_scene(&mut self, scene: &Scene) {
+        let previous = std::mem::take(&mut self.scene_textures);
+        for texture in scene.textures() {
+            let handle = self.textures.acquire(texture);
+            self.scene_textures.push(handle);
+        }
+
+        for handle in previous {
+            self.textures.release(handle);
+        }
+        self.unload_unused();
+    }
+
+    pub fn unload_unused(&mut self) {
+        self.textures.unload_unused();
+        self.fonts.unload_unused();
+        self.sounds.unload_unused();
+        self.scenes.unload_unused();
+    }
+}
+
+// BMP is read by SDL itself, the other formats (PNG from the sprite sheet
+// and map editors, JPG...) by SDL_image.
+fn load_image(path: &Path) -> Result<Surface<'static>, String> {
+    let is_bmp = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("bmp"));
+    if is_bmp {
+        Surface::load_bmp(path)
+    } else {
+        Surface::from_file(path)
+    }
+}
+
+// Font keys are "<path>@<size>".
+fn parse_font_key(key: &str) -> Result<(&str, u16), AssetError> {
+    let invalid = || AssetError::Load { path: PathBuf::from(key), reason: "expected <path>@<size>".to_string() };
+    let at = key.rfind('@').ok_or_else(invalid)?;
+    let size = key[at + 1..].parse().map_err(|_| invalid())?;
+    Ok((&key[..at], size))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn storage_ref_counting() {
+        let mut storage: Storage<u32> = Storage::new();
+        let a = storage.acquire("a.bmp");
+        let a2 = storage.acquire("a.bmp");
+        let b = storage.acquire("b.bmp");
+        assert_eq!(a, a2);
+        assert_ne!(a, b);
+        assert_eq!(storage.ref_count(a), 2);
+
+        // Lazy: nothing is loaded before the first access.
+        assert!(!storage.is_loaded(a));
+        assert_eq!(*storage.get_or_load(a, |_| Ok(1)).unwrap(), 1);
+        assert_eq!(*storage.get_or_load(a, |_| panic!("loaded twice")).unwrap(), 1);
+
+        storage.release(a);
+        storage.release(b);
+        assert_eq!(storage.unload_unused(), 1);
+        assert_eq!(storage.get(a), Some(&1));
+        assert_eq!(storage.get_or_load(b, |_| Ok(2)), Err(AssetError::InvalidHandle));
+
+        storage.release(a2);
+        assert_eq!(storage.unload_unused(), 1);
+        assert_eq!(storage.len(), 0);
+
+        // Reloading gives a new handle, the old one stays invalid.
+        let a3 = storage.acquire("a.bmp");
+        assert_ne!(a, a3);
+        assert_eq!(storage.get(a), None);
+    }
+
+    #[test]
+    fn storage_reuses_unloaded_entries() {
+        let mut storage: Storage<u32> = Storage::new();
+        for i in 0..10 {
+            // Registered without a handle, like find_texture does.
+            let h = storage.register(&format!("{}.bmp", i));
+            storage.get_or_load(h, |_| Ok(i)).unwrap();
+            assert_eq!(storage.unload_unused(), 1);
+        }
+        assert_eq!(storage.entries.len(), 1);
+
+        let old = storage.acquire("old.bmp");
+        storage.release(old);
+        storage.unload_unused();
+        let new = storage.acquire("new.bmp");
+        assert_eq!(new.id, old.id);
+        assert_eq!(storage.ref_count(old), 0);
+        assert_eq!(storage.get_or_load(old, |_| Ok(1)), Err(AssetError::InvalidHandle));
+        assert_eq!(storage.ref_count(new), 1);
+    }
+
+    #[test]
+    fn storage_load_error_is_kept() {
+        let mut storage: Storage<u32> = Storage::new();
+        let h = storage.acquire("missing.bmp");
+        let error = AssetError::NotFound(PathBuf::from("missing.bmp"));
+        let e = error.clone();
+        assert_eq!(storage.get_or_load(h, move |_| Err(e)), Err(error.clone()));
+        assert_eq!(storage.get_or_load(h, |_| Ok(3)), Err(error));
+    }
+
+    #[test]
+    fn font_keys() {
+        assert_eq!(parse_font_key("fonts/a@b.ttf@24"), Ok(("fonts/a@b.ttf", 24)));
+        let invalid = AssetError::Load { path: PathBuf::from("a.ttf"), reason: "expected <path>@<size>".to_string() };
+        assert_eq!(parse_font_key("a.ttf"), Err(invalid));
+        assert!(parse_font_key("a.ttf@big").is_err());
+    }
+}
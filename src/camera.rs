@@ -0,0 +1,318 @@
+use serde_derive::{Serialize, Deserialize};
+
+use super::core::{Camera, GameObject};
+use super::math::Vector2d;
+use super::noise::Noise;
+
+fn default_smooth_time() -> f32 {
+    0.15
+}
+
+fn default_decay() -> f32 {
+    1.0
+}
+
+fn default_frequency() -> f32 {
+    15.0
+}
+
+// Rectangle in world coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Area {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Area {
+
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Area {
+        Area { x, y, w, h }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Follow {
+    // Name of the game object to follow.
+    pub target: String,
+    // Time in seconds to catch up most of the distance to the target. 0
+    // snaps to it.
+    #[serde(default = "default_smooth_time")]
+    pub smooth_time: f32,
+    // The camera aims where the target will be after this many seconds at
+    // its current velocity.
+    #[serde(default)]
+    pub look_ahead: f32,
+    // Added to the center of the target.
+    #[serde(default)]
+    pub offset_x: f32,
+    #[serde(default)]
+    pub offset_y: f32,
+}
+
+impl Follow {
+
+    pub fn new(target: &str) -> Follow {
+        Follow { target: target.to_string(), smooth_time: default_smooth_time(), look_ahead: 0.0, offset_x: 0.0, offset_y: 0.0 }
+    }
+}
+
+// Trauma based shake: gameplay adds trauma (0 to 1) which decays over
+// time, and the camera moves by noise scaled with trauma squared so that
+// small hits are subtle and big ones violent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Shake {
+    // In world units and degrees, at full trauma.
+    pub max_offset: f32,
+    pub max_angle: f32,
+    // Trauma lost per second.
+    #[serde(default = "default_decay")]
+    pub decay: f32,
+    // Speed of the noise.
+    #[serde(default = "default_frequency")]
+    pub frequency: f32,
+}
+
+impl Shake {
+
+    pub fn new(max_offset: f32, max_angle: f32) -> Shake {
+        Shake { max_offset, max_angle, decay: default_decay(), frequency: default_frequency() }
+    }
+}
+
+// Moves the camera of a scene while the game runs. Everything is optional,
+// the default controller leaves the camera alone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CameraController {
+    #[serde(default)]
+    pub follow: Option<Follow>,
+    // Relative to the center of the view, in world units. The camera does
+    // not move while the target is inside.
+    #[serde(default)]
+    pub dead_zone: Option<Area>,
+    // The camera never shows anything outside of it.
+    #[serde(default)]
+    pub bounds: Option<Area>,
+    #[serde(default)]
+    pub shake: Option<Shake>,
+
+    #[serde(skip)]
+    trauma: f32,
+    // Seconds since the start, drives the shake noise.
+    #[serde(skip)]
+    time: f32,
+}
+
+impl CameraController {
+
+    pub fn new() -> CameraController {
+        CameraController::default()
+    }
+
+    // Clamped to 1.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, gameobjects: &[GameObject], output_w: u32, output_h: u32,
+                  dt: u32, noise: &Noise) {
+        let dt = dt as f32 / 1000.0;
+        let view = camera.view(output_w, output_h);
+        let half = Vector2d::new(view.viewport.width() as f32 / 2.0, view.viewport.height() as f32 / 2.0);
+        let mut center = camera.position + half;
+
+        let target = self.follow.as_ref().and_then(|follow| {
+            gameobjects.iter().find(|go| go.name == follow.target).map(|go| (follow, go))
+        });
+        if let Some((follow, go)) = target {
+            let aim = Vector2d::new(go.x() + go.w() as f32 / 2.0 + follow.offset_x,
+                                    go.y() + go.h() as f32 / 2.0 + follow.offset_y)
+                + go.body.velocity * follow.look_ahead;
+            let desired = match self.dead_zone {
+                Some(zone) => center + Vector2d::new(
+                    out_of_zone(aim.x, center.x + zone.x, zone.w),
+                    out_of_zone(aim.y, center.y + zone.y, zone.h)),
+                None => aim,
+            };
+
+            let t = if follow.smooth_time <= 0.0 { 1.0 } else { 1.0 - (-dt / follow.smooth_time).exp() };
+            center = center + (desired - center) * t;
+        }
+
+        if let Some(bounds) = self.bounds {
+            // Half of the visible world, ignoring the rotation.
+            let (hw, hh) = (half.x / view.zoom, half.y / view.zoom);
+            center.x = clamp_to(center.x, bounds.x, bounds.w, hw);
+            center.y = clamp_to(center.y, bounds.y, bounds.h, hh);
+        }
+        camera.position = center - half;
+
+        self.time += dt;
+        camera.shake = match &self.shake {
+            Some(shake) if self.trauma > 0.0 => {
+                let amount = self.trauma * self.trauma;
+                let t = self.time * shake.frequency;
+                (shake.max_offset * amount * noise.perlin1d(t),
+                 shake.max_offset * amount * noise.perlin1d(t + 100.0),
+                 shake.max_angle * amount * noise.perlin1d(t + 200.0))
+            },
+            _ => (0.0, 0.0, 0.0),
+        };
+        let decay = self.shake.as_ref().map(|s| s.decay).unwrap_or(default_decay());
+        self.trauma = (self.trauma - decay * dt).max(0.0);
+    }
+}
+
+// How far pos is outside of [start, start + size] (negative before it), on
+// the dead zone axis.
+fn out_of_zone(pos: f32, start: f32, size: f32) -> f32 {
+    if pos < start {
+        pos - start
+    } else if pos > start + size {
+        pos - start - size
+    } else {
+        0.0
+    }
+}
+
+// Keeps a view of half size half_size inside [start, start + size], or
+// centers it when it is larger.
+fn clamp_to(center: f32, start: f32, size: f32, half_size: f32) -> f32 {
+    if size <= half_size * 2.0 {
+        start + size / 2.0
+    } else {
+        center.max(start + half_size).min(start + size - half_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::core::Scene;
+    use sdl2::pixels::Color;
+
+    // Scene with a 20x20 "player" at (x, y) and a 100x80 camera view.
+    fn scene_with_player(x: f32, y: f32) -> Scene {
+        let mut scene = Scene::new();
+        scene.add_rect(x, y, 20, 20, Color::RGB(255, 255, 255));
+        scene.gameobjects[0].name = "player".to_string();
+        scene
+    }
+
+    fn update(controller: &mut CameraController, scene: &mut Scene, dt: u32) {
+        controller.update(&mut scene.camera, &scene.gameobjects, 100, 80, dt, &Noise::new(0));
+    }
+
+    #[test]
+    fn follow_target() {
+        let mut scene = scene_with_player(200.0, 100.0);
+        let mut controller = CameraController::new();
+        let mut follow = Follow::new("player");
+        follow.smooth_time = 0.0;
+        controller.follow = Some(follow);
+
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position, Vector2d::new(160.0, 70.0));
+
+        // Smoothed: part of the way only, always closer.
+        scene.gameobjects[0].body.position.x = 300.0;
+        controller.follow.as_mut().unwrap().smooth_time = 0.2;
+        update(&mut controller, &mut scene, 100);
+        let x = scene.camera.position.x;
+        assert!(x > 160.0 && x < 260.0);
+        update(&mut controller, &mut scene, 100);
+        assert!(scene.camera.position.x > x);
+
+        // Unknown targets do not move the camera.
+        controller.follow = Some(Follow::new("nobody"));
+        let position = scene.camera.position;
+        update(&mut controller, &mut scene, 100);
+        assert_eq!(scene.camera.position, position);
+    }
+
+    #[test]
+    fn look_ahead_and_dead_zone() {
+        let mut scene = scene_with_player(40.0, 30.0);
+        let mut controller = CameraController::new();
+        let mut follow = Follow::new("player");
+        follow.smooth_time = 0.0;
+        follow.look_ahead = 0.5;
+        controller.follow = Some(follow);
+        scene.gameobjects[0].body.velocity = Vector2d::new(20.0, 0.0);
+
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position, Vector2d::new(10.0, 0.0));
+
+        // Inside the dead zone nothing moves, outside the camera moves just
+        // enough to keep the target on its edge.
+        scene.gameobjects[0].body.velocity = Vector2d::new(0.0, 0.0);
+        controller.dead_zone = Some(Area::new(-10.0, -10.0, 20.0, 20.0));
+        scene.camera.position = Vector2d::new(0.0, 0.0);
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position, Vector2d::new(0.0, 0.0));
+
+        scene.gameobjects[0].body.position.x = 70.0;
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position, Vector2d::new(20.0, 0.0));
+    }
+
+    #[test]
+    fn clamp_to_bounds() {
+        let mut scene = scene_with_player(-50.0, 500.0);
+        let mut controller = CameraController::new();
+        let mut follow = Follow::new("player");
+        follow.smooth_time = 0.0;
+        controller.follow = Some(follow);
+        controller.bounds = Some(Area::new(0.0, 0.0, 400.0, 300.0));
+
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position, Vector2d::new(0.0, 220.0));
+
+        // Zoomed in, the camera can go closer to the edge.
+        scene.camera.zoom = 2.0;
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position, Vector2d::new(-25.0, 240.0));
+
+        // Bounds smaller than the view are centered.
+        scene.camera.zoom = 1.0;
+        controller.bounds = Some(Area::new(0.0, 0.0, 50.0, 300.0));
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.position.x, -25.0);
+    }
+
+    #[test]
+    fn shake_decays() {
+        let mut scene = scene_with_player(0.0, 0.0);
+        let mut controller = CameraController::new();
+        controller.shake = Some(Shake::new(10.0, 5.0));
+
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(scene.camera.shake, (0.0, 0.0, 0.0));
+
+        controller.add_trauma(0.7);
+        controller.add_trauma(0.7);
+        assert_eq!(controller.trauma(), 1.0);
+
+        let mut moved = false;
+        for _ in 0..10 {
+            update(&mut controller, &mut scene, 16);
+            let (x, y, angle) = scene.camera.shake;
+            assert!(x.abs() <= 10.0 && y.abs() <= 10.0 && angle.abs() <= 5.0);
+            moved |= x != 0.0 || y != 0.0;
+        }
+        assert!(moved);
+        // The shake does not move the camera itself.
+        assert_eq!(scene.camera.position, Vector2d::new(0.0, 0.0));
+
+        update(&mut controller, &mut scene, 1000);
+        update(&mut controller, &mut scene, 16);
+        assert_eq!(controller.trauma(), 0.0);
+        assert_eq!(scene.camera.shake, (0.0, 0.0, 0.0));
+    }
+}
@@ -1,30 +1,146 @@
 use sdl2::{Sdl, VideoSubsystem};
-use sdl2::render::WindowCanvas;
+use sdl2::render::{Canvas, RenderTarget};
 use sdl2::ttf::Sdl2TtfContext;
+use sdl2::video::Window;
+#[cfg(test)]
+use sdl2::pixels::{Color, PixelFormatEnum};
+#[cfg(test)]
+use sdl2::surface::Surface;
 
-pub struct Context {
+// Everything needed to draw a frame. The canvas is either a window (the
+// normal case) or a surface in memory for the headless context.
+pub struct Context<T: RenderTarget = Window> {
     pub sdl_context: Sdl,
-    video_subsystem: VideoSubsystem,
-    pub canvas: WindowCanvas,
+    video_subsystem: Option<VideoSubsystem>,
+    pub canvas: Canvas<T>,
     pub font_context: Sdl2TtfContext,
 }
 
-impl Context {
+// Renders with the software renderer into a surface. No window and no GPU
+// are needed so it works on a CI box without a display. Only the tests use it.
+#[cfg(test)]
+pub type HeadlessContext = Context<Surface<'static>>;
 
-    pub fn new() -> Result<Context, Box<std::error::Error>> {
-        let sdl_context = sdl2::init()?; 
+impl Context<Window> {
+
+    pub fn new(width: u32, height: u32) -> Result<Context, Box<dyn std::error::Error>> {
+        let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
         let font_context = sdl2::ttf::init()?;
 
 
-        let window = video_subsystem.window("Mouse", 800, 600)
+        let window = video_subsystem.window("Mouse", width, height)
             .position_centered()
+            .resizable()
             .build()?;
 
         let canvas = window.into_canvas().build()?;
-       
-        Ok(Context { sdl_context, video_subsystem, canvas, font_context,})
+
+        Ok(Context { sdl_context, video_subsystem: Some(video_subsystem), canvas, font_context,})
     }
 }
 
+#[cfg(test)]
+impl Context<Surface<'static>> {
+
+    pub fn headless(width: u32, height: u32) -> Result<HeadlessContext, Box<dyn std::error::Error>> {
+        // The video subsystem is not initialized, the surface does not need it.
+        let sdl_context = sdl2::init()?;
+        let font_context = sdl2::ttf::init()?;
+
+        let surface = Surface::new(width, height, PixelFormatEnum::RGBA8888)?;
+        let canvas = Canvas::from_surface(surface)?;
+
+        Ok(Context { sdl_context, video_subsystem: None, canvas, font_context })
+    }
+
+    // Color of a pixel of what has been rendered so far.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let surface = self.canvas.surface();
+        assert!(x < surface.width() && y < surface.height(),
+                "pixel ({}, {}) outside of {}x{} surface", x, y, surface.width(), surface.height());
+
+        let format = surface.pixel_format();
+        let offset = (y * surface.pitch() + x * 4) as usize;
+        let raw = surface.with_lock(|pixels| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&pixels[offset..offset + 4]);
+            u32::from_ne_bytes(bytes)
+        });
+        Color::from_u32(&format, raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::assets::AssetManager;
+    use crate::core::{FrameStats, Scene, Text};
+    use crate::editor::Editor;
+    use std::path::PathBuf;
+
+    fn asset_root() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    }
 
+    fn headless(width: u32, height: u32) -> HeadlessContext {
+        Context::headless(width, height).expect("Could not create headless context")
+    }
+
+    #[test]
+    fn render_scene_headless() {
+        let mut context = headless(100, 100);
+        let texture_creator = context.canvas.texture_creator();
+        let mut assets = AssetManager::new(asset_root(), &texture_creator, &context.font_context);
+        let mut scene = Scene::new();
+        scene.add_static(10.0, 20.0, 30, 10, Color::RGB(0, 255, 0));
+        scene.add_static(1000.0, 1000.0, 30, 10, Color::RGB(0, 255, 0));
+        scene.camera.position.x = 5.0;
+
+        context.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        context.canvas.clear();
+        let stats = scene.render(&mut context.canvas, &mut assets);
+        context.canvas.present();
+
+        assert_eq!(stats, FrameStats { considered: 2, drawn: 1, culled: 1, draw_calls: 1 });
+
+        assert_eq!(context.pixel(5, 20), Color::RGB(0, 255, 0));
+        assert_eq!(context.pixel(34, 29), Color::RGB(0, 255, 0));
+        assert_eq!(context.pixel(35, 20), Color::RGB(0, 0, 0));
+        assert_eq!(context.pixel(4, 20), Color::RGB(0, 0, 0));
+    }
+
+    #[test]
+    fn render_editor_headless() {
+        let mut context = headless(200, 100);
+        let texture_creator = context.canvas.texture_creator();
+        let mut assets = AssetManager::new(asset_root(), &texture_creator, &context.font_context);
+        let font = assets.font("EBGaramond08-Regular.ttf", 24);
+        assets.set_text_fonts(font, Vec::new()).unwrap();
+
+        let editor = Editor::new();
+        editor.render(&mut context.canvas, &mut assets);
+
+        // Buttons are white squares on the top left.
+        assert_eq!(context.pixel(10, 19), Color::RGB(255, 255, 255));
+        assert_eq!(context.pixel(35, 10), Color::RGB(0, 0, 0));
+    }
+
+    #[test]
+    fn render_any_character() {
+        let mut context = headless(300, 40);
+        let texture_creator = context.canvas.texture_creator();
+        let mut assets = AssetManager::new(asset_root(), &texture_creator, &context.font_context);
+        let font = assets.font("EBGaramond08-Regular.ttf", 24);
+        assets.set_text_fonts(font, Vec::new()).unwrap();
+
+        // Punctuation, accents, and characters no font has.
+        let text = Text { content: "Hello, world! é \u{E000}\u{1F600}".to_string(), color: Color::RGB(255, 255, 255) };
+        text.render(&mut context.canvas, &mut assets, 0, 0);
+        let missing = assets.glyph('\u{E000}').unwrap();
+        assert_eq!(assets.glyph('\u{1F600}').unwrap(), missing);
+        assert_ne!(assets.glyph('!').unwrap(), missing);
+        assert!(assets.take_errors().is_empty());
+    }
+}
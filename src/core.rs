@@ -1,12 +1,26 @@
-use sdl2::render::{Canvas, Texture, RenderTarget, TextureQuery, TextureCreator};
+use sdl2::render::{Canvas, RenderTarget};
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
 use std::collections::HashMap;
+use std::fmt;
 use super::math::Vector2d;
 
 use serde_derive::{Serialize, Deserialize};
 
+use super::animation::Animator;
+use super::camera::CameraController;
+use super::assets::AssetManager;
 use super::physics::{BodyType, RigidBody};
+use super::shapes::VectorShape;
+use super::random::Rng;
+use super::noise::Noise;
+use super::lighting::Lighting;
+use super::parallax::ParallaxLayer;
+use super::particles::ParticleEmitter;
+use super::pipeline::PipelineConfig;
+use super::spatial::SpatialGrid;
+use super::sprite::Sprite;
+use super::tilemap::Tilemap;
 
 #[macro_export]
 macro_rules! rect(
@@ -21,23 +35,123 @@ pub struct Text {
 }
 
 impl Text {
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, fonts: &mut HashMap<char, Texture>, x: i32, y: i32) {
+    // Characters no font has are drawn as a box.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, assets: &mut AssetManager, x: i32, y: i32) {
 
         let mut tx = x;
-        for (_, c) in self.content.char_indices() {
-            let texture: &mut Texture = fonts.get_mut(&c).expect(&format!("no '{}' in map", c));
+        for c in self.content.chars() {
+            // The asset manager reports the error.
+            let glyph = match assets.glyph(c) {
+                Ok(glyph) => glyph,
+                Err(_) => continue,
+            };
+            let texture = match assets.glyph_texture(glyph) {
+                Ok(texture) => texture,
+                Err(_) => continue,
+            };
             texture.set_color_mod(self.color.r,
                                   self.color.g,
                                   self.color.b);
-            let TextureQuery { width, height, .. } = texture.query();
-            let target = rect!(tx, y, width, height);
+            let target = rect!(tx, y, glyph.region.width(), glyph.region.height());
 
-            tx += 2+(width as i32);
-            canvas.copy(&texture, None, Some(target)).unwrap();
+            tx += 2+(glyph.region.width() as i32);
+            canvas.copy(texture, Some(glyph.region), Some(target)).expect("Text render failed");
         }
     }
 }
 
+// Custom value attached to a scene or a game object, usually set in the level
+// editor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+// Objects without a layer are drawn on this one.
+pub const DEFAULT_LAYER: &str = "default";
+
+pub fn default_layer() -> String {
+    DEFAULT_LAYER.to_string()
+}
+
+fn visible() -> bool {
+    true
+}
+
+// Group of things drawn together. Layers are drawn in the order of the scene,
+// inside a layer the smallest z-index is drawn first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderLayer {
+    pub name: String,
+    #[serde(default = "visible")]
+    pub visible: bool,
+    // Objects of a locked layer cannot be selected in the editor.
+    #[serde(default)]
+    pub locked: bool,
+    // Objects with the same z-index may be drawn in any order, they are
+    // grouped by texture (or color) to draw them in fewer batches.
+    #[serde(default)]
+    pub sort_by_texture: bool,
+}
+
+impl RenderLayer {
+
+    pub fn new(name: &str) -> RenderLayer {
+        RenderLayer { name: name.to_string(), visible: true, locked: false, sort_by_texture: false }
+    }
+}
+
+// Counters of the last rendered frame, summed over the cameras.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FrameStats {
+    // Game objects of the scene.
+    pub considered: u32,
+    pub drawn: u32,
+    // Not drawn: outside of the view of the camera or on a layer it does
+    // not show.
+    pub culled: u32,
+    // Calls to the renderer: copies and fills.
+    pub draw_calls: u32,
+}
+
+impl FrameStats {
+
+    pub fn new() -> FrameStats {
+        FrameStats::default()
+    }
+
+    pub fn add(&mut self, other: FrameStats) {
+        self.considered += other.considered;
+        self.drawn += other.drawn;
+        self.culled += other.culled;
+        self.draw_calls += other.draw_calls;
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "drawn:{} culled:{} calls:{}", self.drawn, self.culled, self.draw_calls)
+    }
+}
+
+// Size of the cells of the spatial grid used for culling, in world units.
+const CULLING_CELL_SIZE: f32 = 256.0;
+
+fn default_layers() -> Vec<RenderLayer> {
+    vec![RenderLayer::new("background"), RenderLayer::new(DEFAULT_LAYER), RenderLayer::new("foreground")]
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DrawItem {
+    Parallax(usize),
+    Tilemap(usize),
+    GameObject(usize),
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GameObject {
     pub body: RigidBody,
@@ -45,6 +159,31 @@ pub struct GameObject {
     r: u8,
     g: u8,
     b: u8,
+
+    // Drawn instead of the colored rectangle when set.
+    #[serde(default)]
+    pub sprite: Option<Sprite>,
+
+    // Changes the region of the sprite over time.
+    #[serde(default)]
+    pub animator: Option<Animator>,
+
+    // Drawn instead of the rectangle when there is no sprite.
+    #[serde(default)]
+    pub shape: Option<VectorShape>,
+
+    #[serde(default)]
+    pub emitter: Option<ParticleEmitter>,
+
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub properties: HashMap<String, Property>,
+
+    #[serde(default = "default_layer")]
+    pub layer: String,
+    #[serde(default)]
+    pub z_index: i32,
 }
 
 impl GameObject {
@@ -56,16 +195,77 @@ impl GameObject {
             r,
             g,
             b,
+            sprite: None,
+            animator: None,
+            shape: None,
+            emitter: None,
+            name: String::new(),
+            properties: HashMap::new(),
+            layer: default_layer(),
+            z_index: 0,
+        }
+    }
+
+    // Screen rectangle of the object, see View::world_rect.
+    pub fn screen_rect(&self, view: &View) -> Rect {
+        view.world_rect(self.body.position.x, self.body.position.y,
+                        self.body.shape.w as f32, self.body.shape.h as f32)
+    }
+
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &View, assets: &mut AssetManager) {
+        let dst = self.screen_rect(view);
+
+        // Fall back to the rectangle if the texture could not be loaded. The
+        // asset manager reports the error.
+        if let Some(sprite) = &self.sprite {
+            if let Ok(texture) = assets.find_texture(&sprite.texture) {
+                sprite.render_rotated(canvas, texture, dst, view.angle());
+                return;
+            }
+        }
+
+        let color = self.color();
+        if let Some(shape) = &self.shape {
+            shape.render(canvas, view, (self.x(), self.y(), self.w() as f32, self.h() as f32), color);
+        } else if !view.is_rotated() {
+            canvas.set_draw_color(color);
+            canvas.fill_rect(dst).expect("GameObject render failed");
+        } else if let Ok(white) = assets.white_texture() {
+            white.set_color_mod(color.r, color.g, color.b);
+            canvas.copy_ex(white, None, Some(dst), view.angle(), None, false, false).expect("GameObject render failed");
         }
     }
 
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera) {
-        canvas.set_draw_color(Color::RGB(self.r, self.g, self.b));
+    // Of the rectangle drawn without a sprite.
+    pub fn color(&self) -> Color {
+        Color::RGB(self.r, self.g, self.b)
+    }
+
+    // Solid rectangles can be drawn together with a single fill.
+    fn is_plain_rect(&self) -> bool {
+        self.sprite.is_none() && self.shape.is_none() && self.emitter.is_none()
+    }
+
+    // World rectangle (x, y, w, h) of everything the object draws,
+    // particles included.
+    pub fn bounds(&self) -> (f32, f32, f32, f32) {
+        let (x, y, w, h) = (self.x(), self.y(), self.w() as f32, self.h() as f32);
+        let (x, y, w, h) = match &self.shape {
+            Some(shape) if self.sprite.is_none() => shape.bounds(x, y, w, h),
+            _ => (x, y, w, h),
+        };
+        match self.emitter.as_ref().and_then(|e| e.bounds()) {
+            Some((px, py, pw, ph)) => {
+                let (x0, y0) = (x.min(px), y.min(py));
+                (x0, y0, (x + w).max(px + pw) - x0, (y + h).max(py + ph) - y0)
+            },
+            None => (x, y, w, h),
+        }
+    }
 
-        let global_pos = Vector2d::new(self.body.position.x as i32, self.body.position.y as i32);
-        let local_pos = camera.toLocal(global_pos);
-        canvas.fill_rect(rect!(local_pos.x, local_pos.y,
-                               self.body.shape.w, self.body.shape.h)).expect("GameObject render failed");
+    // Center of the object, where its particles are emitted.
+    pub fn center(&self) -> Vector2d<f32> {
+        Vector2d::new(self.x() + self.w() as f32 / 2.0, self.y() + self.h() as f32 / 2.0)
     }
 
     pub fn contains_point(&self, x: i32, y: i32) -> bool {
@@ -94,11 +294,66 @@ impl GameObject {
 pub struct Scene {
     pub gameobjects: Vec<GameObject>,
     pub camera: Camera,
+
+    // Seed for everything random in the scene so that generated content is
+    // the same every time the scene is loaded.
+    #[serde(default)]
+    pub seed: u64,
+
+    // Drawn below the game objects.
+    #[serde(default)]
+    pub tilemaps: Vec<Tilemap>,
+
+    #[serde(default)]
+    pub properties: HashMap<String, Property>,
+
+    #[serde(default = "default_layers")]
+    pub layers: Vec<RenderLayer>,
+
+    #[serde(default)]
+    pub parallax: Vec<ParallaxLayer>,
+
+    // Moves the camera in game mode.
+    #[serde(default)]
+    pub camera_controller: CameraController,
+
+    // Drawn after the main camera, for split screen and user interfaces.
+    #[serde(default)]
+    pub cameras: Vec<Camera>,
+
+    // Off-screen resolution and post effects in game mode. Drawn directly on
+    // the window when None.
+    #[serde(default)]
+    pub pipeline: Option<PipelineConfig>,
+
+    // The scene is drawn fully lit when None.
+    #[serde(default)]
+    pub lighting: Option<Lighting>,
 }
 
 impl Scene {
     pub fn new() -> Scene {
-        Scene { gameobjects: Vec::new(), camera: Camera::new() }
+        Scene {
+            gameobjects: Vec::new(),
+            camera: Camera::new(),
+            seed: 0,
+            tilemaps: Vec::new(),
+            properties: HashMap::new(),
+            layers: default_layers(),
+            parallax: Vec::new(),
+            camera_controller: CameraController::new(),
+            cameras: Vec::new(),
+            pipeline: None,
+            lighting: None,
+        }
+    }
+
+    pub fn rng(&self) -> Rng {
+        Rng::new(self.seed)
+    }
+
+    pub fn noise(&self) -> Noise {
+        Noise::new(self.seed)
     }
 
     pub fn add_rect(&mut self, x: f32, y: f32, w: u32, h: u32, color: Color) {
@@ -110,54 +365,674 @@ impl Scene {
     }
 
 
-    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
-        for go in self.gameobjects.iter() {
-            go.render(canvas, &self.camera);
+    pub fn layer(&self, name: &str) -> Option<&RenderLayer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut RenderLayer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    // Added on top of the others. Returns the existing layer if there is one
+    // with this name.
+    pub fn add_layer(&mut self, name: &str) -> &mut RenderLayer {
+        let idx = match self.layers.iter().position(|l| l.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.layers.push(RenderLayer::new(name));
+                self.layers.len() - 1
+            },
+        };
+        &mut self.layers[idx]
+    }
+
+    // Unknown layers are visible, unlocked and drawn above all the others.
+    fn layer_order(&self, name: &str) -> (usize, bool) {
+        match self.layers.iter().position(|l| l.name == name) {
+            Some(idx) => (idx, self.layers[idx].visible),
+            None => (self.layers.len(), true),
+        }
+    }
+
+    pub fn is_locked(&self, layer: &str) -> bool {
+        self.layer(layer).map(|l| l.locked).unwrap_or(false)
+    }
+
+    // What is drawn, from the bottom to the top. The sort is stable so with
+    // the same layer and z-index parallax layers are below tilemaps, tilemaps
+    // are below game objects and game objects are in insertion order.
+    pub fn draw_order(&self) -> Vec<DrawItem> {
+        self.filtered_draw_order(|_| true, &self.all_gameobjects())
+    }
+
+    // Same as draw_order, restricted to the layers seen by the camera.
+    pub fn camera_draw_order(&self, camera: &Camera) -> Vec<DrawItem> {
+        self.filtered_draw_order(|layer| camera.sees(layer), &self.all_gameobjects())
+    }
+
+    fn all_gameobjects(&self) -> Vec<usize> {
+        (0..self.gameobjects.len()).collect()
+    }
+
+    fn sorts_by_texture(&self, layer: &str) -> bool {
+        self.layer(layer).map(|l| l.sort_by_texture).unwrap_or(false)
+    }
+
+    // Only the given game objects are drawn.
+    fn filtered_draw_order<F>(&self, shown_layer: F, gameobjects: &[usize]) -> Vec<DrawItem>
+        where F: Fn(&str) -> bool {
+        // The last two keys group the objects by texture and color on the
+        // layers sorted by texture, they are empty everywhere else.
+        let mut items: Vec<(usize, i32, &str, u32, DrawItem)> = Vec::new();
+        for (idx, parallax) in self.parallax.iter().enumerate() {
+            let (order, visible) = self.layer_order(&parallax.layer);
+            if visible && shown_layer(&parallax.layer) {
+                items.push((order, parallax.z_index, "", 0, DrawItem::Parallax(idx)));
+            }
+        }
+        for (idx, tilemap) in self.tilemaps.iter().enumerate() {
+            let (order, visible) = self.layer_order(&tilemap.layer);
+            if visible && shown_layer(&tilemap.layer) {
+                items.push((order, tilemap.z_index, "", 0, DrawItem::Tilemap(idx)));
+            }
+        }
+        for &idx in gameobjects {
+            let go = &self.gameobjects[idx];
+            let (order, visible) = self.layer_order(&go.layer);
+            if visible && shown_layer(&go.layer) {
+                let (texture, color) = match (&go.sprite, self.sorts_by_texture(&go.layer)) {
+                    (_, false) => ("", 0),
+                    (Some(sprite), true) => (sprite.texture.as_str(), 0),
+                    (None, true) => ("", (go.r as u32) << 16 | (go.g as u32) << 8 | go.b as u32),
+                };
+                items.push((order, go.z_index, texture, color, DrawItem::GameObject(idx)));
+            }
+        }
+
+        items.sort_by(|a, b| (a.0, a.1, a.2, a.3).cmp(&(b.0, b.1, b.2, b.3)));
+        items.into_iter().map(|(_, _, _, _, item)| item).collect()
+    }
+
+    // The main camera first, then the others in order.
+    pub fn all_cameras(&self) -> impl Iterator<Item = &Camera> {
+        std::iter::once(&self.camera).chain(self.cameras.iter())
+    }
+
+    // Every camera draws the scene in its viewport, later cameras on top. The
+    // game objects are culled one by one and the occluders of the lighting
+    // found again, see render_with_grid for large scenes.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, assets: &mut AssetManager) -> FrameStats {
+        let occluders = if self.lighting.is_some() { self.occluders() } else { Vec::new() };
+        let mut stats = FrameStats::new();
+        for camera in self.all_cameras() {
+            stats.add(self.render_camera(canvas, camera, None, &occluders, assets));
+        }
+        stats
+    }
+
+    // Same as render, only looking at the game objects in the cells of the
+    // grid seen by the cameras. The grid must be up to date, see
+    // update_grid, and occluders computed once the scene is loaded.
+    pub fn render_with_grid<T: RenderTarget>(&self, canvas: &mut Canvas<T>, assets: &mut AssetManager,
+                                             grid: &SpatialGrid, occluders: &[Rect]) -> FrameStats {
+        let mut stats = FrameStats::new();
+        for camera in self.all_cameras() {
+            stats.add(self.render_camera(canvas, camera, Some(grid), occluders, assets));
+        }
+        stats
+    }
+
+    // World rectangles of the static bodies, which cast shadows.
+    pub fn occluders(&self) -> Vec<Rect> {
+        self.gameobjects.iter()
+            .filter(|go| go.body.body_type == BodyType::Static)
+            .map(|go| rect!(go.x(), go.y(), go.w(), go.h()))
+            .chain(self.tilemaps.iter().flat_map(|t| t.collision_rects()))
+            .collect()
+    }
+
+    // Paths of the images drawn by the scene, without duplicates.
+    pub fn textures(&self) -> Vec<&str> {
+        let mut textures: Vec<&str> = self.gameobjects.iter()
+            .flat_map(|go| go.sprite.iter().map(|s| s.texture.as_str())
+                      .chain(go.emitter.iter().filter_map(|e| e.texture.as_deref())))
+            .chain(self.tilemaps.iter().map(|t| t.tileset.texture.as_str()))
+            .chain(self.parallax.iter().map(|p| p.texture.as_str()))
+            .filter(|t| !t.is_empty())
+            .collect();
+        textures.sort_unstable();
+        textures.dedup();
+        textures
+    }
+
+    // Index of the game objects for culling.
+    pub fn spatial_grid(&self) -> SpatialGrid {
+        let mut grid = SpatialGrid::new(CULLING_CELL_SIZE);
+        self.update_grid(&mut grid);
+        grid
+    }
+
+    // Moves the game objects in the grid after they moved. Objects staying in
+    // the same cells are left alone.
+    pub fn update_grid(&self, grid: &mut SpatialGrid) {
+        for (idx, go) in self.gameobjects.iter().enumerate() {
+            let (x, y, w, h) = go.bounds();
+            grid.insert(idx, x, y, w, h);
         }
     }
 
+    // Game objects overlapping what the view shows, in order. Without a grid
+    // all of them are checked.
+    pub fn visible_gameobjects(&self, view: &View, grid: Option<&SpatialGrid>) -> Vec<usize> {
+        let (x, y, w, h) = view.visible_bounds();
+        let candidates = match grid {
+            Some(grid) => grid.query(x, y, w, h),
+            None => self.all_gameobjects(),
+        };
+        candidates.into_iter()
+            .filter(|&idx| {
+                let (gx, gy, gw, gh) = self.gameobjects[idx].bounds();
+                gx < x + w && gx + gw > x && gy < y + h && gy + gh > y
+            })
+            .collect()
+    }
+
+    // occluders shadow the lights, see occluders.
+    pub fn render_camera<T: RenderTarget>(&self, canvas: &mut Canvas<T>, camera: &Camera, grid: Option<&SpatialGrid>,
+                                          occluders: &[Rect], assets: &mut AssetManager) -> FrameStats {
+        // Logical size, see resolution::Resolution.
+        let (w, h) = canvas.viewport().size();
+        let view = camera.view(w, h);
+        if camera.viewport.is_some() {
+            canvas.set_clip_rect(view.viewport);
+        }
+
+        let visible = self.visible_gameobjects(&view, grid);
+        let mut stats = FrameStats::new();
+        stats.considered = self.gameobjects.len() as u32;
+
+        let items = self.filtered_draw_order(|layer| camera.sees(layer), &visible);
+        let mut i = 0;
+        while i < items.len() {
+            match items[i] {
+                DrawItem::Parallax(idx) => stats.draw_calls += self.parallax[idx].render(canvas, &view, assets),
+                DrawItem::Tilemap(idx) => stats.draw_calls += self.tilemaps[idx].render(canvas, &view, assets),
+                DrawItem::GameObject(idx) => {
+                    let go = &self.gameobjects[idx];
+                    stats.drawn += 1;
+                    stats.draw_calls += 1;
+                    if view.is_rotated() || !go.is_plain_rect() {
+                        go.render(canvas, &view, assets);
+                        // Particles are drawn above their object.
+                        if let Some(emitter) = &go.emitter {
+                            stats.draw_calls += emitter.render(canvas, &view, assets);
+                        }
+                        i += 1;
+                        continue;
+                    }
+
+                    // Following rectangles of the same color are filled
+                    // with the same call.
+                    let mut rects = vec![go.screen_rect(&view)];
+                    while let Some(DrawItem::GameObject(next)) = items.get(i + 1) {
+                        let next = &self.gameobjects[*next];
+                        if !next.is_plain_rect() || next.color() != go.color() {
+                            break;
+                        }
+                        rects.push(next.screen_rect(&view));
+                        stats.drawn += 1;
+                        i += 1;
+                    }
+                    canvas.set_draw_color(go.color());
+                    canvas.fill_rects(&rects).expect("GameObject render failed");
+                },
+            }
+            i += 1;
+        }
+        stats.culled = stats.considered - stats.drawn;
+
+        // Screen space cameras draw the interface, which is not lit.
+        if let (Some(lighting), false) = (&self.lighting, camera.screen_space) {
+            lighting.render(canvas, &view, occluders, assets);
+            stats.draw_calls += 1;
+        }
+
+        canvas.set_clip_rect(None);
+        stats
+    }
+
+}
+
+fn one() -> f32 {
+    1.0
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Part of the window, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Viewport {
+
+    pub fn new(x: i32, y: i32, w: u32, h: u32) -> Viewport {
+        Viewport { x, y, w, h }
+    }
+
+    // Splits a window for local multiplayer: side by side for two players,
+    // a 2x2 grid for three or four.
+    pub fn split(count: u32, window_w: u32, window_h: u32) -> Vec<Viewport> {
+        let (cols, rows) = match count {
+            0 => return Vec::new(),
+            1 => (1, 1),
+            2 => (2, 1),
+            _ => (2, 2),
+        };
+        let (w, h) = (window_w / cols, window_h / rows);
+        (0..count.min(4))
+            .map(|i| Viewport::new(((i % cols) * w) as i32, ((i / cols) * h) as i32, w, h))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Camera {
-    pub position: Vector2d<i32>,
+    // World position shown at the top left of the viewport, when the zoom
+    // is 1 and there is no rotation. Zoom and rotation are around the center
+    // of the viewport.
+    pub position: Vector2d<f32>,
+    #[serde(default = "one")]
+    pub zoom: f32,
+    // Rounds the zoom to an integer and snaps the position to the pixels of
+    // the screen so that pixel art stays crisp.
+    #[serde(default)]
+    pub pixel_perfect: bool,
+    // In degrees, clockwise. The world is drawn rotated the other way.
+    #[serde(default)]
+    pub rotation: f32,
+    // Whole window when None.
+    #[serde(default)]
+    pub viewport: Option<Viewport>,
+
+    // Screen space camera for user interfaces: objects are drawn at their
+    // position in the viewport, without zoom, rotation nor shake.
+    #[serde(default)]
+    pub screen_space: bool,
+    // Names of the layers drawn by this camera, all of them when None.
+    #[serde(default)]
+    pub layer_mask: Option<Vec<String>>,
+
+    // Offset (x, y, degrees) of the screen shake, added by the camera
+    // controller on top of the position and rotation.
+    #[serde(skip)]
+    pub shake: (f32, f32, f32),
 }
 
 impl Camera {
 
     pub fn new() -> Camera {
-        Camera { position: Vector2d::new(0, 0)}
+        Camera { position: Vector2d::new(0.0, 0.0), zoom: 1.0, pixel_perfect: false, rotation: 0.0, viewport: None,
+                 screen_space: false, layer_mask: None, shake: (0.0, 0.0, 0.0) }
+    }
+
+    // Camera for the user interface, drawing only the given layer.
+    pub fn new_ui(layer: &str) -> Camera {
+        let mut camera = Camera::new();
+        camera.screen_space = true;
+        camera.layer_mask = Some(vec![layer.to_string()]);
+        camera
     }
 
-    pub fn toLocal(&self, global_pos: Vector2d<i32>) -> Vector2d<i32> {
-        global_pos - self.position
+    pub fn sees(&self, layer: &str) -> bool {
+        match &self.layer_mask {
+            Some(mask) => mask.iter().any(|l| l == layer),
+            None => true,
+        }
+    }
+
+    // The transform to draw a frame on an output of the given size.
+    pub fn view(&self, output_w: u32, output_h: u32) -> View {
+        let viewport = match self.viewport {
+            Some(v) => Rect::new(v.x, v.y, v.w, v.h),
+            None => Rect::new(0, 0, output_w, output_h),
+        };
+
+        if self.screen_space {
+            return View::new(viewport, Vector2d::new(0.0, 0.0), 1.0, 0.0);
+        }
+
+        let mut zoom = self.zoom.max(0.01);
+        let mut position = self.position + Vector2d::new(self.shake.0, self.shake.1);
+        if self.pixel_perfect {
+            zoom = zoom.round().max(1.0);
+            position = Vector2d::new((position.x * zoom).round() / zoom, (position.y * zoom).round() / zoom);
+        }
+
+        View::new(viewport, position, zoom, self.rotation + self.shake.2)
     }
 }
 
-pub struct TextureCache<'a> {
-    pub char_textures: HashMap<char, Texture<'a>>,
+// A camera resolved for a render target. Everything in the world is drawn
+// and picked through it.
+#[derive(Debug, Copy, Clone)]
+pub struct View {
+    pub viewport: Rect,
+    pub position: Vector2d<f32>,
+    pub zoom: f32,
+    pub rotation: f32,
+
+    // World position at the center of the viewport and its position on the
+    // screen.
+    center: Vector2d<f32>,
+    screen_center: Vector2d<f32>,
+    // Of the rotation applied to the world.
+    cos: f32,
+    sin: f32,
 }
 
-impl<'a> TextureCache<'a> {
+impl View {
 
-    pub fn new<T>(texture_creator: &'a TextureCreator<T>, font: sdl2::ttf::Font) -> TextureCache<'a> {
-        let mut char_textures = HashMap::new();
-        // instead of creating textures every frame for rendering text, I am
-        // going to store the characters texture in a big map and render the
-        // text from these textures.
-        let chars = ['0','1', '2', '3', '4', '5', '6', '7', '8', '9', 'x', 'y', ':', ' ', '_',
-        'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q',
-        'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
-        'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n',
-        'o', 'p', 'q', 'r', 's', 't','u', 'v', 'w', 'x', 'y', 'z'];
-        for c in chars.iter() {
-            let surface = font.render(&c.to_string()).blended(Color::RGBA(255, 0, 0, 255)).unwrap();
-            let texture = texture_creator.create_texture_from_surface(&surface).unwrap();
-            char_textures.insert(*c, texture);
+    pub fn new(viewport: Rect, position: Vector2d<f32>, zoom: f32, rotation: f32) -> View {
+        let half = Vector2d::new(viewport.width() as f32 / 2.0, viewport.height() as f32 / 2.0);
+        let angle = (-rotation).to_radians();
+        View {
+            viewport,
+            position,
+            zoom,
+            rotation,
+            center: position + half,
+            screen_center: Vector2d::new(viewport.x() as f32, viewport.y() as f32) + half,
+            cos: angle.cos(),
+            sin: angle.sin(),
         }
+    }
+
+    pub fn world_to_screen(&self, p: Vector2d<f32>) -> Vector2d<f32> {
+        let d = (p - self.center) * self.zoom;
+        Vector2d::new(d.x * self.cos - d.y * self.sin, d.x * self.sin + d.y * self.cos) + self.screen_center
+    }
 
-        TextureCache { char_textures }
+    pub fn screen_to_world(&self, p: Vector2d<f32>) -> Vector2d<f32> {
+        let d = p - self.screen_center;
+        let d = Vector2d::new(d.x * self.cos + d.y * self.sin, -d.x * self.sin + d.y * self.cos);
+        d * (1.0 / self.zoom) + self.center
+    }
+
+    pub fn is_rotated(&self) -> bool {
+        self.rotation != 0.0
+    }
+
+    // Angle to draw things with, in degrees clockwise as SDL wants it.
+    pub fn angle(&self) -> f64 {
+        -self.rotation as f64
+    }
+
+    // Where a world rectangle is drawn. Drawn rotated by angle() around its
+    // center when the view is rotated. Without rotation the corners are
+    // rounded, not the size, so that neighbour rectangles do not have gaps.
+    pub fn world_rect(&self, x: f32, y: f32, w: f32, h: f32) -> Rect {
+        if !self.is_rotated() {
+            let p0 = self.world_to_screen(Vector2d::new(x, y));
+            let p1 = self.world_to_screen(Vector2d::new(x + w, y + h));
+            let (x0, y0) = (p0.x.round() as i32, p0.y.round() as i32);
+            let (x1, y1) = (p1.x.round() as i32, p1.y.round() as i32);
+            return Rect::new(x0, y0, (x1 - x0).max(1) as u32, (y1 - y0).max(1) as u32);
+        }
+
+        let center = self.world_to_screen(Vector2d::new(x + w / 2.0, y + h / 2.0));
+        let (sw, sh) = ((w * self.zoom).round().max(1.0), (h * self.zoom).round().max(1.0));
+        Rect::new((center.x - sw / 2.0).round() as i32, (center.y - sh / 2.0).round() as i32, sw as u32, sh as u32)
+    }
+
+    // Bounding box of what is visible, in world coordinates: (x, y, w, h).
+    pub fn visible_bounds(&self) -> (f32, f32, f32, f32) {
+        let v = self.viewport;
+        let corners = [
+            self.screen_to_world(Vector2d::new(v.left() as f32, v.top() as f32)),
+            self.screen_to_world(Vector2d::new(v.right() as f32, v.top() as f32)),
+            self.screen_to_world(Vector2d::new(v.left() as f32, v.bottom() as f32)),
+            self.screen_to_world(Vector2d::new(v.right() as f32, v.bottom() as f32)),
+        ];
+        let min_x = corners.iter().map(|c| c.x).fold(f32::MAX, f32::min);
+        let min_y = corners.iter().map(|c| c.y).fold(f32::MAX, f32::min);
+        let max_x = corners.iter().map(|c| c.x).fold(f32::MIN, f32::max);
+        let max_y = corners.iter().map(|c| c.y).fold(f32::MIN, f32::max);
+        (min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    // For things that move slower (factor < 1) or faster than the world when
+    // the camera moves. They are not zoomed nor rotated.
+    pub fn parallax_to_screen(&self, global_pos: Vector2d<f32>, factor_x: f32, factor_y: f32) -> Vector2d<i32> {
+        Vector2d::new((global_pos.x - self.position.x * factor_x).round() as i32 + self.viewport.x(),
+                      (global_pos.y - self.position.y * factor_y).round() as i32 + self.viewport.y())
     }
 }
 
+// Moves a part of a rectangle drawn rotated around the center of the whole
+// rectangle: the part is drawn centered on the returned point with the same
+// angle, around its own center.
+pub fn rotate_around(part: Rect, whole: Rect, angle: f64) -> Rect {
+    if angle == 0.0 {
+        return part;
+    }
+    let (sin, cos) = (angle.to_radians().sin(), angle.to_radians().cos());
+    let (cx, cy) = (whole.center().x() as f64, whole.center().y() as f64);
+    let (dx, dy) = (part.center().x() as f64 - cx, part.center().y() as f64 - cy);
+    let center = Point::new((cx + dx * cos - dy * sin).round() as i32, (cy + dx * sin + dy * cos).round() as i32);
+    Rect::from_center(center, part.width(), part.height())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tilemap::Tileset;
+
+    #[test]
+    fn draw_order_by_layer_and_z_index() {
+        let mut scene = Scene::new();
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(255, 0, 0));
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(0, 255, 0));
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(0, 0, 255));
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(0, 0, 255));
+        scene.gameobjects[0].layer = "foreground".to_string();
+        scene.gameobjects[1].z_index = 2;
+        scene.gameobjects[3].layer = "background".to_string();
+
+        assert_eq!(scene.draw_order(), vec![
+            DrawItem::GameObject(3),
+            DrawItem::GameObject(2),
+            DrawItem::GameObject(1),
+            DrawItem::GameObject(0),
+        ]);
+
+        // Hidden layers are not drawn, unknown layers are on top.
+        scene.layer_mut("background").unwrap().visible = false;
+        scene.gameobjects[2].layer = "ui".to_string();
+        assert_eq!(scene.draw_order(), vec![
+            DrawItem::GameObject(1),
+            DrawItem::GameObject(0),
+            DrawItem::GameObject(2),
+        ]);
+
+        scene.parallax.push(ParallaxLayer::new("sky.bmp", 0.0, 0.0));
+        scene.layer_mut("background").unwrap().visible = true;
+        assert_eq!(scene.draw_order()[0], DrawItem::Parallax(0));
+        assert_eq!(scene.draw_order()[1], DrawItem::GameObject(3));
+
+        scene.add_layer("ui").locked = true;
+        assert!(scene.is_locked("ui"));
+        assert_eq!(scene.layers.len(), 4);
+    }
+
+    #[test]
+    fn camera_conversions() {
+        let mut camera = Camera::new();
+        camera.position = Vector2d::new(4.0, 2.0);
+
+        // Same as the old integer camera.
+        let view = camera.view(64, 48);
+        assert_eq!(view.world_rect(10.0, 8.0, 20.0, 12.0), Rect::new(6, 6, 20, 12));
+        assert_eq!(view.world_to_screen(Vector2d::new(10.0, 8.0)), Vector2d::new(6.0, 6.0));
+
+        // Zoom around the center of the viewport.
+        camera.zoom = 2.0;
+        camera.viewport = Some(Viewport::new(100, 0, 64, 48));
+        let view = camera.view(800, 600);
+        assert_eq!(view.world_to_screen(Vector2d::new(36.0, 26.0)), Vector2d::new(132.0, 24.0));
+        assert_eq!(view.world_rect(36.0, 26.0, 2.0, 1.0), Rect::new(132, 24, 4, 2));
+        let (x, y, w, h) = view.visible_bounds();
+        assert_eq!((x, y, w, h), (20.0, 14.0, 32.0, 24.0));
+
+        camera.rotation = 30.0;
+        let view = camera.view(800, 600);
+        let p = Vector2d::new(13.0, -7.5);
+        let back = view.screen_to_world(view.world_to_screen(p));
+        assert_eq_delta!(back.x, p.x, 0.001);
+        assert_eq_delta!(back.y, p.y, 0.001);
+        // Picking at the center of the viewport gives the center of the view.
+        let center = view.screen_to_world(Vector2d::new(132.0, 24.0));
+        assert_eq_delta!(center.x, 36.0, 0.001);
+        assert_eq_delta!(center.y, 26.0, 0.001);
+    }
+
+    #[test]
+    fn pixel_perfect_camera() {
+        let mut camera = Camera::new();
+        camera.zoom = 2.6;
+        camera.position = Vector2d::new(1.3, 0.0);
+        camera.pixel_perfect = true;
+        let view = camera.view(100, 100);
+        assert_eq!(view.zoom, 3.0);
+        assert_eq_delta!(view.position.x, 4.0 / 3.0, 0.0001);
+
+        camera.zoom = 0.4;
+        assert_eq!(camera.view(100, 100).zoom, 1.0);
+    }
+
+    #[test]
+    fn part_rotated_around_whole() {
+        let whole = Rect::new(0, 0, 20, 20);
+        let part = Rect::new(10, 0, 10, 10);
+        assert_eq!(rotate_around(part, whole, 0.0), part);
+        // A quarter turn clockwise moves the top right corner to the bottom right.
+        assert_eq!(rotate_around(part, whole, 90.0), Rect::new(10, 10, 10, 10));
+    }
+
+    #[test]
+    fn cameras_with_layer_masks() {
+        let mut scene = Scene::new();
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(255, 0, 0));
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(0, 255, 0));
+        scene.gameobjects[1].layer = "ui".to_string();
+        scene.camera.layer_mask = Some(vec![DEFAULT_LAYER.to_string()]);
+        scene.cameras.push(Camera::new_ui("ui"));
+
+        let cameras: Vec<&Camera> = scene.all_cameras().collect();
+        assert_eq!(cameras.len(), 2);
+        assert_eq!(scene.camera_draw_order(cameras[0]), vec![DrawItem::GameObject(0)]);
+        assert_eq!(scene.camera_draw_order(cameras[1]), vec![DrawItem::GameObject(1)]);
+        assert_eq!(scene.draw_order().len(), 2);
+    }
+
+    #[test]
+    fn ui_camera_ignores_world_transform() {
+        let mut camera = Camera::new_ui("ui");
+        camera.position = Vector2d::new(100.0, 50.0);
+        camera.zoom = 3.0;
+        camera.rotation = 45.0;
+        camera.shake = (4.0, 4.0, 10.0);
+        camera.viewport = Some(Viewport::new(400, 0, 400, 300));
+
+        let view = camera.view(800, 600);
+        assert!(!view.is_rotated());
+        assert_eq!(view.world_rect(10.0, 20.0, 30.0, 40.0), Rect::new(410, 20, 30, 40));
+        assert_eq!(view.screen_to_world(Vector2d::new(410.0, 20.0)), Vector2d::new(10.0, 20.0));
+    }
+
+    #[test]
+    fn split_screen_viewports() {
+        assert_eq!(Viewport::split(1, 800, 600), vec![Viewport::new(0, 0, 800, 600)]);
+        assert_eq!(Viewport::split(2, 800, 600), vec![Viewport::new(0, 0, 400, 600), Viewport::new(400, 0, 400, 600)]);
+        let four = Viewport::split(4, 800, 600);
+        assert_eq!(four.len(), 4);
+        assert_eq!(four[3], Viewport::new(400, 300, 400, 300));
+        assert_eq!(Viewport::split(3, 800, 600).len(), 3);
+    }
+
+    #[test]
+    fn culling_against_the_view() {
+        let mut scene = Scene::new();
+        let color = Color::RGB(255, 0, 0);
+        scene.add_rect(10.0, 10.0, 10, 10, color);
+        scene.add_rect(95.0, 70.0, 10, 10, color);
+        scene.add_rect(500.0, 10.0, 10, 10, color);
+        scene.add_rect(-30.0, 10.0, 10, 10, color);
+
+        let mut grid = scene.spatial_grid();
+        let view = scene.camera.view(100, 80);
+        assert_eq!(scene.visible_gameobjects(&view, Some(&grid)), vec![0, 1]);
+        assert_eq!(scene.visible_gameobjects(&view, None), vec![0, 1]);
 
+        scene.camera.position = Vector2d::new(450.0, 0.0);
+        let view = scene.camera.view(100, 80);
+        assert_eq!(scene.visible_gameobjects(&view, Some(&grid)), vec![2]);
+
+        // Moved next to the third one.
+        scene.gameobjects[0].body.position = Vector2d::new(480.0, 30.0);
+        scene.update_grid(&mut grid);
+        assert_eq!(scene.visible_gameobjects(&view, Some(&grid)), vec![0, 2]);
+        assert_eq!(scene.visible_gameobjects(&view, None), vec![0, 2]);
+    }
+
+    #[test]
+    fn layer_sorted_by_texture() {
+        let mut scene = Scene::new();
+        for texture in &["a.bmp", "b.bmp", "a.bmp", "b.bmp"] {
+            scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(0, 0, 0));
+            scene.gameobjects.last_mut().unwrap().sprite = Some(Sprite::new(texture));
+        }
+        scene.gameobjects[3].z_index = -1;
+        let order = |scene: &Scene| -> Vec<usize> {
+            scene.draw_order().into_iter()
+                .filter_map(|item| match item { DrawItem::GameObject(idx) => Some(idx), _ => None })
+                .collect()
+        };
+        assert_eq!(order(&scene), vec![3, 0, 1, 2]);
+
+        // The z-index still comes first.
+        scene.layer_mut(DEFAULT_LAYER).unwrap().sort_by_texture = true;
+        assert_eq!(order(&scene), vec![3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn textures_of_the_scene() {
+        let mut scene = Scene::new();
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(0, 0, 0));
+        scene.gameobjects[0].sprite = Some(Sprite::new("hero.bmp"));
+        let mut emitter = ParticleEmitter::new();
+        emitter.texture = Some("spark.bmp".to_string());
+        scene.gameobjects[0].emitter = Some(emitter);
+        scene.tilemaps.push(Tilemap::new(Tileset::new("tiles.bmp", 16, 16, 4), 2, 2));
+        scene.parallax.push(ParallaxLayer::new("sky.bmp", 0.5, 0.5));
+        scene.parallax.push(ParallaxLayer::new("hero.bmp", 0.5, 0.5));
+        assert_eq!(scene.textures(), vec!["hero.bmp", "sky.bmp", "spark.bmp", "tiles.bmp"]);
+    }
+
+    #[test]
+    fn layers_default_when_deserializing() {
+        let mut scene = Scene::new();
+        scene.add_rect(0.0, 0.0, 10, 10, Color::RGB(255, 0, 0));
+        let mut data: serde_json::Value = serde_json::to_value(&scene).unwrap();
+        data.as_object_mut().unwrap().remove("layers");
+        data["gameobjects"][0].as_object_mut().unwrap().remove("layer");
+
+        let scene: Scene = serde_json::from_value(data).unwrap();
+        assert_eq!(scene.layers.len(), 3);
+        assert_eq!(scene.gameobjects[0].layer, DEFAULT_LAYER);
+        assert_eq!(scene.gameobjects[0].z_index, 0);
+    }
+}
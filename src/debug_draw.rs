@@ -0,0 +1,275 @@
+// Immediate mode debug drawing. Shapes can be queued from anywhere during an
+// update and are drawn above everything by the next render, then forgotten
+// unless they were given a longer lifetime:
+//
+//     DebugDraw::new(Color::RGB(255, 0, 0)).seconds(2.0).arrow(from, to);
+//
+// The queue is per thread. Without the debug-draw feature nothing is queued
+// and the calls compile to nothing.
+#![cfg_attr(not(feature = "debug-draw"), allow(dead_code, unused_imports))]
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::render::{Canvas, RenderTarget};
+
+use super::assets::AssetManager;
+use super::core::{Text, View};
+use super::math::Vector2d;
+
+type FloatVector = Vector2d<f32>;
+
+// Segments of the circles.
+const CIRCLE_SEGMENTS: usize = 24;
+// Length of the arrow heads, in screen pixels.
+const ARROW_HEAD: f32 = 8.0;
+// Expiry of the shapes drawn for longer than a duration can hold.
+const FAR_EXPIRY: Duration = Duration::from_secs(100 * 365 * 24 * 3600);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Space {
+    // Moves with the camera of the scene.
+    World,
+    // In logical pixels, fixed on the screen.
+    Screen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Line(FloatVector, FloatVector),
+    Arrow(FloatVector, FloatVector),
+    Rect(f32, f32, f32, f32),
+    Circle(FloatVector, f32),
+    Text(FloatVector, String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Expiry {
+    // Number of renders left.
+    Frames(u32),
+    At(Instant),
+}
+
+#[derive(Debug, Clone)]
+struct Command {
+    shape: Shape,
+    color: Color,
+    space: Space,
+    expiry: Expiry,
+}
+
+#[cfg(feature = "debug-draw")]
+thread_local! {
+    static QUEUE: std::cell::RefCell<Vec<Command>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+// Style of the shapes to queue. By default they are in world space and
+// drawn by the next render only.
+#[derive(Debug, Copy, Clone)]
+pub struct DebugDraw {
+    color: Color,
+    space: Space,
+    expiry: Expiry,
+}
+
+impl DebugDraw {
+
+    pub fn new(color: Color) -> DebugDraw {
+        DebugDraw { color, space: Space::World, expiry: Expiry::Frames(1) }
+    }
+
+    pub fn screen(mut self) -> DebugDraw {
+        self.space = Space::Screen;
+        self
+    }
+
+    // Drawn by the next count renders.
+    pub fn frames(mut self, count: u32) -> DebugDraw {
+        self.expiry = Expiry::Frames(count.max(1));
+        self
+    }
+
+    // Drawn until this many seconds from now, at least once.
+    pub fn seconds(mut self, seconds: f32) -> DebugDraw {
+        let now = Instant::now();
+        let expiry = Duration::try_from_secs_f32(seconds.max(0.0)).ok()
+            .and_then(|duration| now.checked_add(duration))
+            .unwrap_or(now + FAR_EXPIRY);
+        self.expiry = Expiry::At(expiry);
+        self
+    }
+
+    pub fn line(&self, from: FloatVector, to: FloatVector) {
+        self.push(Shape::Line(from, to));
+    }
+
+    // Line with a head at to.
+    pub fn arrow(&self, from: FloatVector, to: FloatVector) {
+        self.push(Shape::Arrow(from, to));
+    }
+
+    // Outline only.
+    pub fn rect(&self, x: f32, y: f32, w: f32, h: f32) {
+        self.push(Shape::Rect(x, y, w, h));
+    }
+
+    pub fn circle(&self, center: FloatVector, radius: f32) {
+        self.push(Shape::Circle(center, radius));
+    }
+
+    // With the top left of the text at position. The text is not zoomed.
+    pub fn text(&self, position: FloatVector, content: &str) {
+        self.push(Shape::Text(position, content.to_string()));
+    }
+
+    #[cfg(feature = "debug-draw")]
+    fn push(&self, shape: Shape) {
+        let command = Command { shape, color: self.color, space: self.space, expiry: self.expiry };
+        QUEUE.with(|queue| queue.borrow_mut().push(command));
+    }
+
+    #[cfg(not(feature = "debug-draw"))]
+    fn push(&self, _shape: Shape) {}
+}
+
+// Forgets all the queued shapes, when changing scenes for example.
+pub fn clear() {
+    #[cfg(feature = "debug-draw")]
+    QUEUE.with(|queue| queue.borrow_mut().clear());
+}
+
+// The shapes to draw this frame. Keeps the ones living longer in the queue.
+#[cfg(feature = "debug-draw")]
+fn take_frame(now: Instant) -> Vec<Command> {
+    QUEUE.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let frame = queue.clone();
+        queue.retain_mut(|command| match &mut command.expiry {
+            Expiry::Frames(count) => {
+                *count -= 1;
+                *count > 0
+            },
+            Expiry::At(at) => now < *at,
+        });
+        frame
+    })
+}
+
+// Draws the queued shapes, the world ones through view. Call once per frame
+// after the scene.
+#[cfg(feature = "debug-draw")]
+pub fn flush<T: RenderTarget>(canvas: &mut Canvas<T>, view: &View, assets: &mut AssetManager) {
+    for command in take_frame(Instant::now()) {
+        let to_screen = |p: FloatVector| match command.space {
+            Space::World => view.world_to_screen(p),
+            Space::Screen => p,
+        };
+        canvas.set_draw_color(command.color);
+        let lines: Vec<FloatVector> = match &command.shape {
+            Shape::Text(position, content) => {
+                let p = to_screen(*position);
+                let text = Text { content: content.clone(), color: command.color };
+                text.render(canvas, assets, p.x.round() as i32, p.y.round() as i32);
+                continue;
+            },
+            shape => outline(shape).into_iter().map(to_screen).collect(),
+        };
+        let mut polyline = points(&lines);
+        if let Shape::Arrow(..) = command.shape {
+            polyline.extend(points(&arrow_head(lines[0], lines[1])));
+        }
+        canvas.draw_lines(polyline.as_slice()).expect("Debug draw failed");
+    }
+}
+
+#[cfg(not(feature = "debug-draw"))]
+pub fn flush<T: RenderTarget>(_canvas: &mut Canvas<T>, _view: &View, _assets: &mut AssetManager) {}
+
+// Connected points of the shape, before the transform to the screen so that
+// rotated views rotate them.
+fn outline(shape: &Shape) -> Vec<FloatVector> {
+    match *shape {
+        Shape::Line(from, to) | Shape::Arrow(from, to) => vec![from, to],
+        Shape::Rect(x, y, w, h) => vec![
+            FloatVector::new(x, y), FloatVector::new(x + w, y), FloatVector::new(x + w, y + h),
+            FloatVector::new(x, y + h), FloatVector::new(x, y)],
+        Shape::Circle(center, radius) => (0..=CIRCLE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 * 2.0 * PI / CIRCLE_SEGMENTS as f32;
+                center + FloatVector::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect(),
+        Shape::Text(position, _) => vec![position],
+    }
+}
+
+// Back from the tip to one side, to the tip and to the other side, in
+// screen space so that heads keep their size when zoomed.
+fn arrow_head(from: FloatVector, to: FloatVector) -> [FloatVector; 3] {
+    let dir = (to - from).normalized();
+    let back = dir * -ARROW_HEAD;
+    let side = FloatVector::new(-dir.y, dir.x) * (ARROW_HEAD / 2.0);
+    [to + back + side, to, to + back - side]
+}
+
+fn point(p: FloatVector) -> Point {
+    Point::new(p.x.round() as i32, p.y.round() as i32)
+}
+
+fn points(lines: &[FloatVector]) -> Vec<Point> {
+    lines.iter().map(|p| point(*p)).collect()
+}
+
+#[cfg(all(test, feature = "debug-draw"))]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn lifetimes() {
+        clear();
+        let red = DebugDraw::new(Color::RGB(255, 0, 0));
+        red.line(FloatVector::new(0.0, 0.0), FloatVector::new(10.0, 0.0));
+        red.frames(2).circle(FloatVector::new(0.0, 0.0), 5.0);
+        red.seconds(0.0).rect(0.0, 0.0, 1.0, 1.0);
+        red.seconds(60.0).screen().text(FloatVector::new(4.0, 4.0), "fps");
+
+        let now = Instant::now();
+        assert_eq!(take_frame(now).len(), 4);
+        let frame = take_frame(now);
+        assert_eq!(frame.len(), 2);
+        assert_eq!(frame[0].shape, Shape::Circle(FloatVector::new(0.0, 0.0), 5.0));
+        assert_eq!(frame[1].space, Space::Screen);
+
+        assert_eq!(take_frame(now).len(), 1);
+        assert_eq!(take_frame(now + Duration::from_secs(61)).len(), 1);
+        assert!(take_frame(now).is_empty());
+    }
+
+    #[test]
+    fn endless_seconds() {
+        clear();
+        let red = DebugDraw::new(Color::RGB(255, 0, 0));
+        red.seconds(f32::INFINITY).rect(0.0, 0.0, 1.0, 1.0);
+        red.seconds(f32::MAX).rect(0.0, 0.0, 1.0, 1.0);
+
+        let later = Instant::now() + Duration::from_secs(365 * 24 * 3600);
+        assert_eq!(take_frame(later).len(), 2);
+        assert_eq!(take_frame(later).len(), 2);
+        clear();
+    }
+
+    #[test]
+    fn shape_outlines() {
+        assert_eq!(outline(&Shape::Rect(1.0, 2.0, 3.0, 4.0)).len(), 5);
+        let circle = outline(&Shape::Circle(FloatVector::new(10.0, 10.0), 2.0));
+        assert_eq!(circle.len(), CIRCLE_SEGMENTS + 1);
+        assert!(circle.iter().all(|p| (p.distance(FloatVector::new(10.0, 10.0)) - 2.0).abs() < 0.001));
+
+        let head = arrow_head(FloatVector::new(0.0, 0.0), FloatVector::new(20.0, 0.0));
+        assert_eq!(head[0], FloatVector::new(12.0, 4.0));
+        assert_eq!(head[1], FloatVector::new(20.0, 0.0));
+        assert_eq!(head[2], FloatVector::new(12.0, -4.0));
+    }
+}
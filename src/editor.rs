@@ -1,20 +1,41 @@
 
-use super::core::{Text, Scene, TextureCache};
-use super::context::Context;
+use super::animation::{Animator, AnimatorState};
+use super::assets::AssetManager;
+use super::camera::Follow;
+use super::core::{DrawItem, Text, Scene, View};
+use super::debug_draw;
+use super::import::{self, Import, ImportError, SpriteSheet};
+use super::lighting::{Light, Lighting};
+use super::math::Vector2d;
+use super::parallax::ParallaxLayer;
+use super::particles::{Burst, ParticleEmitter, Range};
+use super::pipeline::ScaleMode;
+use super::random::Rng;
+use super::resolution::Resolution;
+use super::shapes::{ShapeKind, VectorShape};
+use super::sprite::Sprite;
 use std::collections::HashSet;
+use std::path::Path;
+use std::time::Instant;
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, RenderTarget};
 
-
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum EditorAction {
     QUIT,
     RUN,
     SWITCH_TO_ADD,
     SWITCH_TO_SELECT,
     SWITCH_TO_STATIC,
+    ASSIGN_SPRITE,
+    NEXT_LAYER,
+    TOGGLE_LAYER_VISIBILITY,
+    TOGGLE_LAYER_LOCK,
+    ADD_PARALLAX,
 }
 
 pub struct Button {
@@ -34,10 +55,10 @@ impl Button {
         Button {label: Text { content, color: text_color }, shape, shape_color, action}
     }
 
-    pub fn render<T: sdl2::render::RenderTarget>(&self, canvas: &mut sdl2::render::Canvas<T>, textures: &mut TextureCache) {
+    pub fn render<T: sdl2::render::RenderTarget>(&self, canvas: &mut sdl2::render::Canvas<T>, assets: &mut AssetManager) {
         canvas.set_draw_color(self.shape_color);
         canvas.fill_rect(self.shape).expect("Button render failed");
-        self.label.render(canvas, &mut textures.char_textures, self.shape.x, self.shape.y);
+        self.label.render(canvas, assets, self.shape.x, self.shape.y);
     }
 
 
@@ -46,6 +67,26 @@ impl Button {
     }
 }
 
+// What the properties are changed on.
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum Inspected {
+    // The sprite of the selected object.
+    Sprite,
+    // The particle emitter of the selected object.
+    Emitter,
+    // The current parallax layer.
+    Parallax,
+}
+
+const SPRITE_PROPERTIES: [&str; 8] = ["sheet", "frame", "red", "green", "blue", "alpha", "flip x", "flip y"];
+// Burst is the count of the burst at the start of the emission, spread the
+// width of the angle range.
+const EMITTER_PROPERTIES: [&str; 10] = ["rate", "burst", "lifetime", "speed", "angle", "spread",
+                                        "gravity x", "gravity y", "collide", "bounce"];
+// The layer property selects the current layer.
+const PARALLAX_PROPERTIES: [&str; 10] = ["layer", "texture", "scroll x", "scroll y", "repeat x", "repeat y",
+                                         "offset x", "offset y", "auto scroll x", "auto scroll y"];
+
 #[derive(PartialEq, Debug)]
 enum EditorState {
     ADD_RECT,
@@ -62,6 +103,26 @@ pub struct Editor {
     state: EditorState,
 
     selection: Option<usize>,
+
+    // Opened with Editor::open, their frames are given to the sprites.
+    sheets: Vec<SpriteSheet>,
+    // I selects what is inspected, Tab its next property and the brackets
+    // change it.
+    inspected: Inspected,
+    property: usize,
+    // Index in the parallax layers of the scene.
+    current_parallax: usize,
+
+    // Index in the layers of the scene. New objects are added on it.
+    current_layer: usize,
+
+    // Plays the auto scroll of the parallax layers and the particles.
+    preview: bool,
+    last_update: Instant,
+    preview_rng: Rng,
+
+    // Maps the window to logical pixels, in which the editor is drawn.
+    pub resolution: Resolution,
 }
 
 impl Editor {
@@ -86,6 +147,36 @@ impl Editor {
                 Color::RGB(255, 255, 255),
                 Color::RGB(255, 255, 255),
                 EditorAction::SWITCH_TO_STATIC));
+        buttons.push(Button::new(
+                "P".to_string(),
+                Rect::new(100, 0, 20, 20),
+                Color::RGB(255, 255, 255),
+                Color::RGB(255, 255, 255),
+                EditorAction::ASSIGN_SPRITE));
+        buttons.push(Button::new(
+                "N".to_string(),
+                Rect::new(130, 0, 20, 20),
+                Color::RGB(255, 255, 255),
+                Color::RGB(255, 255, 255),
+                EditorAction::NEXT_LAYER));
+        buttons.push(Button::new(
+                "V".to_string(),
+                Rect::new(160, 0, 20, 20),
+                Color::RGB(255, 255, 255),
+                Color::RGB(255, 255, 255),
+                EditorAction::TOGGLE_LAYER_VISIBILITY));
+        buttons.push(Button::new(
+                "L".to_string(),
+                Rect::new(190, 0, 20, 20),
+                Color::RGB(255, 255, 255),
+                Color::RGB(255, 255, 255),
+                EditorAction::TOGGLE_LAYER_LOCK));
+        buttons.push(Button::new(
+                "B".to_string(),
+                Rect::new(220, 0, 20, 20),
+                Color::RGB(255, 255, 255),
+                Color::RGB(255, 255, 255),
+                EditorAction::ADD_PARALLAX));
         Editor {
             current_scene: Scene::new(),
             debug_text: Text { content: String::new(), color: Color::RGB(255, 0, 0) },
@@ -93,9 +184,33 @@ impl Editor {
             buttons,
             state: EditorState::ADD_RECT,
             selection: None,
+            sheets: Vec::new(),
+            inspected: Inspected::Sprite,
+            property: 0,
+            current_parallax: 0,
+            current_layer: 1,
+            preview: false,
+            last_update: Instant::now(),
+            preview_rng: Rng::new(0),
+            resolution: Resolution::new(800, 600, ScaleMode::Expand),
         }
     }
 
+    // Opens a file made with the tools of the artists. A map replaces the
+    // scene, the frames of the sprite sheets are given to the objects with the
+    // sprite button.
+    pub fn open(&mut self, path: &Path) -> Result<(), ImportError> {
+        match import::load(path)? {
+            Import::Scene(scene) => {
+                self.current_scene = *scene;
+                self.selection = None;
+                self.current_layer = self.current_layer.min(self.current_scene.layers.len().saturating_sub(1));
+            },
+            Import::Sheet(sheet) => self.sheets.push(*sheet),
+        }
+        Ok(())
+    }
+
     pub fn update(&mut self, events: &mut sdl2::EventPump) -> Option<EditorAction> {
 
         for event in events.poll_iter() {
@@ -103,16 +218,37 @@ impl Editor {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } |
                     Event::Quit { .. } => return Some(EditorAction::QUIT),
                     Event::KeyDown { keycode: Some(Keycode::Space), .. } => return Some(EditorAction::RUN),
-                    Event::KeyDown { keycode: Some(Keycode::Right), .. } => self.current_scene.camera.position.x += 1,
-                    Event::KeyDown { keycode: Some(Keycode::Left), .. } => self.current_scene.camera.position.x -= 1,
-                    Event::KeyDown { keycode: Some(Keycode::Up), .. } => self.current_scene.camera.position.y -= 1,
-                    Event::KeyDown { keycode: Some(Keycode::Down), .. } => self.current_scene.camera.position.y += 1,
+                    Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } => self.resolution.resize(w as u32, h as u32),
+                    Event::KeyDown { keycode: Some(Keycode::Right), .. } => self.current_scene.camera.position.x += 1.0,
+                    Event::KeyDown { keycode: Some(Keycode::Left), .. } => self.current_scene.camera.position.x -= 1.0,
+                    Event::KeyDown { keycode: Some(Keycode::Up), .. } => self.current_scene.camera.position.y -= 1.0,
+                    Event::KeyDown { keycode: Some(Keycode::Down), .. } => self.current_scene.camera.position.y += 1.0,
+                    Event::KeyDown { keycode: Some(Keycode::Equals), .. } => self.current_scene.camera.zoom *= 1.25,
+                    Event::KeyDown { keycode: Some(Keycode::Minus), .. } => self.current_scene.camera.zoom /= 1.25,
+                    Event::KeyDown { keycode: Some(Keycode::Num0), .. } => self.current_scene.camera.zoom = 1.0,
+                    Event::KeyDown { keycode: Some(Keycode::PageUp), .. } => self.change_z_index(1),
+                    Event::KeyDown { keycode: Some(Keycode::PageDown), .. } => self.change_z_index(-1),
+                    Event::KeyDown { keycode: Some(Keycode::T), .. } => self.toggle_preview(),
+                    Event::KeyDown { keycode: Some(Keycode::C), .. } => self.toggle_follow(),
+                    Event::KeyDown { keycode: Some(Keycode::E), .. } => self.toggle_emitter(),
+                    Event::KeyDown { keycode: Some(Keycode::H), .. } => self.next_shape(),
+                    Event::KeyDown { keycode: Some(Keycode::G), .. } => self.toggle_lighting(),
+                    Event::KeyDown { keycode: Some(Keycode::I), .. } => self.inspect(match self.inspected {
+                        Inspected::Sprite => Inspected::Emitter,
+                        Inspected::Emitter => Inspected::Parallax,
+                        Inspected::Parallax => Inspected::Sprite,
+                    }),
+                    Event::KeyDown { keycode: Some(Keycode::Tab), .. } => self.property = (self.property + 1) % self.properties().len(),
+                    Event::KeyDown { keycode: Some(Keycode::RightBracket), .. } => self.change_property(1),
+                    Event::KeyDown { keycode: Some(Keycode::LeftBracket), .. } => self.change_property(-1),
                     _ => {}
             }
         }
 
         // get a mouse state
         let state = events.mouse_state();
+        let mouse = self.resolution.window_to_logical(state.x(), state.y());
+        let (mouse_x, mouse_y) = (mouse.x.floor() as i32, mouse.y.floor() as i32);
 
         // Create a set of pressed Keys.
         let buttons = state.pressed_mouse_buttons().collect();
@@ -125,9 +261,12 @@ impl Editor {
         let current_static_color = Color::RGB(130, 60, 255);
         if !new_buttons.is_empty() {
             let mut button_clicked = false;
+            let mut toggle_sprite = false;
+            let mut add_parallax = false;
+            let mut layer_action = None;
             // detect if click on  editor button.
             for button in &self.buttons {
-                if button.is_pressed(state.x(), state.y()) {
+                if button.is_pressed(mouse_x, mouse_y) {
                     button_clicked = true;
                     match button.action {
                         EditorAction::QUIT => return Some(EditorAction::QUIT),
@@ -135,34 +274,54 @@ impl Editor {
                         EditorAction::SWITCH_TO_SELECT => self.state = EditorState::SELECT,
                         EditorAction::SWITCH_TO_ADD => self.state = EditorState::ADD_RECT,
                         EditorAction::SWITCH_TO_STATIC => self.state = EditorState::ADD_STATIC,
+                        EditorAction::ASSIGN_SPRITE => toggle_sprite = true,
+                        EditorAction::ADD_PARALLAX => add_parallax = true,
+                        EditorAction::NEXT_LAYER |
+                            EditorAction::TOGGLE_LAYER_VISIBILITY |
+                            EditorAction::TOGGLE_LAYER_LOCK => layer_action = Some(button.action),
                     }
                 }
             }
 
+            if toggle_sprite {
+                self.toggle_sprite();
+            }
+            if add_parallax {
+                self.add_parallax();
+            }
+
+            match layer_action {
+                Some(EditorAction::NEXT_LAYER) => {
+                    self.current_layer = (self.current_layer + 1) % self.current_scene.layers.len().max(1);
+                },
+                Some(EditorAction::TOGGLE_LAYER_VISIBILITY) => {
+                    if let Some(layer) = self.current_scene.layers.get_mut(self.current_layer) {
+                        layer.visible = !layer.visible;
+                    }
+                },
+                Some(EditorAction::TOGGLE_LAYER_LOCK) => {
+                    if let Some(layer) = self.current_scene.layers.get_mut(self.current_layer) {
+                        layer.locked = !layer.locked;
+                    }
+                },
+                _ => {},
+            }
+
             if !button_clicked {
 
+                let world = self.view().screen_to_world(mouse);
                 match self.state {
                     EditorState::ADD_RECT => {
                         // If not click on a button, execute whatever action 
-                        self.current_scene.add_rect(state.x() as f32, state.y() as f32, 20, 20, current_color.clone());
+                        self.current_scene.add_rect(world.x, world.y, 20, 20, current_color.clone());
+                        self.move_to_current_layer();
                     },
                     EditorState::SELECT => {
-                        let mut found_selection = false;
-                        for (idx, go) in self.current_scene.gameobjects.iter().enumerate() {
-                            if go.contains_point(state.x(), state.y()) {
-                                self.selection = Some(idx);
-                                found_selection = true;
-                                break;
-                            }
-                        }
-
-                        if !found_selection {
-                            self.selection = None;
-                        }
+                        self.selection = self.pick(world.x.floor() as i32, world.y.floor() as i32);
                     },
                     EditorState::ADD_STATIC => {
-                        self.current_scene.add_static(state.x() as f32, state.y() as f32, 50, 50, current_static_color.clone());
-
+                        self.current_scene.add_static(world.x, world.y, 50, 50, current_static_color.clone());
+                        self.move_to_current_layer();
                     },
                 }
             }
@@ -171,26 +330,449 @@ impl Editor {
         }
 
         self.prev_buttons = buttons;
-        self.debug_text.content = format!("x:{} y:{} state: {:?}", state.x(), state.y(), self.state);
+
+        let now = Instant::now();
+        if self.preview {
+            let dt = (now - self.last_update).as_millis() as u32;
+            for layer in self.current_scene.parallax.iter_mut() {
+                layer.update(dt);
+            }
+            // Without collisions, the editor does not run the physics.
+            for go in self.current_scene.gameobjects.iter_mut() {
+                let center = go.center();
+                if let Some(emitter) = go.emitter.as_mut() {
+                    emitter.update(center, dt, &mut self.preview_rng, &[]);
+                }
+            }
+        }
+        self.last_update = now;
+
+        let layer = self.current_scene.layers.get(self.current_layer);
+        self.debug_text.content = format!("x:{} y:{} state: {:?} layer: {}{}{}{}",
+                                          mouse_x, mouse_y, self.state,
+                                          layer.map(|l| l.name.as_str()).unwrap_or("-"),
+                                          if layer.map(|l| !l.visible).unwrap_or(false) { " hidden" } else { "" },
+                                          if layer.map(|l| l.locked).unwrap_or(false) { " locked" } else { "" },
+                                          self.property_text());
 
         None
     }
 
-    pub fn render(&self, context: &mut Context, mut textures: &mut TextureCache) {
-        let canvas = &mut context.canvas;
+    fn view(&self) -> View {
+        let (w, h) = self.resolution.logical_size();
+        self.current_scene.camera.view(w, h)
+    }
+
+    fn inspect(&mut self, inspected: Inspected) {
+        self.inspected = inspected;
+        self.property = 0;
+    }
+
+    fn properties(&self) -> &'static [&'static str] {
+        match self.inspected {
+            Inspected::Sprite => &SPRITE_PROPERTIES,
+            Inspected::Emitter => &EMITTER_PROPERTIES,
+            Inspected::Parallax => &PARALLAX_PROPERTIES,
+        }
+    }
+
+    fn change_property(&mut self, delta: i32) {
+        match self.inspected {
+            Inspected::Sprite => self.change_sprite(delta),
+            Inspected::Emitter => self.change_emitter(delta),
+            Inspected::Parallax => self.change_parallax(delta),
+        }
+    }
+
+    // The current property and its value, for the debug text.
+    fn property_text(&self) -> String {
+        match self.inspected {
+            Inspected::Sprite => self.sprite_text(),
+            Inspected::Emitter => self.emitter_text(),
+            Inspected::Parallax => self.parallax_text(),
+        }
+    }
+
+    // Images that can be given to the parallax layers: the ones of the sheets
+    // and of the scene.
+    fn textures(&self) -> Vec<String> {
+        let mut textures: Vec<String> = self.sheets.iter().map(|sheet| sheet.atlas.texture.clone()).collect();
+        for texture in self.current_scene.textures() {
+            if !textures.iter().any(|t| t == texture) {
+                textures.push(texture.to_string());
+            }
+        }
+        textures
+    }
+
+    // Repeated horizontally, with the first known image. Its texture is the
+    // next property to change.
+    fn add_parallax(&mut self) {
+        let texture = match self.textures().into_iter().next() {
+            Some(texture) => texture,
+            None => return,
+        };
+        let mut layer = ParallaxLayer::new(&texture, 0.5, 0.5);
+        layer.repeat_x = true;
+        self.current_scene.parallax.push(layer);
+        self.current_parallax = self.current_scene.parallax.len() - 1;
+        self.inspect(Inspected::Parallax);
+        self.property = 1;
+    }
+
+    // Changes the current property of the current parallax layer, delta is 1
+    // or -1. Offsets are in steps of 10 pixels, auto scrolls of 10 pixels per
+    // second.
+    fn change_parallax(&mut self, delta: i32) {
+        let count = self.current_scene.parallax.len();
+        if count == 0 {
+            return;
+        }
+        self.current_parallax = self.current_parallax.min(count - 1);
+
+        let property = PARALLAX_PROPERTIES[self.property];
+        let textures = if property == "texture" { self.textures() } else { Vec::new() };
+        let layer = &mut self.current_scene.parallax[self.current_parallax];
+        let step = delta as f32;
+        match property {
+            "layer" => self.current_parallax = cycle(self.current_parallax, delta, count),
+            "texture" => {
+                let current = textures.iter().position(|t| *t == layer.texture).unwrap_or(0);
+                layer.texture = textures[cycle(current, delta, textures.len())].clone();
+            },
+            "scroll x" => layer.scroll_x = (layer.scroll_x + step * 0.1).max(0.0),
+            "scroll y" => layer.scroll_y = (layer.scroll_y + step * 0.1).max(0.0),
+            "repeat x" => layer.repeat_x = !layer.repeat_x,
+            "repeat y" => layer.repeat_y = !layer.repeat_y,
+            "offset x" => layer.offset_x += step * 10.0,
+            "offset y" => layer.offset_y += step * 10.0,
+            "auto scroll x" => layer.auto_scroll_x += step * 10.0,
+            "auto scroll y" => layer.auto_scroll_y += step * 10.0,
+            _ => {},
+        }
+    }
+
+    fn parallax_text(&self) -> String {
+        let count = self.current_scene.parallax.len();
+        if count == 0 {
+            return " no parallax layer".to_string();
+        }
+        let idx = self.current_parallax.min(count - 1);
+        let layer = &self.current_scene.parallax[idx];
+
+        let name = PARALLAX_PROPERTIES[self.property];
+        let value = match name {
+            "layer" => format!("{}/{}", idx + 1, count),
+            "texture" => layer.texture.clone(),
+            "scroll x" => format!("{:.1}", layer.scroll_x),
+            "scroll y" => format!("{:.1}", layer.scroll_y),
+            "repeat x" => layer.repeat_x.to_string(),
+            "repeat y" => layer.repeat_y.to_string(),
+            "offset x" => layer.offset_x.to_string(),
+            "offset y" => layer.offset_y.to_string(),
+            "auto scroll x" => layer.auto_scroll_x.to_string(),
+            "auto scroll y" => layer.auto_scroll_y.to_string(),
+            _ => String::new(),
+        };
+        format!(" parallax {}: {}", name, value)
+    }
+
+    fn toggle_preview(&mut self) {
+        self.preview = !self.preview;
+        if !self.preview {
+            for layer in self.current_scene.parallax.iter_mut() {
+                layer.reset();
+            }
+            for emitter in self.current_scene.gameobjects.iter_mut().filter_map(|go| go.emitter.as_mut()) {
+                emitter.reset();
+            }
+        }
+    }
+
+    // The camera follows the selected object in game mode. Unnamed objects
+    // are given a name so that the scene can refer to them.
+    fn toggle_follow(&mut self) {
+        let idx = match self.selection {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let scene = &mut self.current_scene;
+        if scene.gameobjects[idx].name.is_empty() {
+            scene.gameobjects[idx].name = format!("object{}", idx);
+        }
+        let name = scene.gameobjects[idx].name.clone();
+        let controller = &mut scene.camera_controller;
+        controller.follow = match &controller.follow {
+            Some(follow) if follow.target == name => None,
+            _ => Some(Follow::new(&name)),
+        };
+    }
+
+    // The object drawn on top at this world position, ignoring the hidden and
+    // locked layers.
+    fn pick(&self, x: i32, y: i32) -> Option<usize> {
+        let scene = &self.current_scene;
+        scene.draw_order().into_iter().rev()
+            .filter_map(|item| match item {
+                DrawItem::GameObject(idx) => Some(idx),
+                _ => None,
+            })
+            .find(|&idx| {
+                let go = &scene.gameobjects[idx];
+                !scene.is_locked(&go.layer) && go.contains_point(x, y)
+            })
+    }
+
+    // Puts the last added object on the current layer.
+    fn move_to_current_layer(&mut self) {
+        let layer = match self.current_scene.layers.get(self.current_layer) {
+            Some(layer) => layer.name.clone(),
+            None => return,
+        };
+        if let Some(go) = self.current_scene.gameobjects.last_mut() {
+            go.layer = layer;
+        }
+    }
+
+    fn change_z_index(&mut self, delta: i32) {
+        if let Some(idx) = self.selection {
+            self.current_scene.gameobjects[idx].z_index += delta;
+        }
+    }
+
+    // Gives the first frame of the last opened sheet to the selected object,
+    // animated with the clips of the sheet. Removes its sprite if it already
+    // has one. The other sheets and frames are chosen with the properties.
+    fn toggle_sprite(&mut self) {
+        if let Some(idx) = self.selection {
+            let go = &mut self.current_scene.gameobjects[idx];
+            if go.sprite.take().is_some() {
+                go.animator = None;
+                return;
+            }
+            if let Some(sheet) = self.sheets.last() {
+                go.sprite = sheet.frames.first().and_then(|frame| sheet.atlas.sprite(frame));
+                go.animator = sheet_animator(sheet);
+                self.inspect(Inspected::Sprite);
+            }
+        }
+    }
+
+    // Changes the current property of the sprite of the selected object,
+    // delta is 1 or -1.
+    fn change_sprite(&mut self, delta: i32) {
+        let go = match self.selection {
+            Some(idx) => &mut self.current_scene.gameobjects[idx],
+            None => return,
+        };
+        let sprite = match go.sprite.as_mut() {
+            Some(sprite) => sprite,
+            None => return,
+        };
+
+        match SPRITE_PROPERTIES[self.property] {
+            // Keeps the tint and flips.
+            "sheet" => {
+                let sheets = &self.sheets;
+                if sheets.is_empty() {
+                    return;
+                }
+                let current = sheet_frame(sheets, sprite).map(|(s, _)| s);
+                let sheet = &sheets[current.map(|s| cycle(s, delta, sheets.len())).unwrap_or(0)];
+                if let Some(frame) = sheet.frames.first() {
+                    sprite.texture = sheet.atlas.texture.clone();
+                    sprite.region = sheet.atlas.region(frame);
+                    sprite.trim = sheet.atlas.trim(frame);
+                    go.animator = sheet_animator(sheet);
+                }
+            },
+            // A chosen frame is not animated.
+            "frame" => if let Some((s, frame)) = sheet_frame(&self.sheets, sprite) {
+                let sheet = &self.sheets[s];
+                if sheet.frames.is_empty() {
+                    return;
+                }
+                let name = &sheet.frames[frame.map(|f| cycle(f, delta, sheet.frames.len())).unwrap_or(0)];
+                sprite.region = sheet.atlas.region(name);
+                sprite.trim = sheet.atlas.trim(name);
+                go.animator = None;
+            },
+            "red" => sprite.tint.0 = step_channel(sprite.tint.0, delta),
+            "green" => sprite.tint.1 = step_channel(sprite.tint.1, delta),
+            "blue" => sprite.tint.2 = step_channel(sprite.tint.2, delta),
+            "alpha" => sprite.alpha = step_channel(sprite.alpha, delta),
+            "flip x" => sprite.flip_horizontal = !sprite.flip_horizontal,
+            "flip y" => sprite.flip_vertical = !sprite.flip_vertical,
+            _ => {},
+        }
+    }
+
+    fn sprite_text(&self) -> String {
+        let sprite = match self.selection.and_then(|idx| self.current_scene.gameobjects[idx].sprite.as_ref()) {
+            Some(sprite) => sprite,
+            None => return String::new(),
+        };
+
+        let name = SPRITE_PROPERTIES[self.property];
+        let value = match name {
+            "sheet" => sprite.texture.clone(),
+            "frame" => sheet_frame(&self.sheets, sprite)
+                .and_then(|(s, frame)| frame.map(|f| self.sheets[s].frames[f].clone()))
+                .unwrap_or_else(|| "-".to_string()),
+            "red" => sprite.tint.0.to_string(),
+            "green" => sprite.tint.1.to_string(),
+            "blue" => sprite.tint.2.to_string(),
+            "alpha" => sprite.alpha.to_string(),
+            "flip x" => sprite.flip_horizontal.to_string(),
+            "flip y" => sprite.flip_vertical.to_string(),
+            _ => String::new(),
+        };
+        format!(" sprite {}: {}", name, value)
+    }
+
+    // Gives a default particle emitter to the selected object, or removes
+    // its emitter. Press T to see the particles.
+    fn toggle_emitter(&mut self) {
+        if let Some(idx) = self.selection {
+            let go = &mut self.current_scene.gameobjects[idx];
+            go.emitter = match go.emitter {
+                Some(_) => None,
+                None => Some(ParticleEmitter::new()),
+            };
+            if go.emitter.is_some() {
+                self.inspect(Inspected::Emitter);
+            }
+        }
+    }
+
+    // Changes the current property of the emitter of the selected object,
+    // delta is 1 or -1.
+    fn change_emitter(&mut self, delta: i32) {
+        let go = match self.selection {
+            Some(idx) => &mut self.current_scene.gameobjects[idx],
+            None => return,
+        };
+        let emitter = match go.emitter.as_mut() {
+            Some(emitter) => emitter,
+            None => return,
+        };
+
+        let step = delta as f32;
+        match EMITTER_PROPERTIES[self.property] {
+            "rate" => emitter.rate = (emitter.rate + step * 5.0).max(0.0),
+            "burst" => {
+                let count = emitter.bursts.iter().find(|b| b.time == 0).map(|b| b.count).unwrap_or(0);
+                let count = (count as i32 + delta * 5).max(0) as u32;
+                emitter.bursts.retain(|b| b.time != 0);
+                if count > 0 {
+                    emitter.bursts.push(Burst { time: 0, count });
+                }
+            },
+            // In steps of 100 milliseconds, at least 100.
+            "lifetime" => {
+                let shift = (step * 100.0).max(100.0 - emitter.lifetime.min);
+                emitter.lifetime = Range::new(emitter.lifetime.min + shift, emitter.lifetime.max + shift);
+            },
+            "speed" => {
+                let shift = (step * 10.0).max(-emitter.speed.min);
+                emitter.speed = Range::new(emitter.speed.min + shift, emitter.speed.max + shift);
+            },
+            // In degrees, the spread is kept.
+            "angle" => emitter.angle = Range::new(emitter.angle.min + step * 15.0, emitter.angle.max + step * 15.0),
+            "spread" => {
+                let half = (step * 5.0).max((emitter.angle.min - emitter.angle.max) / 2.0);
+                emitter.angle = Range::new(emitter.angle.min - half, emitter.angle.max + half);
+            },
+            "gravity x" => emitter.gravity_x += step * 50.0,
+            "gravity y" => emitter.gravity_y += step * 50.0,
+            "collide" => emitter.collide = !emitter.collide,
+            "bounce" => emitter.bounce = (emitter.bounce + step * 0.1).clamp(0.0, 1.0),
+            _ => {},
+        }
+    }
+
+    fn emitter_text(&self) -> String {
+        let emitter = match self.selection.and_then(|idx| self.current_scene.gameobjects[idx].emitter.as_ref()) {
+            Some(emitter) => emitter,
+            None => return String::new(),
+        };
+
+        let name = EMITTER_PROPERTIES[self.property];
+        let value = match name {
+            "rate" => emitter.rate.to_string(),
+            "burst" => emitter.bursts.iter().find(|b| b.time == 0).map(|b| b.count).unwrap_or(0).to_string(),
+            "lifetime" => format!("{}..{}", emitter.lifetime.min, emitter.lifetime.max),
+            "speed" => format!("{}..{}", emitter.speed.min, emitter.speed.max),
+            "angle" => format!("{}", (emitter.angle.min + emitter.angle.max) / 2.0),
+            "spread" => format!("{}", emitter.angle.max - emitter.angle.min),
+            "gravity x" => emitter.gravity_x.to_string(),
+            "gravity y" => emitter.gravity_y.to_string(),
+            "collide" => emitter.collide.to_string(),
+            "bounce" => format!("{:.1}", emitter.bounce),
+            _ => String::new(),
+        };
+        format!(" emitter {}: {}", name, value)
+    }
+
+    // Cycles the selected object through the vector shapes, ending with the
+    // plain rectangle. For prototyping without art.
+    fn next_shape(&mut self) {
+        let idx = match self.selection {
+            Some(idx) => idx,
+            None => return,
+        };
+        let go = &mut self.current_scene.gameobjects[idx];
+        let (w, h) = (go.w() as f32, go.h() as f32);
+        go.shape = match go.shape.as_ref().map(|s| &s.kind) {
+            None => Some(ShapeKind::Circle),
+            Some(ShapeKind::Circle) => Some(ShapeKind::RoundedRect { radius: w.min(h) / 4.0 }),
+            Some(ShapeKind::RoundedRect { .. }) => Some(ShapeKind::Polygon { points: vec![(w / 2.0, 0.0), (w, h), (0.0, h)] }),
+            Some(_) => None,
+        }.map(VectorShape::new);
+    }
+
+    // Darkens the scene with a light on the selected object, or in the
+    // middle of the view. Removes the lighting if there is one.
+    fn toggle_lighting(&mut self) {
+        if self.current_scene.lighting.take().is_some() {
+            return;
+        }
+        let center = match self.selection {
+            Some(idx) => self.current_scene.gameobjects[idx].center(),
+            None => {
+                let viewport = self.view().viewport;
+                self.view().screen_to_world(Vector2d::new(viewport.center().x() as f32, viewport.center().y() as f32))
+            },
+        };
+        let mut lighting = Lighting::new((40, 40, 60));
+        lighting.lights.push(Light::new(center.x, center.y, 200.0));
+        self.current_scene.lighting = Some(lighting);
+    }
+
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, assets: &mut AssetManager) {
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
+        self.resolution.apply(canvas);
+
+        // The editor overlay is drawn above the scene.
+        let stats = self.current_scene.render(canvas, assets);
+        debug_draw::flush(canvas, &self.view(), assets);
 
         for button in self.buttons.iter() {
-            button.render(canvas, &mut textures);
+            button.render(canvas, assets);
         }
 
-        self.debug_text.render(canvas, &mut textures.char_textures, 150, 0);
-        self.current_scene.render(canvas);
+        self.debug_text.render(canvas, assets, 250, 0);
+        let stats_text = Text {
+            content: format!("drawn:{} culled:{} calls:{}", stats.drawn, stats.culled, stats.draw_calls),
+            color: self.debug_text.color,
+        };
+        stats_text.render(canvas, assets, 250, 24);
 
         if let Some(idx) = self.selection {
             let go = &self.current_scene.gameobjects[idx];
-            let rect = Rect::new(go.x() as i32, go.y() as i32, go.w(), go.h());
+            let rect = self.view().world_rect(go.x(), go.y(), go.w() as f32, go.h() as f32);
             canvas.set_draw_color(Color::RGB(255, 255, 255));
             canvas.draw_rect(rect).expect("Could not draw rect: Editor::render");
         }
@@ -199,4 +781,117 @@ impl Editor {
     }
 }
 
+// One state per clip, the first one plays.
+fn sheet_animator(sheet: &SpriteSheet) -> Option<Animator> {
+    if sheet.clips.is_empty() {
+        return None;
+    }
+    Some(Animator::new(sheet.clips.iter()
+                       .map(|clip| AnimatorState { name: clip.name.clone(), clip: clip.clone() })
+                       .collect()))
+}
+
+// Index of the sheet of the sprite, and of its frame in the sheet.
+fn sheet_frame(sheets: &[SpriteSheet], sprite: &Sprite) -> Option<(usize, Option<usize>)> {
+    let s = sheets.iter().position(|sheet| sheet.atlas.texture == sprite.texture)?;
+    let sheet = &sheets[s];
+    Some((s, sheet.frames.iter().position(|f| sheet.atlas.region(f) == sprite.region)))
+}
+
+fn cycle(idx: usize, delta: i32, len: usize) -> usize {
+    (idx as i32 + delta).rem_euclid(len as i32) as usize
+}
+
+// 15 steps from 0 to 255.
+fn step_channel(value: u8, delta: i32) -> u8 {
+    (value as i32 + delta * 17).clamp(0, 255) as u8
+}
 
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::import::aseprite;
+    use crate::sprite::Region;
+
+    #[test]
+    fn sprite_properties() {
+        let mut editor = Editor::new();
+        editor.sheets.push(aseprite::parse(include_str!("../fixtures/aseprite_array.json")).unwrap());
+        editor.current_scene.add_rect(0.0, 0.0, 16, 16, Color::RGB(255, 255, 255));
+        editor.selection = Some(0);
+        editor.toggle_sprite();
+        assert!(editor.current_scene.gameobjects[0].animator.is_some());
+
+        // Frames in the order of the sheet, the previous one of the first is
+        // the last.
+        editor.property = 1;
+        editor.change_sprite(1);
+        let go = &editor.current_scene.gameobjects[0];
+        assert_eq!(go.sprite.as_ref().unwrap().region, Some(Region::new(16, 0, 16, 16)));
+        assert!(go.animator.is_none());
+        editor.change_sprite(-1);
+        editor.change_sprite(-1);
+        assert!(editor.property_text().ends_with("knight 3.aseprite"));
+
+        editor.property = 5;
+        editor.change_sprite(-1);
+        editor.property = 6;
+        editor.change_sprite(1);
+        let sprite = editor.current_scene.gameobjects[0].sprite.as_ref().unwrap();
+        assert_eq!(sprite.alpha, 238);
+        assert!(sprite.flip_horizontal);
+    }
+    #[test]
+    fn parallax_properties() {
+        let mut editor = Editor::new();
+        editor.sheets.push(aseprite::parse(include_str!("../fixtures/aseprite_array.json")).unwrap());
+        editor.sheets.push(aseprite::parse(include_str!("../fixtures/aseprite_hash.json")).unwrap());
+        editor.add_parallax();
+        editor.add_parallax();
+        assert_eq!(editor.inspected, Inspected::Parallax);
+
+        // The texture property is selected after adding a layer.
+        editor.change_property(1);
+        assert_eq!(editor.current_scene.parallax[1].texture, editor.sheets[1].atlas.texture);
+
+        editor.property = 0;
+        editor.change_property(1);
+        assert_eq!(editor.current_parallax, 0);
+        editor.property = 3;
+        editor.change_property(1);
+        editor.property = 9;
+        editor.change_property(-1);
+        let layer = &editor.current_scene.parallax[0];
+        assert_eq!((layer.scroll_x, layer.scroll_y), (0.5, 0.6));
+        assert_eq!(layer.auto_scroll_y, -10.0);
+        assert_eq!(editor.current_scene.parallax[1].auto_scroll_y, 0.0);
+    }
+    #[test]
+    fn emitter_properties() {
+        let mut editor = Editor::new();
+        editor.current_scene.add_rect(0.0, 0.0, 16, 16, Color::RGB(255, 255, 255));
+        editor.selection = Some(0);
+        editor.toggle_emitter();
+        assert_eq!(editor.inspected, Inspected::Emitter);
+
+        let lifetime = editor.current_scene.gameobjects[0].emitter.as_ref().unwrap().lifetime;
+        // Property, delta and times.
+        for &(property, delta, times) in [(1, 1, 2), (2, -1, 100), (5, -1, 100), (6, 1, 2), (8, 1, 1)].iter() {
+            editor.property = property;
+            for _ in 0..times {
+                editor.change_property(delta);
+            }
+        }
+
+        let emitter = editor.current_scene.gameobjects[0].emitter.as_ref().unwrap();
+        assert_eq!(emitter.bursts, vec![Burst { time: 0, count: 10 }]);
+        // The lifetime stays above 100 milliseconds and keeps its range.
+        assert_eq!(emitter.lifetime.min, 100.0);
+        assert_eq!(emitter.lifetime.max - emitter.lifetime.min, lifetime.max - lifetime.min);
+        // No spread left.
+        assert_eq!(emitter.angle.min, emitter.angle.max);
+        assert_eq!(emitter.gravity_x, 100.0);
+        assert!(emitter.collide);
+    }
+}
@@ -0,0 +1,413 @@
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign, Neg};
+use serde_derive::{Serialize, Deserialize};
+
+use super::math::Vector2d;
+
+// 16.16 fixed-point number. All operations are done on integers so the
+// results are bit-for-bit identical on every machine, which is what lockstep
+// networking and replays need. Overflow wraps like i32 in release.
+//
+// Serialized as the raw i32 so that saving and loading never goes through a
+// float.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Default, Serialize, Deserialize)]
+pub struct Fixed(i32);
+
+pub type FixedVector = Vector2d<Fixed>;
+
+const FRAC_BITS: u32 = 16;
+const ONE_RAW: i32 = 1 << FRAC_BITS;
+
+impl Fixed {
+
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(ONE_RAW);
+    pub const HALF: Fixed = Fixed(ONE_RAW / 2);
+    pub const PI: Fixed = Fixed(205887);
+    pub const HALF_PI: Fixed = Fixed(102944);
+    pub const TWO_PI: Fixed = Fixed(411775);
+    pub const MAX: Fixed = Fixed(i32::MAX);
+    pub const MIN: Fixed = Fixed(i32::MIN);
+    // Smallest positive value.
+    pub const EPSILON: Fixed = Fixed(1);
+
+    pub fn from_raw(raw: i32) -> Fixed {
+        Fixed(raw)
+    }
+
+    pub fn raw(self) -> i32 {
+        self.0
+    }
+
+    pub fn from_int(value: i32) -> Fixed {
+        Fixed(value << FRAC_BITS)
+    }
+
+    // Only use this for setup (level data, constants), never inside the
+    // simulation: float to fixed conversion is deterministic but the float
+    // computations leading to the input may not be.
+    pub fn from_f32(value: f32) -> Fixed {
+        Fixed((value * ONE_RAW as f32).round() as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / ONE_RAW as f32
+    }
+
+    // Rounds toward negative infinity.
+    pub fn to_int(self) -> i32 {
+        self.0 >> FRAC_BITS
+    }
+
+    pub fn floor(self) -> Fixed {
+        Fixed(self.0 & !(ONE_RAW - 1))
+    }
+
+    pub fn ceil(self) -> Fixed {
+        (self + Fixed(ONE_RAW - 1)).floor()
+    }
+
+    pub fn round(self) -> Fixed {
+        (self + Fixed::HALF).floor()
+    }
+
+    pub fn frac(self) -> Fixed {
+        Fixed(self.0 & (ONE_RAW - 1))
+    }
+
+    pub fn abs(self) -> Fixed {
+        Fixed(self.0.wrapping_abs())
+    }
+
+    pub fn signum(self) -> Fixed {
+        Fixed::from_int(self.0.signum())
+    }
+
+    pub fn min(self, other: Fixed) -> Fixed {
+        if self < other { self } else { other }
+    }
+
+    pub fn max(self, other: Fixed) -> Fixed {
+        if self > other { self } else { other }
+    }
+
+    pub fn clamp(self, min: Fixed, max: Fixed) -> Fixed {
+        self.max(min).min(max)
+    }
+
+    // Integer square root on the raw value. Negative values return zero.
+    pub fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        Fixed(isqrt((self.0 as u64) << FRAC_BITS) as i32)
+    }
+
+    // Angle in radians. Taylor series after reducing the angle to
+    // [-pi/2, pi/2], max error is around 2e-4.
+    pub fn sin(self) -> Fixed {
+        // Reduce to [-pi, pi].
+        let mut x = Fixed(self.0 % Fixed::TWO_PI.0);
+        if x > Fixed::PI {
+            x -= Fixed::TWO_PI;
+        } else if x < -Fixed::PI {
+            x += Fixed::TWO_PI;
+        }
+
+        // sin(pi - x) = sin(x)
+        if x > Fixed::HALF_PI {
+            x = Fixed::PI - x;
+        } else if x < -Fixed::HALF_PI {
+            x = -Fixed::PI - x;
+        }
+
+        let x2 = x * x;
+        let x3 = x2 * x;
+        let x5 = x3 * x2;
+        let x7 = x5 * x2;
+        x - x3 / Fixed::from_int(6) + x5 / Fixed::from_int(120) - x7 / Fixed::from_int(5040)
+    }
+
+    pub fn cos(self) -> Fixed {
+        (self + Fixed::HALF_PI).sin()
+    }
+
+    pub fn tan(self) -> Fixed {
+        let c = self.cos();
+        if c == Fixed::ZERO {
+            if self.sin() >= Fixed::ZERO { Fixed::MAX } else { Fixed::MIN }
+        } else {
+            self.sin() / c
+        }
+    }
+
+    // Polynomial approximation, max error around 1.5e-3 rad.
+    pub fn atan2(y: Fixed, x: Fixed) -> Fixed {
+        if x == Fixed::ZERO && y == Fixed::ZERO {
+            return Fixed::ZERO;
+        }
+
+        let quarter_pi = Fixed::HALF_PI / Fixed::from_int(2);
+        let atan = |z: Fixed| {
+            // atan(z) ~ pi/4 z - z (|z| - 1) (0.2447 + 0.0663 |z|) for |z| <= 1
+            let a = z.abs();
+            quarter_pi * z - z * (a - Fixed::ONE) * (Fixed(16037) + Fixed(4345) * a)
+        };
+
+        if x.abs() >= y.abs() {
+            let angle = atan(y / x);
+            if x > Fixed::ZERO {
+                angle
+            } else if y >= Fixed::ZERO {
+                angle + Fixed::PI
+            } else {
+                angle - Fixed::PI
+            }
+        } else {
+            let angle = atan(x / y);
+            if y > Fixed::ZERO {
+                Fixed::HALF_PI - angle
+            } else {
+                -Fixed::HALF_PI - angle
+            }
+        }
+    }
+}
+
+// Bit by bit integer square root.
+fn isqrt(mut value: u64) -> u64 {
+    let mut result: u64 = 0;
+    let mut bit: u64 = 1 << 62;
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if value >= result + bit {
+            value -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_f32())
+    }
+}
+
+impl From<i32> for Fixed {
+    fn from(value: i32) -> Fixed {
+        Fixed::from_int(value)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.wrapping_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed(((self.0 as i64 * other.0 as i64) >> FRAC_BITS) as i32)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    // Panics on division by zero, like integers.
+    fn div(self, other: Fixed) -> Fixed {
+        Fixed((((self.0 as i64) << FRAC_BITS) / other.0 as i64) as i32)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(self.0.wrapping_neg())
+    }
+}
+
+impl Mul<i32> for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: i32) -> Fixed {
+        Fixed(self.0.wrapping_mul(rhs))
+    }
+}
+
+impl Div<i32> for Fixed {
+    type Output = Fixed;
+
+    fn div(self, rhs: i32) -> Fixed {
+        Fixed(self.0.wrapping_div(rhs))
+    }
+}
+
+// Needed for Vector2d<Fixed> * usize.
+impl Mul<usize> for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: usize) -> Fixed {
+        Fixed(self.0.wrapping_mul(rhs as i32))
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Fixed) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Fixed) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, other: Fixed) {
+        *self = *self * other;
+    }
+}
+
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, other: Fixed) {
+        *self = *self / other;
+    }
+}
+
+impl Vector2d<Fixed> {
+
+    pub fn from_float(v: Vector2d<f32>) -> FixedVector {
+        Vector2d::new(Fixed::from_f32(v.x), Fixed::from_f32(v.y))
+    }
+
+    pub fn to_float(self) -> Vector2d<f32> {
+        Vector2d::new(self.x.to_f32(), self.y.to_f32())
+    }
+
+    pub fn dot(&self, other: FixedVector) -> Fixed {
+        self.x * other.x + self.y * other.y
+    }
+
+    // Squared length with the 32 fractional bits of the products, in 64 bits
+    // since it overflows a Fixed for components of about 182.
+    pub fn length_squared(&self) -> u64 {
+        let (x, y) = (self.x.raw() as i64, self.y.raw() as i64);
+        (x * x) as u64 + (y * y) as u64
+    }
+
+    // Saturates at Fixed::MAX.
+    pub fn length(&self) -> Fixed {
+        Fixed(isqrt(self.length_squared()).min(i32::MAX as u64) as i32)
+    }
+
+    pub fn distance(&self, other: FixedVector) -> Fixed {
+        (other - *self).length()
+    }
+
+    pub fn scale(&self, factor: Fixed) -> FixedVector {
+        Vector2d::new(self.x * factor, self.y * factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = Fixed::from_f32(2.5);
+        let b = Fixed::from_int(-4);
+        assert_eq!((a + b).to_f32(), -1.5);
+        assert_eq!((a - b).to_f32(), 6.5);
+        assert_eq!((a * b).to_f32(), -10.0);
+        assert_eq_delta!((b / a).to_f32(), -1.6, 0.0001);
+        assert_eq!((-a).to_f32(), -2.5);
+        assert_eq!(Fixed::from_f32(-1.25).to_int(), -2);
+        assert_eq!(Fixed::from_f32(-1.25).floor(), Fixed::from_int(-2));
+        assert_eq!(Fixed::from_f32(1.25).ceil(), Fixed::from_int(2));
+        assert_eq!(Fixed::from_f32(1.5).round(), Fixed::from_int(2));
+        assert_eq!(Fixed::MIN / -1, Fixed::MIN);
+    }
+
+    #[test]
+    fn sqrt() {
+        assert_eq!(Fixed::from_int(16).sqrt(), Fixed::from_int(4));
+        assert_eq!(Fixed::from_f32(0.25).sqrt(), Fixed::HALF);
+        assert_eq_delta!(Fixed::from_int(2).sqrt().to_f32(), std::f32::consts::SQRT_2, 0.0001);
+        assert_eq!(Fixed::from_int(-1).sqrt(), Fixed::ZERO);
+        assert_eq_delta!(Fixed::from_int(30000).sqrt().to_f32(), 173.205, 0.001);
+    }
+
+    #[test]
+    fn trigonometry() {
+        let mut angle = -7.0f32;
+        while angle < 7.0 {
+            let fixed = Fixed::from_f32(angle);
+            assert_eq_delta!(fixed.sin().to_f32(), angle.sin(), 0.001);
+            assert_eq_delta!(fixed.cos().to_f32(), angle.cos(), 0.001);
+
+            let (y, x) = (angle.sin() * 3.0, angle.cos() * 3.0);
+            let expected = y.atan2(x);
+            let computed = Fixed::atan2(Fixed::from_f32(y), Fixed::from_f32(x)).to_f32();
+            // Same angle modulo 2pi.
+            let diff = (computed - expected).abs();
+            assert!(diff < 0.003 || (diff - 2.0 * std::f32::consts::PI).abs() < 0.003,
+                    "atan2 {} vs {}", computed, expected);
+            angle += 0.1;
+        }
+    }
+
+    #[test]
+    fn vector_of_fixed() {
+        let v1 = Vector2d::new(Fixed::from_int(3), Fixed::from_int(0));
+        let v2 = Vector2d::new(Fixed::from_int(0), Fixed::from_int(4));
+        let sum = v1 + v2;
+        assert_eq!(sum.length(), Fixed::from_int(5));
+        assert_eq!((sum - v1), v2);
+        assert_eq!((v1 * 2usize).x, Fixed::from_int(6));
+    }
+
+    #[test]
+    fn length_of_large_vectors() {
+        let v = Vector2d::new(Fixed::from_int(300), Fixed::from_int(400));
+        assert_eq!(v.length(), Fixed::from_int(500));
+        let from = Vector2d::new(Fixed::from_int(-200), Fixed::from_f32(10.5));
+        let to = Vector2d::new(Fixed::from_int(400), Fixed::from_f32(810.5));
+        assert_eq!(from.distance(to), Fixed::from_int(1000));
+        assert_eq_delta!(Vector2d::new(Fixed::from_int(250), Fixed::from_int(-250)).length().to_f32(),
+                         353.553, 0.001);
+        // Out of range of a Fixed.
+        assert_eq!(Vector2d::new(Fixed::MAX, Fixed::MAX).length(), Fixed::MAX);
+    }
+
+    #[test]
+    fn serialized_as_raw_bits() {
+        let v = Vector2d::new(Fixed::from_f32(1.5), Fixed::from_int(-2));
+        let data = serde_json::to_string(&v).unwrap();
+        assert_eq!(data, r#"{"x":98304,"y":-131072}"#);
+        let back: Vector2d<Fixed> = serde_json::from_str(&data).unwrap();
+        assert_eq!(back, v);
+    }
+}
@@ -1,37 +1,106 @@
-use super::core::{Scene, TextureCache};
-use super::context::Context;
+use super::assets::AssetManager;
+use super::core::{FrameStats, Scene};
+use super::debug_draw;
 use std::collections::HashSet;
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
 
-use super::physics::{RigidBody, PhysicSystem};
+use super::math::Vector2d;
+use super::noise::Noise;
+use super::physics::{BodyType, RigidBody, PhysicSystem};
+use super::pipeline::{Pipeline, ScaleMode};
+use super::random::Rng;
+use super::resolution::Resolution;
+use super::spatial::SpatialGrid;
 
 pub enum GameAction {
     QUIT,
 }
 
-pub struct Game {
+// Milliseconds of the fade out before going back to the editor, when the
+// scene has a render pipeline.
+const QUIT_FADE: u32 = 300;
+
+pub struct Game<'a> {
     current_scene: Scene,
     prev_buttons: HashSet<sdl2::mouse::MouseButton>,
 
     physic_system: PhysicSystem,
+    // Static collision geometry of the tilemaps.
+    tile_bodies: Vec<RigidBody>,
+    // Shadow the lights, static as well.
+    occluders: Vec<Rect>,
+    // Game objects for culling, moved after the physics.
+    grid: SpatialGrid,
+
+    // Seeded from the scene when it is loaded.
+    pub rng: Rng,
+    // Drives the camera shake.
+    noise: Noise,
+
+    // Maps the window to logical pixels, in which the game is drawn.
+    pub resolution: Resolution,
+    // Created from the config of the scene, see set_pipeline.
+    pipeline: Option<Pipeline<'a>>,
+    // Fading out before quitting.
+    quitting: bool,
+
+    // Animation events fired during the last update, with the index of the
+    // game object.
+    pub animation_events: Vec<(usize, String)>,
 }
 
-impl Game {
+impl<'a> Game<'a> {
 
-    pub fn new() -> Game {
+    pub fn new() -> Game<'a> {
+        let current_scene = Scene::new();
         Game {
-            current_scene: Scene::new(),
+            grid: current_scene.spatial_grid(),
+            current_scene,
             prev_buttons: HashSet::new(),
             physic_system: PhysicSystem{},
+            tile_bodies: Vec::new(),
+            occluders: Vec::new(),
+            rng: Rng::new(0),
+            noise: Noise::new(0),
+            resolution: Resolution::new(800, 600, ScaleMode::Fit),
+            pipeline: None,
+            quitting: false,
+            animation_events: Vec::new(),
         }
     }
 
     pub fn set_scene(&mut self, scene: &Scene) {
         let data = serde_json::to_string(scene).unwrap();
         self.current_scene = serde_json::from_str(&data).unwrap();
+        debug_draw::clear();
+        self.rng = self.current_scene.rng();
+        self.noise = self.current_scene.noise();
+        self.tile_bodies = self.current_scene.tilemaps.iter()
+            .flat_map(|t| t.collision_bodies())
+            .collect();
+        self.occluders = self.current_scene.occluders();
+        self.grid = self.current_scene.spatial_grid();
+    }
+
+    // The pipeline must be made from the config of the current scene.
+    pub fn set_pipeline(&mut self, pipeline: Option<Pipeline<'a>>) {
+        self.pipeline = pipeline;
+        self.quitting = false;
+    }
+
+    pub fn fade_out(&mut self, duration: u32) {
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            pipeline.fade_out(duration);
+        }
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.pipeline.as_ref().is_some_and(|p| p.is_fading())
     }
 
     pub fn update(&mut self, events: &mut sdl2::EventPump, dt: u32) -> Option<GameAction> {
@@ -39,11 +108,22 @@ impl Game {
         for event in events.poll_iter() {
             match event {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } |
-                    Event::Quit { .. } => return Some(GameAction::QUIT),
+                    Event::Quit { .. } if !self.quitting => {
+                        self.fade_out(QUIT_FADE);
+                        self.quitting = true;
+                    },
+                    Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } => self.resolution.resize(w as u32, h as u32),
                     _ => {}
             }
         }
 
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            pipeline.update(dt);
+        }
+        if self.quitting && !self.is_fading() {
+            return Some(GameAction::QUIT);
+        }
+
         // get a mouse state
         let state = events.mouse_state();
 
@@ -61,17 +141,115 @@ impl Game {
         // TODO not so good here... maybe store rigid bodies in the physic system..
         let bodies: Vec<&mut RigidBody> = self.current_scene.gameobjects.iter_mut()
             .map(|go| &mut go.body)
+            .chain(self.tile_bodies.iter_mut())
             .collect();
         self.physic_system.update(bodies, dt);
+        self.current_scene.update_grid(&mut self.grid);
+
+        self.update_animations(dt);
+        self.update_particles(dt);
+        for layer in self.current_scene.parallax.iter_mut() {
+            layer.update(dt);
+        }
+
+        // After the physics so that the camera sees where the target is now.
+        let (w, h) = self.view_size();
+        let scene = &mut self.current_scene;
+        scene.camera_controller.update(&mut scene.camera, &scene.gameobjects, w, h, dt, &self.noise);
 
         None
     }
 
-    pub fn render(&self, context: &mut Context, textures: &TextureCache) {
-        context.canvas.set_draw_color(Color::RGB(0, 0, 0));
-        context.canvas.clear();
-        self.current_scene.render(&mut context.canvas);
-        context.canvas.present();
+    // Size of what the cameras draw on: the off-screen target of the
+    // pipeline if there is one, the logical resolution otherwise.
+    fn view_size(&self) -> (u32, u32) {
+        match &self.current_scene.pipeline {
+            Some(config) => (config.width, config.height),
+            None => self.resolution.logical_size(),
+        }
+    }
+
+    // Mouse position in the pixels the cameras draw on, see view_size.
+    pub fn mouse_position(&self, events: &sdl2::EventPump) -> Vector2d<f32> {
+        let state = events.mouse_state();
+        let logical = self.resolution.window_to_logical(state.x(), state.y());
+        match &self.current_scene.pipeline {
+            Some(config) => {
+                let (w, h) = self.resolution.logical_size();
+                config.output_to_target(logical, w, h)
+            },
+            None => logical,
+        }
+    }
+
+    // Shakes the camera, amount is between 0 and 1. See camera::Shake.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.current_scene.camera_controller.add_trauma(amount);
+    }
+
+    fn update_animations(&mut self, dt: u32) {
+        self.animation_events.clear();
+        for (idx, go) in self.current_scene.gameobjects.iter_mut().enumerate() {
+            if let Some(animator) = go.animator.as_mut() {
+                for e in animator.update(dt) {
+                    self.animation_events.push((idx, e));
+                }
+
+                if let (Some(sprite), Some(frame)) = (go.sprite.as_mut(), animator.current_frame()) {
+                    sprite.region = Some(frame.region);
+                    sprite.trim = frame.trim;
+                }
+            }
+        }
+    }
+
+    // Particles collide with the static game objects and the tiles.
+    fn update_particles(&mut self, dt: u32) {
+        let gameobjects = &mut self.current_scene.gameobjects;
+        if gameobjects.iter().all(|go| go.emitter.is_none()) {
+            return;
+        }
+
+        let statics: Vec<Rect> = gameobjects.iter()
+            .map(|go| &go.body)
+            .chain(self.tile_bodies.iter())
+            .filter(|body| body.body_type == BodyType::Static)
+            .map(|body| Rect::new(body.position.x as i32, body.position.y as i32, body.shape.w, body.shape.h))
+            .collect();
+        for go in gameobjects.iter_mut() {
+            let center = go.center();
+            if let Some(emitter) = go.emitter.as_mut() {
+                emitter.update(center, dt, &mut self.rng, &statics);
+            }
+        }
+    }
+
+    pub fn render<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, assets: &mut AssetManager) -> FrameStats {
+        let scene = &self.current_scene;
+        let (grid, occluders) = (&self.grid, &self.occluders);
+        let mut stats = FrameStats::new();
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        self.resolution.apply(canvas);
+
+        let mut draw = |canvas: &mut Canvas<T>| {
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            stats = scene.render_with_grid(canvas, assets, grid, occluders);
+            let (w, h) = canvas.viewport().size();
+            debug_draw::flush(canvas, &scene.camera.view(w, h), assets);
+        };
+
+        match self.pipeline.as_mut() {
+            Some(pipeline) => {
+                if let Err(e) = pipeline.render(canvas, draw) {
+                    println!("Render pipeline failed: {}", e);
+                }
+            },
+            None => draw(canvas),
+        }
+        canvas.present();
+        stats
     }
 }
 
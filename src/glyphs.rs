@@ -0,0 +1,255 @@
+// Glyph atlas for the text. Glyphs are rendered in white by SDL_ttf the first
+// time a character is drawn and packed into large pages, so a line of text is
+// a few copies from the same texture and any character can be shown. The
+// pages are kept as surfaces and turned into textures when they change.
+use std::collections::HashMap;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture};
+use sdl2::surface::{Surface, SurfaceRef};
+
+// Size of the pages, in pixels.
+const PAGE_SIZE: u32 = 512;
+// Between glyphs, so that scaled text does not bleed into its neighbours.
+const PADDING: u32 = 1;
+
+// Fills a page row by row. A glyph goes to the right of the previous one
+// until the row is full, then a new row starts below the tallest glyph.
+#[derive(Debug, Clone)]
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    row_height: u32,
+}
+
+impl ShelfPacker {
+
+    pub fn new(width: u32, height: u32) -> ShelfPacker {
+        ShelfPacker { width, height, x: 0, y: 0, row_height: 0 }
+    }
+
+    // Top left of a free w x h area, None when the page is full.
+    pub fn pack(&mut self, w: u32, h: u32) -> Option<(i32, i32)> {
+        let (w, h) = (w + PADDING, h + PADDING);
+        if w > self.width || h > self.height {
+            return None;
+        }
+        if self.x + w > self.width {
+            self.y += self.row_height;
+            self.x = 0;
+            self.row_height = 0;
+        }
+        if self.y + h > self.height {
+            return None;
+        }
+        let position = (self.x as i32, self.y as i32);
+        self.x += w;
+        self.row_height = self.row_height.max(h);
+        Some(position)
+    }
+}
+
+// Where a glyph is in the atlas.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Glyph {
+    pub page: usize,
+    pub region: Rect,
+}
+
+struct Page<'a> {
+    surface: Surface<'static>,
+    packer: ShelfPacker,
+    // None when the surface changed since it was made.
+    texture: Option<Texture<'a>>,
+}
+
+pub struct GlyphAtlas<'a> {
+    glyphs: HashMap<char, Glyph>,
+    // Box drawn for the characters no font has.
+    missing: Option<Glyph>,
+    pages: Vec<Page<'a>>,
+}
+
+impl<'a> GlyphAtlas<'a> {
+
+    pub fn new() -> GlyphAtlas<'a> {
+        GlyphAtlas { glyphs: HashMap::new(), missing: None, pages: Vec::new() }
+    }
+
+    // Forgets every glyph, when the fonts change.
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+        self.missing = None;
+        self.pages.clear();
+    }
+
+    pub fn get(&self, c: char) -> Option<Glyph> {
+        self.glyphs.get(&c).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    // Copies the image of the glyph of c into a page, in a new page when the
+    // others are full.
+    pub fn insert(&mut self, c: char, image: &mut SurfaceRef) -> Result<Glyph, String> {
+        let glyph = self.pack(image)?;
+        self.glyphs.insert(c, glyph);
+        Ok(glyph)
+    }
+
+    // Draws c with the missing glyph box, created for this text height the
+    // first time.
+    pub fn insert_missing(&mut self, c: char, height: u32) -> Result<Glyph, String> {
+        let glyph = match self.missing {
+            Some(glyph) => glyph,
+            None => {
+                let mut image = missing_glyph_image(height)?;
+                let glyph = self.pack(&mut image)?;
+                self.missing = Some(glyph);
+                glyph
+            },
+        };
+        self.glyphs.insert(c, glyph);
+        Ok(glyph)
+    }
+
+    fn pack(&mut self, image: &mut SurfaceRef) -> Result<Glyph, String> {
+        let (w, h) = (image.width().max(1), image.height().max(1));
+        let mut found = None;
+        for (idx, page) in self.pages.iter_mut().enumerate() {
+            if let Some((x, y)) = page.packer.pack(w, h) {
+                found = Some((idx, x, y));
+                break;
+            }
+        }
+        let (page, x, y) = match found {
+            Some(found) => found,
+            None => {
+                let size = PAGE_SIZE.max(w + PADDING).max(h + PADDING);
+                let mut packer = ShelfPacker::new(size, size);
+                let (x, y) = packer.pack(w, h).unwrap();
+                let mut surface = Surface::new(size, size, PixelFormatEnum::RGBA8888)?;
+                surface.fill_rect(None, Color::RGBA(255, 255, 255, 0))?;
+                self.pages.push(Page { surface, packer, texture: None });
+                (self.pages.len() - 1, x, y)
+            },
+        };
+
+        let region = Rect::new(x, y, w, h);
+        // Copied as is, blending would darken the edges against the
+        // transparent page.
+        image.set_blend_mode(BlendMode::None)?;
+        let page_ref = &mut self.pages[page];
+        image.blit(None, &mut page_ref.surface, Some(region))?;
+        page_ref.texture = None;
+        Ok(Glyph { page, region })
+    }
+
+    // Texture of the page, made again with create_texture if glyphs were
+    // added to it.
+    pub fn texture<F>(&mut self, page: usize, create_texture: F) -> Result<&mut Texture<'a>, String>
+        where F: Fn(&SurfaceRef) -> Result<Texture<'a>, String> {
+        let page = self.pages.get_mut(page).ok_or_else(|| "no such glyph page".to_string())?;
+        if page.texture.is_none() {
+            let mut texture = create_texture(&page.surface)?;
+            texture.set_blend_mode(BlendMode::Blend);
+            page.texture = Some(texture);
+        }
+        Ok(page.texture.as_mut().unwrap())
+    }
+}
+
+// White outline of a box, half as wide as it is high, with a pixel of
+// space on each side as if it were a letter.
+fn missing_glyph_image(height: u32) -> Result<Surface<'static>, String> {
+    let height = height.max(4);
+    let width = (height / 2).max(4);
+    let mut surface = Surface::new(width, height, PixelFormatEnum::RGBA8888)?;
+    surface.fill_rect(None, Color::RGBA(255, 255, 255, 0))?;
+    let white = Color::RGBA(255, 255, 255, 255);
+    let (x0, y0, w, h) = (1, (height / 5) as i32, width - 2, height - height / 5 * 2);
+    surface.fill_rects(&[Rect::new(x0, y0, w, 1), Rect::new(x0, y0 + h as i32 - 1, w, 1),
+                         Rect::new(x0, y0, 1, h), Rect::new(x0 + w as i32 - 1, y0, 1, h)], white)?;
+    Ok(surface)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn image(w: u32, h: u32) -> Surface<'static> {
+        let mut surface = Surface::new(w, h, PixelFormatEnum::RGBA8888).unwrap();
+        surface.fill_rect(None, Color::RGBA(255, 255, 255, 128)).unwrap();
+        surface
+    }
+
+    fn pixel(surface: &SurfaceRef, x: u32, y: u32) -> Color {
+        let pitch = surface.pitch() as usize;
+        let format = surface.pixel_format();
+        surface.with_lock(|pixels| {
+            let offset = y as usize * pitch + x as usize * 4;
+            let value = u32::from_ne_bytes([pixels[offset], pixels[offset + 1], pixels[offset + 2], pixels[offset + 3]]);
+            Color::from_u32(&format, value)
+        })
+    }
+
+    #[test]
+    fn shelf_packing() {
+        let mut packer = ShelfPacker::new(20, 10);
+        assert_eq!(packer.pack(8, 4), Some((0, 0)));
+        assert_eq!(packer.pack(8, 2), Some((9, 0)));
+        // Does not fit on the row: below the tallest glyph of the row.
+        assert_eq!(packer.pack(8, 3), Some((0, 5)));
+        assert_eq!(packer.pack(8, 5), None);
+        assert_eq!(packer.pack(30, 1), None);
+    }
+
+    #[test]
+    fn glyphs_are_copied_to_pages() {
+        let mut atlas = GlyphAtlas::new();
+        let a = atlas.insert('a', &mut image(10, 20)).unwrap();
+        let b = atlas.insert('!', &mut image(12, 20)).unwrap();
+        assert_eq!(a, Glyph { page: 0, region: Rect::new(0, 0, 10, 20) });
+        assert_eq!(b.region, Rect::new(11, 0, 12, 20));
+        assert_eq!(atlas.get('!'), Some(b));
+        assert_eq!(atlas.get('?'), None);
+
+        // Not blended with the empty page.
+        let page = &atlas.pages[0].surface;
+        assert_eq!(pixel(page, 5, 5), Color::RGBA(255, 255, 255, 128));
+        assert_eq!(pixel(page, 10, 5).a, 0);
+
+        // Too large for a page: on its own larger page.
+        let big = atlas.insert('W', &mut image(600, 30)).unwrap();
+        assert_eq!(big.page, 1);
+        assert_eq!(atlas.page_count(), 2);
+    }
+
+    #[test]
+    fn missing_glyphs_share_a_box() {
+        let mut atlas = GlyphAtlas::new();
+        let first = atlas.insert_missing('\u{1F600}', 20).unwrap();
+        let second = atlas.insert_missing('\u{E000}', 20).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.region.size(), (10, 20));
+        assert_eq!(atlas.len(), 2);
+
+        let page = &atlas.pages[0].surface;
+        assert_eq!(pixel(page, 1, 4), Color::RGBA(255, 255, 255, 255));
+        assert_eq!(pixel(page, 4, 10).a, 0);
+
+        atlas.clear();
+        assert_eq!(atlas.len(), 0);
+        assert_eq!(atlas.page_count(), 0);
+    }
+}
@@ -0,0 +1,215 @@
+// Aseprite "Export Sprite Sheet" JSON, hash or array layout. Frame tags
+// become animation clips, slices are stored in the atlas.
+use serde_derive::Deserialize;
+use std::path::Path;
+
+use super::{build_atlas, image_path, read_file, ImportError, RawFrames, RawRect, SpriteSheet};
+use crate::animation::{AnimationClip, Frame, PlayMode};
+use crate::sprite::{Region, Slice};
+
+// Used when a frame has no duration.
+const DEFAULT_DURATION: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+struct File {
+    frames: RawFrames,
+    meta: Meta,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    frame_tags: Vec<FrameTag>,
+    #[serde(default)]
+    slices: Vec<RawSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FrameTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default = "forward")]
+    direction: String,
+    // Number of times the tag is played, forever when missing.
+    #[serde(default)]
+    repeat: Option<String>,
+}
+
+fn forward() -> String {
+    "forward".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSlice {
+    name: String,
+    keys: Vec<SliceKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SliceKey {
+    bounds: RawRect,
+    center: Option<RawRect>,
+    pivot: Option<Pivot>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pivot {
+    x: i32,
+    y: i32,
+}
+
+fn to_region(r: &RawRect) -> Region {
+    Region::new(r.x, r.y, r.w, r.h)
+}
+
+pub fn parse(json: &str) -> Result<SpriteSheet, ImportError> {
+    let file: File = serde_json::from_str(json)?;
+    let frames = file.frames.into_named()?;
+    let (mut atlas, names) = build_atlas(&file.meta.image, &frames);
+
+    let anim_frames: Vec<Frame> = frames.iter().map(|(_, f)| Frame {
+        region: f.region(),
+        duration: f.duration.unwrap_or(DEFAULT_DURATION),
+        trim: f.trim(),
+    }).collect();
+
+    let mut clips = Vec::new();
+    for tag in &file.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= anim_frames.len() {
+            return Err(ImportError::Format(format!("tag '{}' uses frames {}..{} out of {}",
+                                                   tag.name, tag.from, tag.to, anim_frames.len())));
+        }
+
+        let mut tag_frames = anim_frames[tag.from..=tag.to].to_vec();
+        let mode = match tag.direction.as_str() {
+            "forward" => PlayMode::Loop,
+            "reverse" => {
+                tag_frames.reverse();
+                PlayMode::Loop
+            },
+            "pingpong" => PlayMode::PingPong,
+            "pingpong_reverse" => {
+                tag_frames.reverse();
+                PlayMode::PingPong
+            },
+            other => return Err(ImportError::Format(format!("unknown direction '{}' for tag '{}'", other, tag.name))),
+        };
+
+        // A finite repeat count is unrolled into a clip played once.
+        let repeat = tag.repeat.as_ref().and_then(|r| r.parse::<usize>().ok()).unwrap_or(0);
+        let (tag_frames, mode) = if repeat > 0 && mode == PlayMode::Loop {
+            (tag_frames.iter().cloned().cycle().take(tag_frames.len() * repeat).collect(), PlayMode::Once)
+        } else {
+            (tag_frames, mode)
+        };
+        clips.push(AnimationClip::new(&tag.name, tag_frames, mode));
+    }
+
+    // Without tags the whole sheet is a single animation.
+    if clips.is_empty() && !anim_frames.is_empty() {
+        clips.push(AnimationClip::new("default", anim_frames, PlayMode::Loop));
+    }
+
+    // Slices can be animated, only the first key is kept.
+    for slice in &file.meta.slices {
+        if let Some(key) = slice.keys.first() {
+            atlas.slices.insert(slice.name.clone(), Slice {
+                bounds: to_region(&key.bounds),
+                center: key.center.as_ref().map(to_region),
+                pivot: key.pivot.as_ref().map(|p| (p.x, p.y)),
+            });
+        }
+    }
+
+    Ok(SpriteSheet { atlas, frames: names, clips })
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<SpriteSheet, ImportError> {
+    let path = path.as_ref();
+    let mut sheet = parse(&read_file(path)?)?;
+    sheet.atlas.texture = image_path(path, &sheet.atlas.texture);
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sprite::Trim;
+
+    #[test]
+    fn import_array() {
+        let sheet = parse(include_str!("../../fixtures/aseprite_array.json")).unwrap();
+        assert_eq!(sheet.atlas.texture, "knight.png");
+        assert_eq!(sheet.frames, vec!["knight 0.aseprite", "knight 1.aseprite", "knight 2.aseprite", "knight 3.aseprite"]);
+        assert_eq!(sheet.atlas.region("knight 1.aseprite"), Some(Region::new(16, 0, 16, 16)));
+
+        // Trimmed frame.
+        assert_eq!(sheet.atlas.region("knight 3.aseprite"), Some(Region::new(48, 0, 10, 14)));
+        assert_eq!(sheet.atlas.trim("knight 3.aseprite"), Some(Trim { x: 3, y: 2, source_w: 16, source_h: 16, rotated: false }));
+        assert_eq!(sheet.atlas.trim("knight 0.aseprite"), None);
+
+        let idle = sheet.clip("idle").unwrap();
+        assert_eq!(idle.mode, PlayMode::Loop);
+        assert_eq!(idle.frames.len(), 2);
+        assert_eq!(idle.total_duration(), 300);
+
+        let attack = sheet.clip("attack").unwrap();
+        assert_eq!(attack.mode, PlayMode::PingPong);
+        assert_eq!(attack.frames[1].trim.map(|t| t.x), Some(3));
+
+        // Played once.
+        let hit = sheet.clip("hit").unwrap();
+        assert_eq!(hit.mode, PlayMode::Once);
+        assert_eq!(hit.frames.len(), 1);
+
+        let hitbox = &sheet.atlas.slices["hitbox"];
+        assert_eq!(hitbox.bounds, Region::new(4, 2, 8, 12));
+        assert_eq!(hitbox.pivot, Some((8, 14)));
+        assert_eq!(hitbox.center, None);
+    }
+
+    #[test]
+    fn import_hash() {
+        let sheet = parse(include_str!("../../fixtures/aseprite_hash.json")).unwrap();
+        // In the order of the file, not alphabetically.
+        assert_eq!(sheet.frames[2], "coin 2");
+        assert_eq!(sheet.frames[10], "coin 10");
+
+        // Reverse tag.
+        let spin = sheet.clip("spin").unwrap();
+        assert_eq!(spin.frames[0].region, Region::new(80, 0, 8, 8));
+        assert_eq!(spin.frames.last().unwrap().region, Region::new(0, 0, 8, 8));
+
+        let panel = &sheet.atlas.slices["panel"];
+        assert_eq!(panel.center, Some(Region::new(2, 2, 4, 4)));
+    }
+
+    #[test]
+    fn no_tags_gives_default_clip() {
+        let json = r#"{
+            "frames": [{"filename": "a", "frame": {"x": 0, "y": 0, "w": 4, "h": 4}, "duration": 50}],
+            "meta": {"image": "a.png"}
+        }"#;
+        let sheet = parse(json).unwrap();
+        assert_eq!(sheet.clips.len(), 1);
+        assert_eq!(sheet.clips[0].name, "default");
+        assert_eq!(sheet.clips[0].total_duration(), 50);
+    }
+
+    #[test]
+    fn tag_out_of_range() {
+        let json = r#"{
+            "frames": [{"filename": "a", "frame": {"x": 0, "y": 0, "w": 4, "h": 4}}],
+            "meta": {"frameTags": [{"name": "run", "from": 0, "to": 3}]}
+        }"#;
+        match parse(json) {
+            Err(ImportError::Format(_)) => {},
+            other => panic!("unexpected {:?}", other.map(|s| s.frames)),
+        }
+    }
+}
@@ -0,0 +1,506 @@
+// LDtk projects (.ldtk). Every level becomes a scene with its content at its
+// position in the world, so the scenes of neighbour levels can be merged and
+// shown side by side.
+//
+// Tile, auto and IntGrid layers with tiles become tilemaps. LDtk can stack
+// several tiles in a cell, extra tiles go in additional layers of the same
+// tilemap. The values of an IntGrid layer are kept in an invisible collision
+// layer, every non zero value is solid. Entities become game objects with
+// their fields as properties, dynamic when they have a "dynamic" tag or a
+// "body" field set to "dynamic".
+use sdl2::pixels::Color;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{read_file, ImportError};
+use crate::core::{GameObject, Property, Scene};
+use crate::physics::BodyType;
+use crate::sprite::{Region, Sprite};
+use crate::tilemap::{Tilemap, Tileset, FLIP_HORIZONTAL, FLIP_VERTICAL};
+
+pub struct Level {
+    pub name: String,
+    // Position and size in the world, in pixels.
+    pub world_x: i32,
+    pub world_y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scene: Scene,
+}
+
+pub struct Project {
+    pub levels: Vec<Level>,
+}
+
+impl Project {
+
+    pub fn level(&self, name: &str) -> Option<&Level> {
+        self.levels.iter().find(|l| l.name == name)
+    }
+
+    // All the levels in a single scene. The properties of the levels are
+    // merged, the last level wins.
+    pub fn world_scene(self) -> Scene {
+        let mut world = Scene::new();
+        for level in self.levels {
+            world.gameobjects.extend(level.scene.gameobjects);
+            world.tilemaps.extend(level.scene.tilemaps);
+            world.properties.extend(level.scene.properties);
+        }
+        world
+    }
+}
+
+pub fn parse(json: &str) -> Result<Project, ImportError> {
+    build_project(serde_json::from_str(json)?, None)
+}
+
+// Also loads the external levels. Tileset images are relative to the project.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Project, ImportError> {
+    let path = path.as_ref();
+    build_project(serde_json::from_str(&read_file(path)?)?, path.parent())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdtkProject {
+    #[serde(default)]
+    world_layout: Option<String>,
+    defs: Defs,
+    levels: Vec<LdtkLevel>,
+}
+
+#[derive(Deserialize)]
+struct Defs {
+    #[serde(default)]
+    tilesets: Vec<TilesetDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TilesetDef {
+    uid: i64,
+    // None for the internal icons tileset.
+    rel_path: Option<String>,
+    px_wid: u32,
+    tile_grid_size: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    padding: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LdtkLevel {
+    identifier: String,
+    #[serde(default)]
+    world_x: i32,
+    #[serde(default)]
+    world_y: i32,
+    px_wid: u32,
+    px_hei: u32,
+    #[serde(default)]
+    field_instances: Vec<FieldInstance>,
+    // None when the level is in its own file.
+    layer_instances: Option<Vec<LayerInstance>>,
+    external_rel_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LayerInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__type")]
+    kind: String,
+    #[serde(rename = "__cWid")]
+    width: u32,
+    #[serde(rename = "__cHei")]
+    height: u32,
+    #[serde(rename = "__gridSize")]
+    grid_size: u32,
+    #[serde(rename = "__tilesetDefUid")]
+    tileset_uid: Option<i64>,
+    #[serde(rename = "overrideTilesetUid", default)]
+    override_tileset_uid: Option<i64>,
+    #[serde(rename = "__pxTotalOffsetX", default)]
+    offset_x: i32,
+    #[serde(rename = "__pxTotalOffsetY", default)]
+    offset_y: i32,
+    #[serde(default = "visible")]
+    visible: bool,
+    #[serde(rename = "intGridCsv", default)]
+    int_grid: Vec<u32>,
+    #[serde(rename = "gridTiles", default)]
+    grid_tiles: Vec<TileInstance>,
+    #[serde(rename = "autoLayerTiles", default)]
+    auto_layer_tiles: Vec<TileInstance>,
+    #[serde(rename = "entityInstances", default)]
+    entities: Vec<EntityInstance>,
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct TileInstance {
+    // Position in the layer.
+    px: (i32, i32),
+    // Tile id, from 0.
+    t: u32,
+    // Bit 0 for a horizontal flip, bit 1 for a vertical one.
+    #[serde(default)]
+    f: u32,
+}
+
+#[derive(Deserialize)]
+struct EntityInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__pivot", default)]
+    pivot: (f32, f32),
+    #[serde(rename = "__tags", default)]
+    tags: Vec<String>,
+    #[serde(rename = "__smartColor", default)]
+    color: Option<String>,
+    #[serde(rename = "__tile", default)]
+    tile: Option<TileRect>,
+    px: (i32, i32),
+    width: u32,
+    height: u32,
+    #[serde(rename = "fieldInstances", default)]
+    fields: Vec<FieldInstance>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TileRect {
+    tileset_uid: i64,
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct FieldInstance {
+    #[serde(rename = "__identifier")]
+    identifier: String,
+    #[serde(rename = "__value")]
+    value: serde_json::Value,
+}
+
+fn properties(fields: &[FieldInstance]) -> HashMap<String, Property> {
+    fields.iter().filter_map(|f| {
+        let value = match &f.value {
+            serde_json::Value::Null => return None,
+            serde_json::Value::Bool(b) => Property::Bool(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Property::Int(i),
+                None => Property::Float(n.as_f64().unwrap_or(0.0)),
+            },
+            serde_json::Value::String(s) => Property::String(s.clone()),
+            // Points, arrays and references are kept as json.
+            other => Property::String(other.to_string()),
+        };
+        Some((f.identifier.clone(), value))
+    }).collect()
+}
+
+fn build_project(project: LdtkProject, dir: Option<&Path>) -> Result<Project, ImportError> {
+    let mut tilesets = HashMap::new();
+    for def in &project.defs.tilesets {
+        if let Some(rel_path) = &def.rel_path {
+            let texture = match dir {
+                Some(dir) => dir.join(rel_path).to_string_lossy().into_owned(),
+                None => rel_path.clone(),
+            };
+            let size = def.tile_grid_size;
+            if size == 0 {
+                return Err(ImportError::Format(format!("tileset '{}' has a tile size of 0", rel_path)));
+            }
+            let columns = def.padding.checked_mul(2)
+                .and_then(|border| def.px_wid.checked_sub(border))
+                .map(|inner| inner.saturating_add(def.spacing) / size.saturating_add(def.spacing))
+                .ok_or_else(|| ImportError::Format(format!("tileset '{}' padding is larger than the image", rel_path)))?;
+            let mut tileset = Tileset::new(&texture, size, size, columns);
+            tileset.margin = def.padding;
+            tileset.spacing = def.spacing;
+            tilesets.insert(def.uid, tileset);
+        }
+    }
+
+    let mut levels = Vec::new();
+    for level in project.levels {
+        let level = match &level.external_rel_path {
+            Some(rel_path) if level.layer_instances.is_none() => {
+                let dir = dir.ok_or_else(|| ImportError::Format(
+                    format!("external level '{}' needs the project to be loaded from a file", rel_path)))?;
+                serde_json::from_str(&read_file(&dir.join(rel_path))?)?
+            },
+            _ => level,
+        };
+        levels.push(level);
+    }
+
+    // Linear layouts do not store the positions of the levels.
+    let mut next = 0;
+    for level in &mut levels {
+        match project.world_layout.as_deref() {
+            Some("LinearHorizontal") => {
+                level.world_x = next;
+                level.world_y = 0;
+                next += level.px_wid as i32;
+            },
+            Some("LinearVertical") => {
+                level.world_x = 0;
+                level.world_y = next;
+                next += level.px_hei as i32;
+            },
+            _ => {},
+        }
+    }
+
+    let levels = levels.iter()
+        .map(|level| build_level(level, &tilesets))
+        .collect::<Result<Vec<Level>, ImportError>>()?;
+    Ok(Project { levels })
+}
+
+fn build_level(level: &LdtkLevel, tilesets: &HashMap<i64, Tileset>) -> Result<Level, ImportError> {
+    let mut scene = Scene::new();
+    scene.properties = properties(&level.field_instances);
+
+    // The first layer is the one on top. The z-index keeps the order of the
+    // layers when the scene is drawn.
+    let layers = level.layer_instances.as_deref().unwrap_or(&[]);
+    for (z_index, layer) in layers.iter().rev().enumerate() {
+        let z_index = z_index as i32;
+        let x = level.world_x + layer.offset_x;
+        let y = level.world_y + layer.offset_y;
+
+        if layer.kind == "Entities" {
+            for entity in &layer.entities {
+                let mut go = game_object(entity, x, y, tilesets);
+                go.z_index = z_index;
+                scene.gameobjects.push(go);
+            }
+            continue;
+        }
+
+        let tiles = if layer.grid_tiles.is_empty() { &layer.auto_layer_tiles } else { &layer.grid_tiles };
+        let tileset = layer.override_tileset_uid.or(layer.tileset_uid).and_then(|uid| tilesets.get(&uid));
+
+        let mut tilemap = match tileset {
+            Some(tileset) => {
+                if tileset.tile_w != layer.grid_size {
+                    return Err(ImportError::Format(format!("layer '{}' has {}px cells but {}px tiles",
+                                                           layer.identifier, layer.grid_size, tileset.tile_w)));
+                }
+                Tilemap::new(tileset.clone(), layer.width, layer.height)
+            },
+            // Only used for collisions.
+            None => Tilemap::new(Tileset::new("", layer.grid_size, layer.grid_size, 0), layer.width, layer.height),
+        };
+        tilemap.x = x;
+        tilemap.y = y;
+        tilemap.z_index = z_index;
+
+        if tileset.is_some() {
+            for tile in tiles {
+                place_tile(&mut tilemap, &layer.identifier, layer.visible, tile);
+            }
+        }
+
+        if layer.kind == "IntGrid" && !layer.int_grid.is_empty() {
+            if layer.int_grid.len() != (layer.width * layer.height) as usize {
+                return Err(ImportError::Format(format!("layer '{}' has {} values instead of {}",
+                                                       layer.identifier, layer.int_grid.len(), layer.width * layer.height)));
+            }
+            let idx = tilemap.add_layer(&layer.identifier);
+            let collision = &mut tilemap.layers[idx];
+            collision.tiles = layer.int_grid.clone();
+            collision.visible = false;
+            collision.collision = true;
+        }
+
+        if !tilemap.layers.is_empty() {
+            scene.tilemaps.push(tilemap);
+        }
+    }
+
+    Ok(Level {
+        name: level.identifier.clone(),
+        world_x: level.world_x,
+        world_y: level.world_y,
+        width: level.px_wid,
+        height: level.px_hei,
+        scene,
+    })
+}
+
+// Puts the tile in the first layer where its cell is free.
+fn place_tile(tilemap: &mut Tilemap, name: &str, visible: bool, tile: &TileInstance) {
+    let size = tilemap.tileset.tile_w as i32;
+    let (col, row) = (tile.px.0 / size, tile.px.1 / size);
+    if col < 0 || row < 0 || col >= tilemap.width as i32 || row >= tilemap.height as i32 {
+        return;
+    }
+
+    let mut value = tile.t + 1;
+    if tile.f & 1 != 0 {
+        value |= FLIP_HORIZONTAL;
+    }
+    if tile.f & 2 != 0 {
+        value |= FLIP_VERTICAL;
+    }
+
+    let idx = (row as u32 * tilemap.width + col as u32) as usize;
+    let free = tilemap.layers.iter().position(|l| l.tiles[idx] == 0);
+    let layer = match free {
+        Some(layer) => layer,
+        None => {
+            let layer = tilemap.add_layer(name);
+            tilemap.layers[layer].visible = visible;
+            layer
+        },
+    };
+    tilemap.layers[layer].tiles[idx] = value;
+}
+
+fn game_object(entity: &EntityInstance, layer_x: i32, layer_y: i32, tilesets: &HashMap<i64, Tileset>) -> GameObject {
+    let properties = properties(&entity.fields);
+    let dynamic = entity.tags.iter().any(|t| t == "dynamic")
+        || properties.get("body") == Some(&Property::String("dynamic".to_string()));
+    let body_type = if dynamic { BodyType::Dynamic } else { BodyType::Static };
+
+    let color = entity.color.as_ref()
+        .and_then(|c| u32::from_str_radix(c.trim_start_matches('#'), 16).ok())
+        .map(|c| Color::RGB((c >> 16) as u8, (c >> 8) as u8, c as u8))
+        .unwrap_or(Color::RGB(128, 128, 128));
+
+    // The position is the one of the pivot.
+    let x = layer_x as f32 + entity.px.0 as f32 - entity.pivot.0 * entity.width as f32;
+    let y = layer_y as f32 + entity.px.1 as f32 - entity.pivot.1 * entity.height as f32;
+    let mut go = GameObject::new(x, y, entity.width, entity.height, color.r, color.g, color.b, body_type);
+    go.name = entity.identifier.clone();
+    go.properties = properties;
+
+    if let Some(tile) = &entity.tile {
+        if let Some(tileset) = tilesets.get(&tile.tileset_uid) {
+            let mut sprite = Sprite::new(&tileset.texture);
+            sprite.region = Some(Region::new(tile.x, tile.y, tile.w, tile.h));
+            go.sprite = Some(sprite);
+        }
+    }
+    go
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("ldtk").join(name)
+    }
+
+    #[test]
+    fn load_world() {
+        let project = load(fixture("world.ldtk")).unwrap();
+        assert_eq!(project.levels.len(), 2);
+
+        let start = project.level("Start").unwrap();
+        assert_eq!((start.world_x, start.world_y, start.width, start.height), (0, 0, 64, 32));
+        assert_eq!(start.scene.properties["music"], Property::String("intro.wav".to_string()));
+
+        // Bottom layer first: the IntGrid with its auto tiles, then the tiles.
+        let scene = &start.scene;
+        assert_eq!(scene.tilemaps.len(), 2);
+        let ground = &scene.tilemaps[0];
+        assert_eq!(PathBuf::from(&ground.tileset.texture), fixture("tiles.png"));
+        assert_eq!((ground.tileset.columns, ground.tileset.tile_w), (4, 16));
+        assert_eq!(ground.layers.len(), 2);
+        assert_eq!(ground.layers[0].tiles, vec![0, 0, 0, 0, 5, 6, 6 | FLIP_HORIZONTAL, 5]);
+        let collision = &ground.layers[1];
+        assert!(collision.collision && !collision.visible);
+        assert_eq!(collision.tiles, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(ground.collision_rects().len(), 1);
+
+        // Two tiles in the same cell.
+        let decoration = &scene.tilemaps[1];
+        assert_eq!(decoration.layers.len(), 2);
+        assert!(decoration.z_index > ground.z_index);
+        assert_eq!(decoration.layers[0].tiles[1], 3);
+        assert_eq!(decoration.layers[1].tiles[1], 4 | FLIP_VERTICAL);
+
+        let player = &scene.gameobjects[0];
+        assert_eq!(player.name, "Player");
+        assert!(player.z_index > decoration.z_index);
+        assert_eq!(player.body.body_type, BodyType::Dynamic);
+        // Pivot at the bottom center.
+        assert_eq!((player.x(), player.y(), player.w(), player.h()), (12.0, 2.0, 8, 14));
+        assert_eq!(player.properties["lives"], Property::Int(3));
+        assert_eq!(player.properties["speed"], Property::Float(1.5));
+        assert!(!player.properties.contains_key("target"));
+        assert_eq!(player.sprite.as_ref().unwrap().region, Some(Region::new(0, 16, 16, 16)));
+
+        // The second level is in its own file, on the right of the first.
+        let cave = project.level("Cave").unwrap();
+        assert_eq!((cave.world_x, cave.world_y), (64, 0));
+        assert_eq!(cave.scene.tilemaps[0].x, 64);
+        let chest = &cave.scene.gameobjects[0];
+        assert_eq!(chest.body.body_type, BodyType::Static);
+        assert_eq!((chest.x(), chest.y()), (64.0 + 16.0, 16.0));
+        assert_eq!(chest.properties["loot"], Property::String("[\"gold\",\"key\"]".to_string()));
+
+        let world = project.world_scene();
+        assert_eq!(world.gameobjects.len(), 2);
+        assert_eq!(world.tilemaps.len(), 3);
+    }
+
+    #[test]
+    fn linear_layout() {
+        let json = r#"{
+            "worldLayout": "LinearVertical",
+            "defs": {"tilesets": []},
+            "levels": [
+                {"identifier": "A", "worldX": -1, "worldY": -1, "pxWid": 32, "pxHei": 48, "layerInstances": []},
+                {"identifier": "B", "worldX": -1, "worldY": -1, "pxWid": 32, "pxHei": 16, "layerInstances": []}
+            ]
+        }"#;
+        let project = parse(json).unwrap();
+        assert_eq!((project.levels[0].world_x, project.levels[0].world_y), (0, 0));
+        assert_eq!((project.levels[1].world_x, project.levels[1].world_y), (0, 48));
+    }
+
+    #[test]
+    fn external_level_needs_a_file() {
+        let json = r#"{
+            "defs": {"tilesets": []},
+            "levels": [{"identifier": "A", "pxWid": 32, "pxHei": 32, "layerInstances": null, "externalRelPath": "world/A.ldtkl"}]
+        }"#;
+        match parse(json) {
+            Err(ImportError::Format(e)) => assert!(e.contains("A.ldtkl")),
+            other => panic!("unexpected {:?}", other.map(|p| p.levels.len())),
+        }
+    }
+
+    #[test]
+    fn invalid_tilesets() {
+        let project = |tileset: &str| format!(r#"{{"defs": {{"tilesets": [{}]}}, "levels": []}}"#, tileset);
+        let padding = project(r#"{"uid": 1, "relPath": "a.png", "pxWid": 8, "tileGridSize": 8, "padding": 5}"#);
+        match parse(&padding) {
+            Err(ImportError::Format(e)) => assert!(e.contains("padding")),
+            other => panic!("unexpected {:?}", other.map(|p| p.levels.len())),
+        }
+        let empty = project(r#"{"uid": 1, "relPath": "a.png", "pxWid": 8, "tileGridSize": 0}"#);
+        match parse(&empty) {
+            Err(ImportError::Format(e)) => assert!(e.contains("tile size")),
+            other => panic!("unexpected {:?}", other.map(|p| p.levels.len())),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// Importers for the files produced by the tools of the artists. They all
+// turn the file into the engine's own definitions (atlas, clips...).
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde_derive::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::animation::AnimationClip;
+use super::core::Scene;
+use super::sprite::{Atlas, Region, Trim};
+
+pub mod aseprite;
+pub mod ldtk;
+pub mod texturepacker;
+pub mod tiled;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(String),
+    Json(serde_json::Error),
+    Xml(String),
+    // The file is valid but does not contain what is expected.
+    Format(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "import io error: {}", e),
+            ImportError::Json(e) => write!(f, "invalid json: {}", e),
+            ImportError::Xml(e) => write!(f, "invalid xml: {}", e),
+            ImportError::Format(e) => write!(f, "invalid file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(e: serde_json::Error) -> ImportError {
+        ImportError::Json(e)
+    }
+}
+
+// TexturePacker files have no durations.
+const SHEET_FRAME_DURATION: u32 = 100;
+
+// What a file opened in the editor contains.
+pub enum Import {
+    Scene(Box<Scene>),
+    Sheet(Box<SpriteSheet>),
+}
+
+// Guesses the format from the extension, and from the content for JSON.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Import, ImportError> {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => return tiled::load(path).map(|scene| Import::Scene(Box::new(scene))),
+        // All the levels, at their place in the world.
+        Some("ldtk") => return ldtk::load(path).map(|project| Import::Scene(Box::new(project.world_scene()))),
+        _ => {},
+    }
+
+    // Tiled maps have no frames.
+    let json: serde_json::Value = serde_json::from_str(&read_file(path)?)?;
+    if json.get("frames").is_none() {
+        return tiled::load(path).map(|scene| Import::Scene(Box::new(scene)));
+    }
+
+    let app = json.pointer("/meta/app").and_then(|app| app.as_str()).unwrap_or("");
+    if app.contains("aseprite") || json.pointer("/meta/frameTags").is_some() {
+        aseprite::load(path).map(|sheet| Import::Sheet(Box::new(sheet)))
+    } else {
+        texturepacker::load(path, SHEET_FRAME_DURATION).map(|sheet| Import::Sheet(Box::new(sheet)))
+    }
+}
+
+// A packed sheet: the regions of each frame and the animations using them.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub atlas: Atlas,
+    // Names of the frames, in the order of the file.
+    pub frames: Vec<String>,
+    pub clips: Vec<AnimationClip>,
+}
+
+impl SpriteSheet {
+
+    pub fn clip(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.iter().find(|c| c.name == name)
+    }
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<String, ImportError> {
+    fs::read_to_string(path).map_err(|e| ImportError::Io(format!("{}: {}", path.display(), e)))
+}
+
+// The image path in the files is relative to the file itself.
+pub(crate) fn image_path(file: &Path, image: &str) -> String {
+    match file.parent() {
+        Some(dir) => dir.join(image).to_string_lossy().into_owned(),
+        None => image.to_string(),
+    }
+}
+
+// Aseprite and TexturePacker share the same "json hash" and "json array"
+// layouts for the frames.
+#[derive(Debug, Deserialize)]
+pub(crate) struct RawRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RawSize {
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RawFrame {
+    #[serde(default)]
+    pub filename: Option<String>,
+    pub frame: RawRect,
+    #[serde(default)]
+    pub rotated: bool,
+    #[serde(default)]
+    pub trimmed: bool,
+    pub sprite_source_size: Option<RawRect>,
+    pub source_size: Option<RawSize>,
+    // Aseprite only, in milliseconds.
+    pub duration: Option<u32>,
+    // TexturePacker only. Pivot relative to the source size, 0.5 is the
+    // center, and 9-slice center part.
+    pub pivot: Option<RawPivot>,
+    pub borders: Option<RawRect>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct RawPivot {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum RawFrames {
+    Array(Vec<RawFrame>),
+    Hash(FrameHash),
+}
+
+// The frames of the hash layout in the order of the file, which is the one
+// of the indexes used by the Aseprite tags.
+#[derive(Debug)]
+pub(crate) struct FrameHash(Vec<(String, RawFrame)>);
+
+impl<'de> Deserialize<'de> for FrameHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FrameHash, D::Error> {
+        struct FrameHashVisitor;
+
+        impl<'de> Visitor<'de> for FrameHashVisitor {
+            type Value = FrameHash;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of frames")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FrameHash, A::Error> {
+                let mut frames = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    frames.push(entry);
+                }
+                Ok(FrameHash(frames))
+            }
+        }
+
+        deserializer.deserialize_map(FrameHashVisitor)
+    }
+}
+
+impl RawFrames {
+
+    // Frames with their names, in the order of the file.
+    pub fn into_named(self) -> Result<Vec<(String, RawFrame)>, ImportError> {
+        match self {
+            RawFrames::Array(frames) => frames.into_iter().enumerate().map(|(idx, frame)| {
+                match frame.filename.clone() {
+                    Some(name) => Ok((name, frame)),
+                    None => Err(ImportError::Format(format!("frame {} has no filename", idx))),
+                }
+            }).collect(),
+            RawFrames::Hash(FrameHash(frames)) => Ok(frames),
+        }
+    }
+}
+
+impl RawFrame {
+
+    // Part of the sheet actually occupied by the frame. A rotated frame is
+    // stored rotated clockwise, its size in the file is the unrotated one.
+    pub fn region(&self) -> Region {
+        let f = &self.frame;
+        if self.rotated {
+            Region::new(f.x, f.y, f.h, f.w)
+        } else {
+            Region::new(f.x, f.y, f.w, f.h)
+        }
+    }
+
+    pub fn trim(&self) -> Option<Trim> {
+        if !self.trimmed && !self.rotated {
+            return None;
+        }
+
+        let (x, y) = self.sprite_source_size.as_ref().map(|s| (s.x, s.y)).unwrap_or((0, 0));
+        let (source_w, source_h) = self.source_size.as_ref()
+            .map(|s| (s.w, s.h))
+            .unwrap_or((self.frame.w, self.frame.h));
+        Some(Trim { x, y, source_w, source_h, rotated: self.rotated })
+    }
+}
+
+// Puts the frames in a new atlas, returns it with the names in order.
+pub(crate) fn build_atlas(image: &str, frames: &[(String, RawFrame)]) -> (Atlas, Vec<String>) {
+    let mut atlas = Atlas::new(image);
+    let mut names = Vec::with_capacity(frames.len());
+    for (name, frame) in frames {
+        atlas.regions.insert(name.clone(), frame.region());
+        if let Some(trim) = frame.trim() {
+            atlas.trims.insert(name.clone(), trim);
+        }
+        names.push(name.clone());
+    }
+    (atlas, names)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join(name)
+    }
+
+    #[test]
+    fn guess_format() {
+        match load(fixture("aseprite_hash.json")) {
+            Ok(Import::Sheet(sheet)) => assert!(sheet.clip("spin").is_some()),
+            _ => panic!("aseprite sheet not loaded"),
+        }
+        match load(fixture("texturepacker_array.json")) {
+            Ok(Import::Sheet(sheet)) => assert!(sheet.atlas.texture.ends_with("gems.png")),
+            _ => panic!("texturepacker sheet not loaded"),
+        }
+        match load(fixture("tiled/level.json")) {
+            Ok(Import::Scene(scene)) => assert!(!scene.tilemaps.is_empty()),
+            _ => panic!("tiled map not loaded"),
+        }
+        match load(fixture("tiled/level.tmx")) {
+            Ok(Import::Scene(scene)) => assert!(!scene.tilemaps.is_empty()),
+            _ => panic!("tiled map not loaded"),
+        }
+        match load(fixture("ldtk/world.ldtk")) {
+            Ok(Import::Scene(scene)) => assert!(!scene.gameobjects.is_empty()),
+            _ => panic!("ldtk project not loaded"),
+        }
+    }
+
+    #[test]
+    fn hash_keeps_file_order() {
+        let json = r#"{
+            "walk 10": {"frame": {"x": 0, "y": 0, "w": 4, "h": 4}},
+            "walk 2": {"frame": {"x": 4, "y": 0, "w": 4, "h": 4}},
+            "idle": {"frame": {"x": 8, "y": 0, "w": 4, "h": 4}}
+        }"#;
+        let frames: RawFrames = serde_json::from_str(json).unwrap();
+        let names: Vec<String> = frames.into_named().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["walk 10", "walk 2", "idle"]);
+    }
+}
@@ -0,0 +1,127 @@
+// TexturePacker JSON (hash or array). The optional "animations" object, as
+// written by the Phaser and PixiJS exporters, lists the frames of each
+// animation. The format has no durations so they all use the given one.
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::{build_atlas, image_path, read_file, ImportError, RawFrames, SpriteSheet};
+use crate::animation::{AnimationClip, PlayMode};
+use crate::sprite::{Region, Slice};
+
+#[derive(Debug, Deserialize)]
+struct File {
+    frames: RawFrames,
+    meta: Meta,
+    #[serde(default)]
+    animations: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    #[serde(default)]
+    image: String,
+}
+
+pub fn parse(json: &str, frame_duration: u32) -> Result<SpriteSheet, ImportError> {
+    let file: File = serde_json::from_str(json)?;
+    let frames = file.frames.into_named()?;
+    let (mut atlas, names) = build_atlas(&file.meta.image, &frames);
+
+    // Pivots and 9-slice borders are stored as a slice named after the frame.
+    for (name, frame) in &frames {
+        if frame.pivot.is_none() && frame.borders.is_none() {
+            continue;
+        }
+
+        let (w, h) = frame.source_size.as_ref()
+            .map(|s| (s.w, s.h))
+            .unwrap_or((frame.frame.w, frame.frame.h));
+        atlas.slices.insert(name.clone(), Slice {
+            bounds: Region::new(0, 0, w, h),
+            center: frame.borders.as_ref().map(|b| Region::new(b.x, b.y, b.w, b.h)),
+            pivot: frame.pivot.as_ref().map(|p| ((p.x * w as f32).round() as i32, (p.y * h as f32).round() as i32)),
+        });
+    }
+
+    let mut clips = Vec::new();
+    for (name, frame_names) in &file.animations {
+        if let Some(missing) = frame_names.iter().find(|f| atlas.region(f).is_none()) {
+            return Err(ImportError::Format(format!("animation '{}' uses unknown frame '{}'", name, missing)));
+        }
+        let frame_names: Vec<&str> = frame_names.iter().map(|f| f.as_str()).collect();
+        clips.push(AnimationClip::from_atlas(name, &atlas, &frame_names, frame_duration, PlayMode::Loop));
+    }
+
+    Ok(SpriteSheet { atlas, frames: names, clips })
+}
+
+pub fn load<P: AsRef<Path>>(path: P, frame_duration: u32) -> Result<SpriteSheet, ImportError> {
+    let path = path.as_ref();
+    let mut sheet = parse(&read_file(path)?, frame_duration)?;
+    sheet.atlas.texture = image_path(path, &sheet.atlas.texture);
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sprite::Trim;
+    use std::path::PathBuf;
+
+    #[test]
+    fn import_hash() {
+        let sheet = parse(include_str!("../../fixtures/texturepacker_hash.json"), 80).unwrap();
+        assert_eq!(sheet.atlas.texture, "robot.png");
+        // In the order of the file.
+        assert_eq!(sheet.frames, vec!["robot_idle.png", "robot_run_1.png", "robot_run_10.png", "robot_run_2.png"]);
+
+        // Rotated: 20x30 in the original image, 30x20 in the sheet.
+        assert_eq!(sheet.atlas.region("robot_run_2.png"), Some(Region::new(40, 0, 30, 20)));
+        assert_eq!(sheet.atlas.trim("robot_run_2.png"), Some(Trim { x: 6, y: 1, source_w: 32, source_h: 32, rotated: true }));
+
+        // Trimmed only.
+        assert_eq!(sheet.atlas.region("robot_run_1.png"), Some(Region::new(32, 0, 8, 30)));
+        assert_eq!(sheet.atlas.trim("robot_run_1.png").map(|t| t.rotated), Some(false));
+        assert_eq!(sheet.atlas.trim("robot_idle.png"), None);
+
+        let run = sheet.clip("run").unwrap();
+        assert_eq!(run.frames.len(), 3);
+        assert_eq!(run.total_duration(), 240);
+        assert_eq!(run.frames[1].trim.map(|t| t.rotated), Some(true));
+
+        let idle = &sheet.atlas.slices["robot_idle.png"];
+        assert_eq!(idle.pivot, Some((16, 32)));
+        assert_eq!(idle.center, Some(Region::new(4, 4, 24, 24)));
+    }
+
+    #[test]
+    fn import_array() {
+        let sheet = parse(include_str!("../../fixtures/texturepacker_array.json"), 100).unwrap();
+        assert_eq!(sheet.frames, vec!["gem_b.png", "gem_a.png"]);
+        assert_eq!(sheet.atlas.region("gem_a.png"), Some(Region::new(0, 0, 12, 12)));
+        assert!(sheet.clips.is_empty());
+        assert!(sheet.atlas.slices.is_empty());
+    }
+
+    #[test]
+    fn image_relative_to_file() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("texturepacker_array.json");
+        let sheet = load(&path, 100).unwrap();
+        assert_eq!(PathBuf::from(sheet.atlas.texture), path.parent().unwrap().join("gems.png"));
+    }
+
+    #[test]
+    fn unknown_animation_frame() {
+        let json = r#"{
+            "frames": {"a.png": {"frame": {"x": 0, "y": 0, "w": 4, "h": 4}}},
+            "animations": {"run": ["a.png", "b.png"]},
+            "meta": {"image": "a.png"}
+        }"#;
+        match parse(json, 100) {
+            Err(ImportError::Format(e)) => assert!(e.contains("b.png")),
+            other => panic!("unexpected {:?}", other.map(|s| s.frames)),
+        }
+    }
+}
@@ -0,0 +1,721 @@
+// Tiled maps, JSON (.json, .tmj) or XML (.tmx), orthogonal and not infinite.
+//
+// Tile layers become tilemaps. A tilemap only has one tileset so there is one
+// tilemap per tileset used by each layer. Tilemaps and objects get the index
+// of their layer as z-index, the scene is drawn in the order of the file.
+// Objects of the object layers become game objects: static unless their type
+// (or class) or their "body" property is "dynamic". A "color" property gives
+// the color of the rectangle, tile objects get a sprite. A tile layer with a
+// "collision" property set to true is solid.
+use flate2::read::{GzDecoder, ZlibDecoder};
+use sdl2::pixels::Color;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use xml::reader::{EventReader, XmlEvent};
+
+use super::{read_file, ImportError};
+use crate::core::{GameObject, Property, Scene};
+use crate::physics::BodyType;
+use crate::sprite::Sprite;
+use crate::tilemap::{self, Tilemap, Tileset, TileLayer, FLIP_HORIZONTAL, FLIP_VERTICAL};
+
+// Color of the objects without a color property.
+const DEFAULT_COLOR: Color = Color { r: 128, g: 128, b: 128, a: 255 };
+
+// What both formats are read into before building the scene.
+struct Map {
+    width: u32,
+    height: u32,
+    tilesets: Vec<TilesetRef>,
+    layers: Vec<Layer>,
+    properties: HashMap<String, Property>,
+}
+
+struct TilesetRef {
+    first_gid: u32,
+    tileset: Tileset,
+}
+
+enum Layer {
+    Tiles { name: String, visible: bool, gids: Vec<u32>, properties: HashMap<String, Property> },
+    Objects { objects: Vec<Object> },
+}
+
+struct Object {
+    name: String,
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    gid: Option<u32>,
+    properties: HashMap<String, Property>,
+}
+
+pub fn parse_json(data: &str) -> Result<Scene, ImportError> {
+    build_scene(json_map(data, None)?)
+}
+
+pub fn parse_tmx(data: &str) -> Result<Scene, ImportError> {
+    build_scene(tmx_map(data, None)?)
+}
+
+// Also loads the external tilesets. The images are relative to the file
+// referencing them.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, ImportError> {
+    let path = path.as_ref();
+    let data = read_file(path)?;
+    let dir = path.parent();
+    let map = match path.extension().and_then(|e| e.to_str()) {
+        Some("tmx") => tmx_map(&data, dir)?,
+        _ => json_map(&data, dir)?,
+    };
+    build_scene(map)
+}
+
+fn build_scene(map: Map) -> Result<Scene, ImportError> {
+    let Map { width, height, mut tilesets, layers, properties } = map;
+    let mut scene = Scene::new();
+    scene.properties = properties;
+
+    tilesets.sort_by_key(|t| t.first_gid);
+    // A gid belongs to the last tileset starting before it.
+    let find_tileset = |gid: u32| {
+        let id = tilemap::tile_id(gid);
+        tilesets.iter().enumerate().rev().find(|(_, t)| t.first_gid <= id)
+    };
+
+    for (z_index, layer) in layers.into_iter().enumerate() {
+        let z_index = z_index as i32;
+        match layer {
+            Layer::Tiles { name, visible, gids, properties } => {
+                if gids.len() != (width * height) as usize {
+                    return Err(ImportError::Format(format!("layer '{}' has {} tiles instead of {}",
+                                                           name, gids.len(), width * height)));
+                }
+
+                let mut layers: Vec<Option<TileLayer>> = vec![None; tilesets.len()];
+                for (idx, &gid) in gids.iter().enumerate() {
+                    if tilemap::tile_id(gid) == 0 {
+                        continue;
+                    }
+                    let (t, tileset) = find_tileset(gid)
+                        .ok_or_else(|| ImportError::Format(format!("no tileset for tile {}", tilemap::tile_id(gid))))?;
+                    let layer = layers[t].get_or_insert_with(|| TileLayer {
+                        name: name.clone(),
+                        tiles: vec![0; gids.len()],
+                        visible,
+                        collision: properties.get("collision") == Some(&Property::Bool(true)),
+                        properties: properties.clone(),
+                    });
+                    // Ids in the tilemap are relative to the tileset, flags are kept.
+                    layer.tiles[idx] = gid - tileset.first_gid + 1;
+                }
+
+                for (t, layer) in layers.into_iter().enumerate() {
+                    if let Some(layer) = layer {
+                        let mut tilemap = Tilemap::new(tilesets[t].tileset.clone(), width, height);
+                        tilemap.layers.push(layer);
+                        tilemap.z_index = z_index;
+                        scene.tilemaps.push(tilemap);
+                    }
+                }
+            },
+            Layer::Objects { objects } => {
+                for object in objects {
+                    let mut go = game_object(&object);
+                    go.z_index = z_index;
+                    if let Some(gid) = object.gid {
+                        let (_, tileset) = find_tileset(gid)
+                            .ok_or_else(|| ImportError::Format(format!("no tileset for tile {} of object '{}'",
+                                                                       tilemap::tile_id(gid), object.name)))?;
+                        go.sprite = Some(tile_sprite(tileset, gid));
+                    }
+                    scene.gameobjects.push(go);
+                }
+            },
+        }
+    }
+
+    Ok(scene)
+}
+
+fn game_object(object: &Object) -> GameObject {
+    let body = match object.properties.get("body") {
+        Some(Property::String(body)) => body.as_str(),
+        _ => object.kind.as_str(),
+    };
+    let body_type = if body == "dynamic" { BodyType::Dynamic } else { BodyType::Static };
+
+    let color = match object.properties.get("color") {
+        Some(Property::String(color)) => parse_color(color).unwrap_or(DEFAULT_COLOR),
+        _ => DEFAULT_COLOR,
+    };
+
+    // The position of tile objects is their bottom left corner.
+    let y = if object.gid.is_some() { object.y - object.height } else { object.y };
+    let mut go = GameObject::new(object.x, y,
+                                 object.width.round() as u32, object.height.round() as u32,
+                                 color.r, color.g, color.b, body_type);
+    go.name = object.name.clone();
+    go.properties = object.properties.clone();
+    go
+}
+
+fn tile_sprite(tileset: &TilesetRef, gid: u32) -> Sprite {
+    let id = tilemap::tile_id(gid) - tileset.first_gid + 1;
+    let mut sprite = Sprite::new(&tileset.tileset.texture);
+    sprite.region = tileset.tileset.region(id);
+    sprite.flip_horizontal = gid & FLIP_HORIZONTAL != 0;
+    sprite.flip_vertical = gid & FLIP_VERTICAL != 0;
+    sprite
+}
+
+// "#RRGGBB" or "#AARRGGBB", the alpha is ignored.
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.trim_start_matches('#');
+    let hex = match hex.len() {
+        6 => hex,
+        8 => &hex[2..],
+        _ => return None,
+    };
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::RGB((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+fn property(kind: &str, value: &str) -> Property {
+    match kind {
+        "bool" => Property::Bool(value == "true"),
+        "int" | "object" => value.parse().map(Property::Int).unwrap_or_else(|_| Property::String(value.to_string())),
+        "float" => value.parse().map(Property::Float).unwrap_or_else(|_| Property::String(value.to_string())),
+        _ => Property::String(value.to_string()),
+    }
+}
+
+// Tile data of a layer. csv is the default of Tiled.
+fn decode_tiles(data: &str, encoding: Option<&str>, compression: Option<&str>) -> Result<Vec<u32>, ImportError> {
+    match encoding {
+        None | Some("csv") => data.split(',')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.parse().map_err(|_| ImportError::Format(format!("invalid tile '{}'", v))))
+            .collect(),
+        Some("base64") => {
+            let bytes = base64::decode(data.trim())
+                .map_err(|e| ImportError::Format(format!("invalid base64 tiles: {}", e)))?;
+            let mut raw = Vec::new();
+            let read = match compression {
+                None | Some("") => {
+                    raw = bytes;
+                    Ok(0)
+                },
+                Some("zlib") => ZlibDecoder::new(&bytes[..]).read_to_end(&mut raw),
+                Some("gzip") => GzDecoder::new(&bytes[..]).read_to_end(&mut raw),
+                Some(other) => return Err(ImportError::Format(format!("unsupported compression '{}'", other))),
+            };
+            read.map_err(|e| ImportError::Format(format!("cannot decompress tiles: {}", e)))?;
+            if raw.len() % 4 != 0 {
+                return Err(ImportError::Format(format!("{} bytes of tiles, not a multiple of 4", raw.len())));
+            }
+            Ok(raw.chunks_exact(4)
+                .map(|c| u32::from(c[0]) | u32::from(c[1]) << 8 | u32::from(c[2]) << 16 | u32::from(c[3]) << 24)
+                .collect())
+        },
+        Some(other) => Err(ImportError::Format(format!("unsupported encoding '{}'", other))),
+    }
+}
+
+fn external_tileset(dir: Option<&Path>, source: &str) -> Result<Tileset, ImportError> {
+    let dir = dir.ok_or_else(|| ImportError::Format(format!("external tileset '{}' needs the map to be loaded from a file", source)))?;
+    let path = dir.join(source);
+    let data = read_file(&path)?;
+    if source.ends_with(".tsx") {
+        tmx_tileset(&parse_xml(&data)?, path.parent())
+    } else {
+        let tileset: JsonTileset = serde_json::from_str(&data)?;
+        json_tileset(&tileset, path.parent())
+    }
+}
+
+fn image_path(dir: Option<&Path>, image: &str) -> String {
+    match dir {
+        Some(dir) => dir.join(image).to_string_lossy().into_owned(),
+        None => image.to_string(),
+    }
+}
+
+fn check_orthogonal(orientation: &str, infinite: bool) -> Result<(), ImportError> {
+    if orientation != "orthogonal" {
+        return Err(ImportError::Format(format!("{} maps are not supported", orientation)));
+    }
+    if infinite {
+        return Err(ImportError::Format("infinite maps are not supported".to_string()));
+    }
+    Ok(())
+}
+
+//
+// JSON
+//
+
+fn visible() -> bool {
+    true
+}
+
+fn orthogonal() -> String {
+    "orthogonal".to_string()
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    #[serde(default = "orthogonal")]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "visible")]
+    visible: bool,
+    // Array of gids or string, depending on the encoding.
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    // Of a group layer.
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    source: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    #[serde(default)]
+    name: String,
+    // "class" since Tiled 1.9.
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    gid: Option<u32>,
+    #[serde(default)]
+    point: bool,
+    polygon: Option<serde_json::Value>,
+    polyline: Option<serde_json::Value>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+fn json_properties(properties: &[JsonProperty]) -> HashMap<String, Property> {
+    properties.iter().map(|p| {
+        let value = match &p.value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        (p.name.clone(), property(&p.kind, &value))
+    }).collect()
+}
+
+fn json_tileset(tileset: &JsonTileset, dir: Option<&Path>) -> Result<Tileset, ImportError> {
+    let image = tileset.image.as_ref()
+        .ok_or_else(|| ImportError::Format("tilesets made of a collection of images are not supported".to_string()))?;
+    let mut result = Tileset::new(&image_path(dir, image), tileset.tilewidth, tileset.tileheight, tileset.columns);
+    result.margin = tileset.margin;
+    result.spacing = tileset.spacing;
+    Ok(result)
+}
+
+fn json_layers(layers: &[JsonLayer], parent_visible: bool, out: &mut Vec<Layer>) -> Result<(), ImportError> {
+    for layer in layers {
+        let visible = parent_visible && layer.visible;
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let gids = match &layer.data {
+                    Some(serde_json::Value::Array(values)) => values.iter()
+                        .map(|v| v.as_u64().map(|v| v as u32).ok_or_else(|| ImportError::Format(format!("invalid tile {}", v))))
+                        .collect::<Result<Vec<u32>, ImportError>>()?,
+                    Some(serde_json::Value::String(data)) =>
+                        decode_tiles(data, layer.encoding.as_deref(), layer.compression.as_deref())?,
+                    _ => return Err(ImportError::Format(format!("layer '{}' has no data", layer.name))),
+                };
+                out.push(Layer::Tiles { name: layer.name.clone(), visible, gids, properties: json_properties(&layer.properties) });
+            },
+            "objectgroup" => {
+                let objects = layer.objects.iter()
+                    .filter(|o| !o.point && o.polygon.is_none() && o.polyline.is_none())
+                    .map(|o| Object {
+                        name: o.name.clone(),
+                        kind: if o.kind.is_empty() { o.class.clone() } else { o.kind.clone() },
+                        x: o.x,
+                        y: o.y,
+                        width: o.width,
+                        height: o.height,
+                        gid: o.gid,
+                        properties: json_properties(&o.properties),
+                    })
+                    .collect();
+                out.push(Layer::Objects { objects });
+            },
+            "group" => json_layers(&layer.layers, visible, out)?,
+            // Image layers.
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+fn json_map(data: &str, dir: Option<&Path>) -> Result<Map, ImportError> {
+    let map: JsonMap = serde_json::from_str(data)?;
+    check_orthogonal(&map.orientation, map.infinite)?;
+
+    let mut tilesets = Vec::new();
+    for tileset in &map.tilesets {
+        let tileset_def = match &tileset.source {
+            Some(source) => external_tileset(dir, source)?,
+            None => json_tileset(tileset, dir)?,
+        };
+        tilesets.push(TilesetRef { first_gid: tileset.firstgid, tileset: tileset_def });
+    }
+
+    let mut layers = Vec::new();
+    json_layers(&map.layers, true, &mut layers)?;
+
+    Ok(Map {
+        width: map.width,
+        height: map.height,
+        tilesets,
+        layers,
+        properties: json_properties(&map.properties),
+    })
+}
+
+//
+// TMX
+//
+
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|a| a.as_str())
+    }
+
+    fn parse_attr<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, ImportError> {
+        match self.attr(name) {
+            Some(value) => value.parse().map_err(|_| ImportError::Format(
+                format!("invalid {} '{}' in <{}>", name, value, self.name))),
+            None => Ok(default),
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+}
+
+fn parse_xml(data: &str) -> Result<Element, ImportError> {
+    let mut stack: Vec<Element> = Vec::new();
+    for event in EventReader::new(data.as_bytes()) {
+        match event.map_err(|e| ImportError::Xml(e.to_string()))? {
+            XmlEvent::StartElement { name, attributes, .. } => stack.push(Element {
+                name: name.local_name,
+                attributes: attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+                children: Vec::new(),
+                text: String::new(),
+            }),
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().expect("unbalanced xml");
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            },
+            _ => {},
+        }
+    }
+    Err(ImportError::Xml("no root element".to_string()))
+}
+
+fn tmx_properties(element: &Element) -> HashMap<String, Property> {
+    let properties = match element.child("properties") {
+        Some(properties) => properties,
+        None => return HashMap::new(),
+    };
+    properties.children.iter().filter(|p| p.name == "property").map(|p| {
+        // Multiline strings are in the text of the element.
+        let value = p.attr("value").unwrap_or(&p.text);
+        (p.attr("name").unwrap_or("").to_string(), property(p.attr("type").unwrap_or("string"), value))
+    }).collect()
+}
+
+fn tmx_tileset(element: &Element, dir: Option<&Path>) -> Result<Tileset, ImportError> {
+    let image = element.child("image").and_then(|i| i.attr("source"))
+        .ok_or_else(|| ImportError::Format("tilesets made of a collection of images are not supported".to_string()))?;
+    let mut tileset = Tileset::new(&image_path(dir, image),
+                                   element.parse_attr("tilewidth", 0)?,
+                                   element.parse_attr("tileheight", 0)?,
+                                   element.parse_attr("columns", 0)?);
+    tileset.margin = element.parse_attr("margin", 0)?;
+    tileset.spacing = element.parse_attr("spacing", 0)?;
+    Ok(tileset)
+}
+
+fn tmx_layers(element: &Element, parent_visible: bool, out: &mut Vec<Layer>) -> Result<(), ImportError> {
+    for child in &element.children {
+        let visible = parent_visible && child.attr("visible") != Some("0");
+        let name = child.attr("name").unwrap_or("").to_string();
+        match child.name.as_str() {
+            "layer" => {
+                let data = child.child("data")
+                    .ok_or_else(|| ImportError::Format(format!("layer '{}' has no data", name)))?;
+                let gids = match data.attr("encoding") {
+                    // One <tile gid=".."/> element per tile.
+                    None => data.children.iter()
+                        .filter(|t| t.name == "tile")
+                        .map(|t| t.parse_attr("gid", 0))
+                        .collect::<Result<Vec<u32>, ImportError>>()?,
+                    encoding => decode_tiles(&data.text, encoding, data.attr("compression"))?,
+                };
+                out.push(Layer::Tiles { name, visible, gids, properties: tmx_properties(child) });
+            },
+            "objectgroup" => {
+                let mut objects = Vec::new();
+                for object in child.children.iter().filter(|o| o.name == "object") {
+                    if ["point", "polygon", "polyline"].iter().any(|shape| object.child(shape).is_some()) {
+                        continue;
+                    }
+                    objects.push(Object {
+                        name: object.attr("name").unwrap_or("").to_string(),
+                        kind: object.attr("type").or_else(|| object.attr("class")).unwrap_or("").to_string(),
+                        x: object.parse_attr("x", 0.0)?,
+                        y: object.parse_attr("y", 0.0)?,
+                        width: object.parse_attr("width", 0.0)?,
+                        height: object.parse_attr("height", 0.0)?,
+                        gid: match object.attr("gid") {
+                            Some(_) => Some(object.parse_attr("gid", 0)?),
+                            None => None,
+                        },
+                        properties: tmx_properties(object),
+                    });
+                }
+                out.push(Layer::Objects { objects });
+            },
+            "group" => tmx_layers(child, visible, out)?,
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+fn tmx_map(data: &str, dir: Option<&Path>) -> Result<Map, ImportError> {
+    let root = parse_xml(data)?;
+    if root.name != "map" {
+        return Err(ImportError::Format(format!("expected <map>, found <{}>", root.name)));
+    }
+    check_orthogonal(root.attr("orientation").unwrap_or("orthogonal"), root.attr("infinite") == Some("1"))?;
+
+    let mut tilesets = Vec::new();
+    for element in root.children.iter().filter(|c| c.name == "tileset") {
+        let tileset = match element.attr("source") {
+            Some(source) => external_tileset(dir, source)?,
+            None => tmx_tileset(element, dir)?,
+        };
+        tilesets.push(TilesetRef { first_gid: element.parse_attr("firstgid", 1)?, tileset });
+    }
+
+    let mut layers = Vec::new();
+    tmx_layers(&root, true, &mut layers)?;
+
+    Ok(Map {
+        width: root.parse_attr("width", 0)?,
+        height: root.parse_attr("height", 0)?,
+        tilesets,
+        layers,
+        properties: tmx_properties(&root),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sprite::Region;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures").join("tiled").join(name)
+    }
+
+    // Both fixtures describe the same map.
+    fn check_level(scene: &Scene) {
+        assert_eq!(scene.properties["gravity"], Property::Float(9.5));
+        assert_eq!(scene.properties["music"], Property::String("cave.wav".to_string()));
+
+        // One tilemap per layer and tileset, in the order of the layers.
+        assert_eq!(scene.tilemaps.len(), 3);
+        let ground = &scene.tilemaps[0];
+        assert_eq!((ground.width, ground.height), (4, 3));
+        assert_eq!((ground.tileset.tile_w, ground.tileset.columns), (16, 8));
+        assert_eq!(ground.layers.len(), 1);
+        assert_eq!(ground.layers[0].name, "ground");
+        assert!(ground.layers[0].collision);
+        assert_eq!(ground.layers[0].tiles, vec![0, 0, 0, 0,
+                                                0, 0, 0, 3,
+                                                1, 2, 2 | FLIP_HORIZONTAL, 1]);
+
+        // The group is hidden.
+        let decoration = &scene.tilemaps[1];
+        assert_eq!(decoration.layers[0].name, "decoration");
+        assert!(!decoration.layers[0].visible);
+        assert!(!decoration.layers[0].collision);
+        assert!(decoration.z_index > ground.z_index);
+
+        // Ids are relative to the second tileset.
+        let items = &scene.tilemaps[2];
+        assert_eq!(items.layers[0].name, "decoration");
+        assert_eq!(items.layers[0].tiles[4], 2);
+        assert_eq!(items.z_index, decoration.z_index);
+
+        assert_eq!(scene.gameobjects.len(), 3);
+        let player = &scene.gameobjects[0];
+        assert_eq!(player.name, "player");
+        assert!(player.z_index > decoration.z_index);
+        assert_eq!(player.body.body_type, BodyType::Dynamic);
+        assert_eq!((player.x(), player.y(), player.w(), player.h()), (8.0, 4.0, 12, 14));
+        assert_eq!(player.properties["health"], Property::Int(3));
+
+        let wall = &scene.gameobjects[1];
+        assert_eq!(wall.body.body_type, BodyType::Static);
+        assert_eq!(wall.properties["breakable"], Property::Bool(true));
+
+        // Tile object, positioned by its bottom left corner.
+        let chest = &scene.gameobjects[2];
+        assert_eq!((chest.x(), chest.y()), (32.0, 16.0));
+        let sprite = chest.sprite.as_ref().unwrap();
+        assert_eq!(sprite.region, Some(Region::new(0, 0, 16, 16)));
+        assert!(sprite.texture.ends_with("items.png"));
+    }
+
+    #[test]
+    fn load_json() {
+        let scene = load(fixture("level.json")).unwrap();
+        check_level(&scene);
+        assert_eq!(PathBuf::from(&scene.tilemaps[0].tileset.texture), fixture("terrain.png"));
+    }
+
+    #[test]
+    fn load_tmx() {
+        let scene = load(fixture("level.tmx")).unwrap();
+        check_level(&scene);
+        assert_eq!(PathBuf::from(&scene.tilemaps[0].tileset.texture), fixture("terrain.png"));
+    }
+
+    #[test]
+    fn tile_encodings() {
+        let expected = vec![1, 2, 0, 0x8000_0003];
+        assert_eq!(decode_tiles("1,2,\n0,2147483651", Some("csv"), None).unwrap(), expected);
+        // Little endian u32, base64 and zlib.
+        assert_eq!(decode_tiles("AQAAAAIAAAAAAAAAAwAAgA==", Some("base64"), None).unwrap(), expected);
+        assert_eq!(decode_tiles("eJxjZGBgYGKAAGYGhgYAAMQAhw==", Some("base64"), Some("zlib")).unwrap(), expected);
+
+        match decode_tiles("AAAA", Some("base64"), Some("zstd")) {
+            Err(ImportError::Format(_)) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+        // Truncated: 6 bytes.
+        match decode_tiles("AQAAAAIA", Some("base64"), None) {
+            Err(ImportError::Format(_)) => {},
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn external_tileset_needs_a_file() {
+        let data = read_file(&fixture("level.tmx")).unwrap();
+        match parse_tmx(&data) {
+            Err(ImportError::Format(e)) => assert!(e.contains("terrain.tsx")),
+            other => panic!("unexpected {:?}", other.map(|s| s.gameobjects.len())),
+        }
+    }
+
+    #[test]
+    fn tile_object_without_tileset() {
+        let json = r#"{"width": 1, "height": 1, "orientation": "orthogonal", "tilesets": [],
+            "layers": [{"type": "objectgroup", "name": "things",
+                        "objects": [{"name": "chest", "gid": 5, "x": 0, "y": 16, "width": 16, "height": 16}]}]}"#;
+        match parse_json(json) {
+            Err(ImportError::Format(e)) => assert!(e.contains("chest")),
+            other => panic!("unexpected {:?}", other.map(|s| s.gameobjects.len())),
+        }
+    }
+
+    #[test]
+    fn unsupported_maps() {
+        let json = r#"{"width": 1, "height": 1, "orientation": "isometric"}"#;
+        assert!(parse_json(json).is_err());
+        let tmx = r#"<map orientation="orthogonal" width="1" height="1" infinite="1"></map>"#;
+        assert!(parse_tmx(tmx).is_err());
+        assert_eq!(parse_color("#ff102030"), Some(Color::RGB(0x10, 0x20, 0x30)));
+    }
+}
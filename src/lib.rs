@@ -1,18 +1,47 @@
 use std::time::{Duration, Instant};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use sdl2::pixels::Color;
 
+#[macro_use]
+mod math;
+mod animation;
+mod assets;
+mod camera;
 mod context;
 mod core;
+mod debug_draw;
 mod editor;
+mod fixed;
 mod game;
-mod math;
+mod glyphs;
+pub mod import;
+mod lighting;
+mod noise;
+mod parallax;
+mod particles;
 mod physics;
+mod pipeline;
+mod random;
+mod resolution;
+mod shapes;
+#[cfg(test)]
+mod snapshot;
+mod spatial;
+mod sprite;
+mod tilemap;
+mod tween;
 use std::env;
 
 use self::context::Context;
-use self::core::TextureCache;
+pub use self::debug_draw::DebugDraw;
+use self::assets::AssetManager;
 use self::editor::{EditorAction, Editor};
 use self::game::{GameAction, Game};
+use self::pipeline::Pipeline;
+pub use self::pipeline::ScaleMode;
+use self::resolution::Resolution;
+pub use self::math::Vector2d;
+pub use self::tween::{ease, lerp, inverse_lerp, cubic_bezier, catmull_rom, ArcLengthTable, Curve, Easing, Interpolate, Spline, Tween};
 
 #[derive(PartialEq)]
 enum LoopMode {
@@ -21,18 +50,44 @@ enum LoopMode {
 }
 
 pub fn run(font_path: &Path) {
+    run_with_resolution(font_path, 800, 600, ScaleMode::Fit);
+}
+
+// Opens the maps and sprite sheets in the editor, see Editor::open.
+pub fn run_with_files(font_path: &Path, files: &[PathBuf]) {
+    start(font_path, 800, 600, ScaleMode::Fit, files);
+}
+
+// The game is drawn at width x height logical pixels, scaled to the window
+// with the given mode. The window starts at that size.
+pub fn run_with_resolution(font_path: &Path, width: u32, height: u32, mode: ScaleMode) {
+    start(font_path, width, height, mode, &[]);
+}
+
+fn start(font_path: &Path, width: u32, height: u32, mode: ScaleMode, files: &[PathBuf]) {
 
     let mut loop_mode = LoopMode::EDITOR;
-    let mut context = Context::new().unwrap();
+    let mut context = Context::new(width, height).unwrap();
     let texture_creator = context.canvas.texture_creator();
 
-    let font = context.font_context.load_font(font_path, 24).unwrap();
-    let mut textures = TextureCache::new(&texture_creator, font);
+    let mut assets = AssetManager::new(".", &texture_creator, &context.font_context);
+    let font = assets.font(&font_path.to_string_lossy(), 24);
+    if let Err(e) = assets.set_text_fonts(font, Vec::new()) {
+        println!("{}", e);
+        return;
+    }
     let mut events = context.sdl_context.event_pump().unwrap();
 
 
     let mut editor = Editor::new();
+    editor.resolution = Resolution::new(width, height, ScaleMode::Expand);
+    for file in files {
+        if let Err(e) = editor.open(file) {
+            println!("Could not open {}: {}", file.display(), e);
+        }
+    }
     let mut game = Game::new();
+    game.resolution = Resolution::new(width, height, mode);
 
     let fixed_time_stamp = Duration::new(0, 16666667); 
     let mut previous_clock = Instant::now();
@@ -45,14 +100,23 @@ pub fn run(font_path: &Path) {
                 Some(EditorAction::QUIT) => break 'running,
                 Some(EditorAction::RUN) => {
                     loop_mode = LoopMode::GAME; 
+                    // Each mode only sees the resize events while it runs.
+                    let (w, h) = editor.resolution.window_size();
+                    game.resolution.resize(w, h);
                     game.set_scene(&editor.current_scene);
+                    assets.change_scene(&editor.current_scene);
+                    game.set_pipeline(editor.current_scene.pipeline.clone().and_then(|config| {
+                        Pipeline::new(config, &texture_creator)
+                            .map_err(|e| println!("Could not create the render pipeline: {}", e))
+                            .ok()
+                    }));
                     previous_clock = Instant::now();
                     continue
                 },
                 _ => {}
             }
 
-            editor.render(&mut context, &mut textures);
+            editor.render(&mut context.canvas, &mut assets);
         std::thread::sleep(Duration::from_millis(10));
         } else {
 
@@ -62,14 +126,27 @@ pub fn run(font_path: &Path) {
             while accumulator > fixed_time_stamp {
                 accumulator -= fixed_time_stamp;
                 match game.update(&mut events, fixed_time_stamp.subsec_millis()) {
-                    Some(GameAction::QUIT) => loop_mode = LoopMode::EDITOR,
+                    Some(GameAction::QUIT) => {
+                        loop_mode = LoopMode::EDITOR;
+                        let (w, h) = game.resolution.window_size();
+                        editor.resolution.resize(w, h);
+                    },
                     _ => {},
                 }
             }
 
-            game.render(&mut context, &mut textures);
+            let stats = game.render(&mut context.canvas, &mut assets);
+            // Shown by the next frame.
+            if cfg!(feature = "debug-draw") {
+                DebugDraw::new(Color::RGB(255, 255, 0)).screen()
+                    .text(Vector2d::new(4.0, 4.0), &stats.to_string());
+            }
             std::thread::sleep(fixed_time_stamp);
         }
+
+        for e in assets.take_errors() {
+            println!("{}", e);
+        }
         
     }
 
@@ -0,0 +1,310 @@
+// Lighting computed on the CPU into a low resolution light map: ambient light
+// plus every light reaching each texel, stopped by the static bodies. The map
+// is uploaded into a streaming texture and multiplied onto the scene with the
+// Mod blend mode, which every renderer supports.
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use serde_derive::{Serialize, Deserialize};
+
+use super::assets::AssetManager;
+use super::core::View;
+use super::math::Vector2d;
+
+type FloatVector = Vector2d<f32>;
+
+fn white() -> (u8, u8, u8) {
+    (255, 255, 255)
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn default_falloff() -> f32 {
+    2.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn point() -> LightKind {
+    LightKind::Point
+}
+
+fn default_texel_size() -> u32 {
+    4
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    // In every direction.
+    Point,
+    // Cone around direction, both in degrees: direction 0 points right, 90
+    // down, and cone is the full opening.
+    Spot { direction: f32, cone: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    // Position in the world.
+    pub x: f32,
+    pub y: f32,
+    #[serde(default = "white")]
+    pub color: (u8, u8, u8),
+    // Nothing is lit further away.
+    pub radius: f32,
+    // Multiplies the color, above 1 saturates near the light.
+    #[serde(default = "one")]
+    pub intensity: f32,
+    // Exponent of the decrease with the distance: 1 is linear, more keeps
+    // the light closer to the source.
+    #[serde(default = "default_falloff")]
+    pub falloff: f32,
+    #[serde(default = "point")]
+    pub kind: LightKind,
+    // Stopped by the static bodies.
+    #[serde(default = "default_true")]
+    pub shadows: bool,
+}
+
+impl Light {
+
+    pub fn new(x: f32, y: f32, radius: f32) -> Light {
+        Light {
+            x,
+            y,
+            color: white(),
+            radius,
+            intensity: 1.0,
+            falloff: default_falloff(),
+            kind: point(),
+            shadows: true,
+        }
+    }
+
+    pub fn spot(x: f32, y: f32, radius: f32, direction: f32, cone: f32) -> Light {
+        Light { kind: LightKind::Spot { direction, cone }, ..Light::new(x, y, radius) }
+    }
+
+    // Fraction of the light reaching p, shadows aside.
+    fn attenuation(&self, p: FloatVector) -> f32 {
+        let (dx, dy) = (p.x - self.x, p.y - self.y);
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance >= self.radius {
+            return 0.0;
+        }
+        let amount = (1.0 - distance / self.radius).powf(self.falloff.max(0.0));
+
+        match self.kind {
+            LightKind::Point => amount,
+            LightKind::Spot { direction, cone } => {
+                if distance == 0.0 {
+                    return amount;
+                }
+                let angle = dy.atan2(dx).to_degrees();
+                let off = ((angle - direction + 180.0).rem_euclid(360.0) - 180.0).abs();
+                let half = cone / 2.0;
+                // The last tenth of the cone fades out to soften its edge.
+                let edge = (half * 0.1).max(0.0001);
+                amount * ((half - off) / edge).clamp(0.0, 1.0)
+            },
+        }
+    }
+}
+
+// Lighting of a scene. Objects are drawn with their colors multiplied by
+// the light, so black is darkness and white leaves them as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lighting {
+    // Light everywhere, added to the lights.
+    #[serde(default)]
+    pub ambient: (u8, u8, u8),
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    // Size of a texel of the light map, in pixels. Larger is faster and
+    // blockier.
+    #[serde(default = "default_texel_size")]
+    pub texel_size: u32,
+}
+
+impl Lighting {
+
+    pub fn new(ambient: (u8, u8, u8)) -> Lighting {
+        Lighting { ambient, lights: Vec::new(), texel_size: default_texel_size() }
+    }
+
+    // Light at the world position p. occluders are the world rectangles of
+    // the static bodies. They do not shadow themselves nor a light inside
+    // them.
+    pub fn light_at(&self, p: FloatVector, occluders: &[Rect]) -> (u8, u8, u8) {
+        let mut total = (self.ambient.0 as f32, self.ambient.1 as f32, self.ambient.2 as f32);
+        for light in self.lights.iter() {
+            let amount = light.attenuation(p) * light.intensity;
+            if amount <= 0.0 {
+                continue;
+            }
+            let origin = FloatVector::new(light.x, light.y);
+            if light.shadows && occluders.iter().any(|r| !contains(r, p) && !contains(r, origin) && blocks(r, origin, p)) {
+                continue;
+            }
+            total.0 += light.color.0 as f32 * amount;
+            total.1 += light.color.1 as f32 * amount;
+            total.2 += light.color.2 as f32 * amount;
+        }
+        let channel = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        (channel(total.0), channel(total.1), channel(total.2))
+    }
+
+    // RGB24 light map covering the viewport of the view, one texel per
+    // texel_size pixels. Returns its width, height and pixels.
+    pub fn light_map(&self, view: &View, occluders: &[Rect]) -> (u32, u32, Vec<u8>) {
+        let size = self.texel_size.max(1);
+        let viewport = view.viewport;
+        let (w, h) = (viewport.width().div_ceil(size), viewport.height().div_ceil(size));
+
+        // Only the occluders near a light can shadow.
+        let near: Vec<Rect> = occluders.iter()
+            .filter(|r| self.lights.iter().any(|l| l.shadows && overlaps_circle(r, l)))
+            .cloned()
+            .collect();
+
+        let mut pixels = Vec::with_capacity((w * h * 3) as usize);
+        for row in 0..h {
+            for col in 0..w {
+                let screen = FloatVector::new(viewport.x() as f32 + (col as f32 + 0.5) * size as f32,
+                                              viewport.y() as f32 + (row as f32 + 0.5) * size as f32);
+                let (r, g, b) = self.light_at(view.screen_to_world(screen), &near);
+                pixels.extend_from_slice(&[r, g, b]);
+            }
+        }
+        (w, h, pixels)
+    }
+
+    // Multiplies what the view shows by the light map. occluders are the
+    // world rectangles of the static bodies, see Scene::occluders.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &View, occluders: &[Rect],
+                                   assets: &mut AssetManager) {
+        let (w, h, pixels) = self.light_map(view, occluders);
+        if w == 0 || h == 0 {
+            return;
+        }
+        // Left unlit if the texture cannot be made or updated.
+        let texture = match assets.light_map_texture(w, h) {
+            Ok(texture) => texture,
+            Err(_) => return,
+        };
+        let src = Rect::new(0, 0, w, h);
+        if texture.update(src, &pixels, (w * 3) as usize).is_err() {
+            return;
+        }
+        texture.set_blend_mode(BlendMode::Mod);
+        let size = self.texel_size.max(1);
+        let dst = Rect::new(view.viewport.x(), view.viewport.y(), w * size, h * size);
+        canvas.copy(texture, Some(src), Some(dst)).expect("Lighting render failed");
+    }
+}
+
+fn contains(r: &Rect, p: FloatVector) -> bool {
+    p.x >= r.x() as f32 && p.x < r.right() as f32 && p.y >= r.y() as f32 && p.y < r.bottom() as f32
+}
+
+fn overlaps_circle(r: &Rect, light: &Light) -> bool {
+    let nearest_x = light.x.clamp(r.x() as f32, r.right() as f32);
+    let nearest_y = light.y.clamp(r.y() as f32, r.bottom() as f32);
+    let (dx, dy) = (light.x - nearest_x, light.y - nearest_y);
+    dx * dx + dy * dy < light.radius * light.radius
+}
+
+// Whether the segment from a to b crosses the rectangle (slab method).
+fn blocks(r: &Rect, a: FloatVector, b: FloatVector) -> bool {
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    let axes = [(a.x, b.x - a.x, r.x() as f32, r.right() as f32),
+                (a.y, b.y - a.y, r.y() as f32, r.bottom() as f32)];
+    for &(start, delta, min, max) in axes.iter() {
+        if delta == 0.0 {
+            if start < min || start >= max {
+                return false;
+            }
+            continue;
+        }
+        let (mut near, mut far) = ((min - start) / delta, (max - start) / delta);
+        if near > far {
+            std::mem::swap(&mut near, &mut far);
+        }
+        t0 = t0.max(near);
+        t1 = t1.min(far);
+        if t0 >= t1 {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::core::Camera;
+
+    #[test]
+    fn point_light_falloff() {
+        let mut lighting = Lighting::new((20, 20, 20));
+        let mut light = Light::new(0.0, 0.0, 100.0);
+        light.falloff = 1.0;
+        light.color = (200, 100, 0);
+        lighting.lights.push(light);
+
+        assert_eq!(lighting.light_at(FloatVector::new(0.0, 0.0), &[]), (220, 120, 20));
+        assert_eq!(lighting.light_at(FloatVector::new(0.0, 50.0), &[]), (120, 70, 20));
+        // Ambient only out of the radius.
+        assert_eq!(lighting.light_at(FloatVector::new(300.0, 0.0), &[]), (20, 20, 20));
+
+        // Saturates instead of wrapping.
+        lighting.lights[0].intensity = 4.0;
+        assert_eq!(lighting.light_at(FloatVector::new(0.0, 0.0), &[]).0, 255);
+    }
+
+    #[test]
+    fn spot_light_cone() {
+        let mut lighting = Lighting::new((0, 0, 0));
+        // Pointing down, 90 degrees wide.
+        lighting.lights.push(Light::spot(0.0, 0.0, 100.0, 90.0, 90.0));
+
+        assert!(lighting.light_at(FloatVector::new(0.0, 10.0), &[]).0 > 0);
+        assert!(lighting.light_at(FloatVector::new(10.0, 15.0), &[]).0 > 0);
+        assert_eq!(lighting.light_at(FloatVector::new(10.0, 5.0), &[]), (0, 0, 0));
+        assert_eq!(lighting.light_at(FloatVector::new(0.0, -10.0), &[]), (0, 0, 0));
+    }
+
+    #[test]
+    fn hard_shadows() {
+        let mut lighting = Lighting::new((10, 10, 10));
+        lighting.lights.push(Light::new(0.0, 0.0, 100.0));
+        let wall = [Rect::new(20, -10, 10, 20)];
+
+        // Behind the wall, in front of it, and next to it.
+        assert_eq!(lighting.light_at(FloatVector::new(50.0, 0.0), &wall), (10, 10, 10));
+        assert!(lighting.light_at(FloatVector::new(10.0, 0.0), &wall).0 > 10);
+        assert!(lighting.light_at(FloatVector::new(50.0, 40.0), &wall).0 > 10);
+        // The wall itself is lit.
+        assert!(lighting.light_at(FloatVector::new(21.0, 0.0), &wall).0 > 10);
+
+        lighting.lights[0].shadows = false;
+        assert!(lighting.light_at(FloatVector::new(50.0, 0.0), &wall).0 > 10);
+    }
+
+    #[test]
+    fn light_map_covers_the_view() {
+        let mut lighting = Lighting::new((0, 0, 0));
+        lighting.texel_size = 10;
+        lighting.lights.push(Light::new(5.0, 5.0, 10.0));
+        let view = Camera::new().view(95, 40);
+
+        let (w, h, pixels) = lighting.light_map(&view, &[]);
+        assert_eq!((w, h), (10, 4));
+        assert_eq!(pixels.len(), 10 * 4 * 3);
+        // Texel centered on the light, its neighbour out of reach.
+        assert_eq!(&pixels[..6], &[255, 255, 255, 0, 0, 0]);
+    }
+}
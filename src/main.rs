@@ -1,16 +1,17 @@
-use embed::run;
+use embed::run_with_files;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 fn main() {
     let args: Vec<_> = env::args().collect();
 
     println!("linked sdl2_ttf: {}", sdl2::ttf::get_linked_version());
 
     if args.len() < 2 {
-        println!("Usage: ./demo font.[ttf|ttc|fon]")
+        println!("Usage: ./demo font.[ttf|ttc|fon] [maps and sprite sheets...]")
     } else {
         let path: &Path = Path::new(&args[1]);
-        run(path);
+        let files: Vec<PathBuf> = args[2..].iter().map(PathBuf::from).collect();
+        run_with_files(path, &files);
     }
 
 }
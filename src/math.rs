@@ -13,6 +13,31 @@ impl<T> Vector2d<T> {
     }
 }
 
+impl Vector2d<f32> {
+
+    pub fn dot(&self, other: Vector2d<f32>) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn distance(&self, other: Vector2d<f32>) -> f32 {
+        (other - *self).length()
+    }
+
+    // Returns the zero vector when the length is zero instead of NaNs.
+    pub fn normalized(&self) -> Vector2d<f32> {
+        let length = self.length();
+        if length == 0.0 {
+            Vector2d::new(0.0, 0.0)
+        } else {
+            Vector2d::new(self.x / length, self.y / length)
+        }
+    }
+}
+
 impl<T: Add<Output=T>> Add for Vector2d<T> {
 
     type Output = Vector2d<T>;
@@ -49,6 +74,17 @@ impl<T: Mul<usize, Output=T>> Mul<usize> for Vector2d<T> {
     }
 }
 
+impl Mul<f32> for Vector2d<f32> {
+
+    type Output = Vector2d<f32>;
+
+    fn mul(self, rhs: f32) -> Vector2d<f32> {
+        Vector2d {
+            x: self.x * rhs,
+            y: self.y * rhs,
+        }
+    }
+}
 
 macro_rules! assert_eq_delta (
     ($lhs:expr, $rhs:expr, $delta:expr) => {
@@ -92,4 +128,15 @@ mod tests {
         assert_eq!(mult.x, 4);
         assert_eq!(mult.y, 8);
     }
+
+    #[test]
+    fn float_vector_length() {
+        let v = Vector2d::new(3.0, 4.0);
+        assert_eq_delta!(v.length(), 5.0, 0.0001);
+
+        let n = v.normalized();
+        assert_eq_delta!(n.x, 0.6, 0.0001);
+        assert_eq_delta!(n.y, 0.8, 0.0001);
+        assert_eq!(Vector2d::new(0.0, 0.0).normalized(), Vector2d::new(0.0, 0.0));
+    }
 }
@@ -0,0 +1,233 @@
+use std::f32::consts::{FRAC_1_SQRT_2, SQRT_2};
+
+use super::random::Rng;
+use super::tween::lerp;
+
+// Value, Perlin and simplex noise in 1D and 2D. All functions return values
+// roughly in [-1, 1] and are fully determined by the seed.
+pub struct Noise {
+    pub seed: u64,
+    // Permutation of 0..256, repeated twice to avoid wrapping the indices.
+    perm: Vec<usize>,
+}
+
+const GRAD2: [(f32, f32); 8] = [
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0),
+    (FRAC_1_SQRT_2, FRAC_1_SQRT_2), (-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+    (FRAC_1_SQRT_2, -FRAC_1_SQRT_2), (-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+];
+
+const SIMPLEX_F2: f32 = 0.36602542; // 0.5 * (sqrt(3) - 1)
+const SIMPLEX_G2: f32 = 0.21132487; // (3 - sqrt(3)) / 6
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn grad1(hash: usize, x: f32) -> f32 {
+    let h = hash & 15;
+    let grad = 1.0 + (h & 7) as f32;
+    if h & 8 != 0 { -grad * x } else { grad * x }
+}
+
+fn grad2(hash: usize, x: f32, y: f32) -> f32 {
+    let (gx, gy) = GRAD2[hash & 7];
+    gx * x + gy * y
+}
+
+impl Noise {
+
+    pub fn new(seed: u64) -> Noise {
+        let mut rng = Rng::new(seed);
+        let mut table: Vec<usize> = (0..256).collect();
+        rng.shuffle(&mut table);
+
+        let mut perm = table.clone();
+        perm.extend(table);
+        Noise { seed, perm }
+    }
+
+    fn hash1(&self, i: i32) -> usize {
+        self.perm[(i & 255) as usize]
+    }
+
+    fn hash2(&self, i: i32, j: i32) -> usize {
+        self.perm[self.hash1(i) + (j & 255) as usize]
+    }
+
+    // Random value in [-1, 1] attached to a lattice point.
+    fn lattice1(&self, i: i32) -> f32 {
+        self.hash1(i) as f32 / 127.5 - 1.0
+    }
+
+    fn lattice2(&self, i: i32, j: i32) -> f32 {
+        self.hash2(i, j) as f32 / 127.5 - 1.0
+    }
+
+    pub fn value1d(&self, x: f32) -> f32 {
+        let i = x.floor() as i32;
+        let t = fade(x - i as f32);
+        lerp(self.lattice1(i), self.lattice1(i + 1), t)
+    }
+
+    pub fn value2d(&self, x: f32, y: f32) -> f32 {
+        let i = x.floor() as i32;
+        let j = y.floor() as i32;
+        let tx = fade(x - i as f32);
+        let ty = fade(y - j as f32);
+
+        let top = lerp(self.lattice2(i, j), self.lattice2(i + 1, j), tx);
+        let bottom = lerp(self.lattice2(i, j + 1), self.lattice2(i + 1, j + 1), tx);
+        lerp(top, bottom, ty)
+    }
+
+    pub fn perlin1d(&self, x: f32) -> f32 {
+        let i = x.floor() as i32;
+        let fx = x - i as f32;
+        let t = fade(fx);
+
+        // Max gradient is 8, max distance 0.5 -> scale back to [-1, 1].
+        let n = lerp(grad1(self.hash1(i), fx), grad1(self.hash1(i + 1), fx - 1.0), t);
+        (n * 0.25).clamp(-1.0, 1.0)
+    }
+
+    pub fn perlin2d(&self, x: f32, y: f32) -> f32 {
+        let i = x.floor() as i32;
+        let j = y.floor() as i32;
+        let fx = x - i as f32;
+        let fy = y - j as f32;
+        let u = fade(fx);
+        let v = fade(fy);
+
+        let n00 = grad2(self.hash2(i, j), fx, fy);
+        let n10 = grad2(self.hash2(i + 1, j), fx - 1.0, fy);
+        let n01 = grad2(self.hash2(i, j + 1), fx, fy - 1.0);
+        let n11 = grad2(self.hash2(i + 1, j + 1), fx - 1.0, fy - 1.0);
+
+        let n = lerp(lerp(n00, n10, u), lerp(n01, n11, u), v);
+        (n * SQRT_2).clamp(-1.0, 1.0)
+    }
+
+    pub fn simplex1d(&self, x: f32) -> f32 {
+        let i0 = x.floor() as i32;
+        let x0 = x - i0 as f32;
+        let x1 = x0 - 1.0;
+
+        let mut t0 = 1.0 - x0 * x0;
+        t0 *= t0;
+        let n0 = t0 * t0 * grad1(self.hash1(i0), x0);
+
+        let mut t1 = 1.0 - x1 * x1;
+        t1 *= t1;
+        let n1 = t1 * t1 * grad1(self.hash1(i0 + 1), x1);
+
+        (0.395 * (n0 + n1)).clamp(-1.0, 1.0)
+    }
+
+    pub fn simplex2d(&self, x: f32, y: f32) -> f32 {
+        // Skew the input space to find which simplex cell we are in.
+        let s = (x + y) * SIMPLEX_F2;
+        let i = (x + s).floor() as i32;
+        let j = (y + s).floor() as i32;
+        let t = (i + j) as f32 * SIMPLEX_G2;
+        let x0 = x - (i as f32 - t);
+        let y0 = y - (j as f32 - t);
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let x1 = x0 - i1 as f32 + SIMPLEX_G2;
+        let y1 = y0 - j1 as f32 + SIMPLEX_G2;
+        let x2 = x0 - 1.0 + 2.0 * SIMPLEX_G2;
+        let y2 = y0 - 1.0 + 2.0 * SIMPLEX_G2;
+
+        let corner = |hash: usize, x: f32, y: f32| {
+            let t = 0.5 - x * x - y * y;
+            if t < 0.0 {
+                0.0
+            } else {
+                let t = t * t;
+                t * t * grad2(hash, x, y)
+            }
+        };
+
+        let n0 = corner(self.hash2(i, j), x0, y0);
+        let n1 = corner(self.hash2(i + i1, j + j1), x1, y1);
+        let n2 = corner(self.hash2(i + 1, j + 1), x2, y2);
+
+        (70.0 * (n0 + n1 + n2)).clamp(-1.0, 1.0)
+    }
+
+    // Fractal brownian motion over perlin2d. Classic values are lacunarity 2
+    // and gain 0.5.
+    pub fn fbm2d(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut norm = 0.0;
+
+        for _ in 0..octaves {
+            sum += amplitude * self.perlin2d(x * frequency, y * frequency);
+            norm += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+
+        if norm == 0.0 { 0.0 } else { sum / norm }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn samples() -> Vec<(f32, f32)> {
+        (0..400).map(|i| (i as f32 * 0.137 - 20.0, i as f32 * 0.291 - 50.0)).collect()
+    }
+
+    #[test]
+    fn noise_is_deterministic() {
+        let a = Noise::new(5);
+        let b = Noise::new(5);
+        let c = Noise::new(6);
+        let mut differs = false;
+        for (x, y) in samples() {
+            assert_eq!(a.perlin2d(x, y), b.perlin2d(x, y));
+            assert_eq!(a.simplex2d(x, y), b.simplex2d(x, y));
+            assert_eq!(a.value1d(x), b.value1d(x));
+            differs |= a.perlin2d(x, y) != c.perlin2d(x, y);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn noise_is_bounded() {
+        let noise = Noise::new(9);
+        for (x, y) in samples() {
+            for n in [noise.value1d(x), noise.value2d(x, y), noise.perlin1d(x),
+                      noise.perlin2d(x, y), noise.simplex1d(x), noise.simplex2d(x, y),
+                      noise.fbm2d(x, y, 4, 2.0, 0.5)].iter() {
+                assert!(*n >= -1.0 && *n <= 1.0, "{} out of range", n);
+            }
+        }
+    }
+
+    #[test]
+    fn perlin_is_zero_on_lattice() {
+        let noise = Noise::new(2);
+        for i in -5..5 {
+            assert_eq!(noise.perlin1d(i as f32), 0.0);
+            assert_eq!(noise.perlin2d(i as f32, (i * 3) as f32), 0.0);
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous() {
+        let noise = Noise::new(4);
+        for (x, y) in samples() {
+            assert!((noise.value2d(x, y) - noise.value2d(x + 0.001, y)).abs() < 0.05);
+            assert!((noise.perlin2d(x, y) - noise.perlin2d(x + 0.001, y)).abs() < 0.05);
+            assert!((noise.simplex2d(x, y) - noise.simplex2d(x, y + 0.001)).abs() < 0.05);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget, TextureQuery};
+use serde_derive::{Serialize, Deserialize};
+
+use super::assets::AssetManager;
+use super::core::View;
+use super::math::Vector2d;
+
+fn background() -> String {
+    "background".to_string()
+}
+
+fn half() -> f32 {
+    0.5
+}
+
+// Image scrolling slower than the camera to fake depth. A scroll factor of
+// 0 keeps the image fixed on the screen, 1 moves it with the world.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParallaxLayer {
+    pub texture: String,
+    #[serde(default = "half")]
+    pub scroll_x: f32,
+    #[serde(default = "half")]
+    pub scroll_y: f32,
+
+    // Repeat the image to fill the screen on this axis.
+    #[serde(default)]
+    pub repeat_x: bool,
+    #[serde(default)]
+    pub repeat_y: bool,
+
+    // Position of the image when the camera is at the origin.
+    #[serde(default)]
+    pub offset_x: f32,
+    #[serde(default)]
+    pub offset_y: f32,
+
+    // In pixels per second, for clouds and such.
+    #[serde(default)]
+    pub auto_scroll_x: f32,
+    #[serde(default)]
+    pub auto_scroll_y: f32,
+
+    // Render layer of the scene, see core::RenderLayer.
+    #[serde(default = "background")]
+    pub layer: String,
+    #[serde(default)]
+    pub z_index: i32,
+
+    // Distance moved by the auto scroll so far.
+    #[serde(skip)]
+    scrolled: (f32, f32),
+}
+
+impl ParallaxLayer {
+
+    pub fn new(texture: &str, scroll_x: f32, scroll_y: f32) -> ParallaxLayer {
+        ParallaxLayer {
+            texture: texture.to_string(),
+            scroll_x,
+            scroll_y,
+            repeat_x: false,
+            repeat_y: false,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            auto_scroll_x: 0.0,
+            auto_scroll_y: 0.0,
+            layer: background(),
+            z_index: 0,
+            scrolled: (0.0, 0.0),
+        }
+    }
+
+    pub fn update(&mut self, dt: u32) {
+        self.scrolled.0 += self.auto_scroll_x * dt as f32 / 1000.0;
+        self.scrolled.1 += self.auto_scroll_y * dt as f32 / 1000.0;
+    }
+
+    pub fn reset(&mut self) {
+        self.scrolled = (0.0, 0.0);
+    }
+
+    // Screen rectangles where the image is drawn. Backgrounds are not zoomed
+    // nor rotated with the camera, they fill its viewport.
+    pub fn placements(&self, view: &View, image_w: u32, image_h: u32) -> Vec<Rect> {
+        if image_w == 0 || image_h == 0 {
+            return Vec::new();
+        }
+
+        let origin = Vector2d::new(self.offset_x + self.scrolled.0, self.offset_y + self.scrolled.1);
+        let local = view.parallax_to_screen(origin, self.scroll_x, self.scroll_y);
+        let viewport = view.viewport;
+
+        // First copy just before the left (or top) of the screen.
+        let axis = |pos: i32, size: u32, view_start: i32, view_size: u32, repeat: bool| -> Vec<i32> {
+            if !repeat {
+                return vec![pos];
+            }
+            let mut start = view_start + (pos - view_start).rem_euclid(size as i32);
+            if start > view_start {
+                start -= size as i32;
+            }
+            (0..).map(|i| start + i * size as i32).take_while(|&p| p < view_start + view_size as i32).collect()
+        };
+
+        let xs = axis(local.x, image_w, viewport.x(), viewport.width(), self.repeat_x);
+        let ys = axis(local.y, image_h, viewport.y(), viewport.height(), self.repeat_y);
+        ys.iter()
+            .flat_map(|&y| xs.iter().map(move |&x| Rect::new(x, y, image_w, image_h)))
+            .collect()
+    }
+
+    // Returns the number of copies of the image drawn.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &View, assets: &mut AssetManager) -> u32 {
+        // The asset manager reports the error.
+        let texture = match assets.find_texture(&self.texture) {
+            Ok(texture) => texture,
+            Err(_) => return 0,
+        };
+        let TextureQuery { width, height, .. } = texture.query();
+
+        let placements = self.placements(view, width, height);
+        for dst in placements.iter() {
+            canvas.copy(texture, None, Some(*dst)).expect("Parallax render failed");
+        }
+        placements.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::core::{Camera, Viewport};
+
+    #[test]
+    fn scrolls_slower_than_camera() {
+        let mut camera = Camera::new();
+        let mut layer = ParallaxLayer::new("hills.bmp", 0.5, 0.0);
+        layer.offset_y = 10.0;
+        assert_eq!(layer.placements(&camera.view(100, 100), 50, 20), vec![Rect::new(0, 10, 50, 20)]);
+
+        camera.position = Vector2d::new(40.0, 40.0);
+        assert_eq!(layer.placements(&camera.view(100, 100), 50, 20), vec![Rect::new(-20, 10, 50, 20)]);
+    }
+
+    #[test]
+    fn repeats_to_fill_the_view() {
+        let mut camera = Camera::new();
+        camera.position.x = 30.0;
+        let mut layer = ParallaxLayer::new("clouds.bmp", 1.0, 1.0);
+        layer.repeat_x = true;
+
+        let xs: Vec<i32> = layer.placements(&camera.view(100, 60), 40, 20).iter().map(|r| r.x()).collect();
+        assert_eq!(xs, vec![-30, 10, 50, 90]);
+
+        layer.repeat_y = true;
+        assert_eq!(layer.placements(&camera.view(100, 60), 40, 20).len(), 12);
+
+        // Filling a viewport, not the whole window.
+        layer.repeat_y = false;
+        camera.viewport = Some(Viewport::new(200, 0, 100, 60));
+        let xs: Vec<i32> = layer.placements(&camera.view(800, 600), 40, 20).iter().map(|r| r.x()).collect();
+        assert_eq!(xs, vec![170, 210, 250, 290]);
+    }
+
+    #[test]
+    fn auto_scroll() {
+        let camera = Camera::new();
+        let mut layer = ParallaxLayer::new("clouds.bmp", 0.0, 0.0);
+        layer.auto_scroll_x = -20.0;
+        layer.update(500);
+        assert_eq!(layer.placements(&camera.view(100, 60), 40, 20)[0].x(), -10);
+
+        layer.reset();
+        assert_eq!(layer.placements(&camera.view(100, 60), 40, 20)[0].x(), 0);
+    }
+}
@@ -0,0 +1,450 @@
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use serde_derive::{Serialize, Deserialize};
+
+use super::assets::AssetManager;
+use super::core::View;
+use super::math::Vector2d;
+use super::random::Rng;
+use super::tween::Curve;
+
+type FloatVector = Vector2d<f32>;
+
+fn default_rate() -> f32 {
+    20.0
+}
+
+fn default_max_particles() -> usize {
+    256
+}
+
+fn default_lifetime() -> Range {
+    Range::new(600.0, 1000.0)
+}
+
+fn default_speed() -> Range {
+    Range::new(40.0, 80.0)
+}
+
+fn default_angle() -> Range {
+    Range::new(250.0, 290.0)
+}
+
+fn default_size() -> Curve<f32> {
+    Curve::new(vec![(0.0, 4.0), (1.0, 1.0)])
+}
+
+fn default_color() -> Curve<(u8, u8, u8, u8)> {
+    Curve::new(vec![(0.0, (255, 255, 255, 255)), (1.0, (255, 255, 255, 0))])
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_bounce() -> f32 {
+    0.5
+}
+
+// Uniform random value in [min, max].
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Range {
+
+    pub fn new(min: f32, max: f32) -> Range {
+        Range { min, max }
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> f32 {
+        rng.range_f32(self.min, self.max)
+    }
+}
+
+// count particles emitted at once, time milliseconds after the start of
+// the emission.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Burst {
+    pub time: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    position: FloatVector,
+    velocity: FloatVector,
+    // In milliseconds.
+    age: u32,
+    lifetime: u32,
+}
+
+impl Particle {
+
+    // From 0 when emitted to 1 when it dies.
+    fn progress(&self) -> f32 {
+        (self.age as f32 / self.lifetime.max(1) as f32).min(1.0)
+    }
+}
+
+// Emits particles from the center of its game object. Particles live in the
+// world: they do not move with the object once emitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticleEmitter {
+    // Particles per second, on top of the bursts.
+    #[serde(default = "default_rate")]
+    pub rate: f32,
+    #[serde(default)]
+    pub bursts: Vec<Burst>,
+    // Length of the emission in milliseconds, 0 emits forever.
+    #[serde(default)]
+    pub duration: u32,
+    // Starts again after duration, with the bursts.
+    #[serde(default = "default_true")]
+    pub looping: bool,
+    // New particles are dropped above this count.
+    #[serde(default = "default_max_particles")]
+    pub max_particles: usize,
+
+    // In milliseconds.
+    #[serde(default = "default_lifetime")]
+    pub lifetime: Range,
+    // In pixels per second.
+    #[serde(default = "default_speed")]
+    pub speed: Range,
+    // Direction of the velocity in degrees: 0 is right, 90 is down.
+    #[serde(default = "default_angle")]
+    pub angle: Range,
+    // Acceleration in pixels per second squared.
+    #[serde(default)]
+    pub gravity_x: f32,
+    #[serde(default)]
+    pub gravity_y: f32,
+
+    // Over the lifetime of a particle: side of its square in pixels, and
+    // its color with the alpha.
+    #[serde(default = "default_size")]
+    pub size: Curve<f32>,
+    #[serde(default = "default_color")]
+    pub color: Curve<(u8, u8, u8, u8)>,
+    // Drawn tinted by the color, plain squares without it.
+    #[serde(default)]
+    pub texture: Option<String>,
+
+    // Bounce off static bodies, losing velocity by the bounce factor.
+    #[serde(default)]
+    pub collide: bool,
+    #[serde(default = "default_bounce")]
+    pub bounce: f32,
+
+    // From the center of the game object.
+    #[serde(default)]
+    pub offset_x: f32,
+    #[serde(default)]
+    pub offset_y: f32,
+
+    #[serde(skip)]
+    particles: Vec<Particle>,
+    // Time since the start of the emission, in milliseconds.
+    #[serde(skip)]
+    elapsed: u32,
+    // Fraction of a particle not emitted yet by the rate.
+    #[serde(skip)]
+    pending: f32,
+}
+
+impl ParticleEmitter {
+
+    pub fn new() -> ParticleEmitter {
+        ParticleEmitter {
+            rate: default_rate(),
+            bursts: Vec::new(),
+            duration: 0,
+            looping: true,
+            max_particles: default_max_particles(),
+            lifetime: default_lifetime(),
+            speed: default_speed(),
+            angle: default_angle(),
+            gravity_x: 0.0,
+            gravity_y: 0.0,
+            size: default_size(),
+            color: default_color(),
+            texture: None,
+            collide: false,
+            bounce: default_bounce(),
+            offset_x: 0.0,
+            offset_y: 0.0,
+            particles: Vec::new(),
+            elapsed: 0,
+            pending: 0.0,
+        }
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.particles.len()
+    }
+
+    // Removes the particles and restarts the emission.
+    pub fn reset(&mut self) {
+        self.particles.clear();
+        self.elapsed = 0;
+        self.pending = 0.0;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.duration > 0 && self.elapsed >= self.duration
+    }
+
+    pub fn emit(&mut self, count: u32, origin: FloatVector, rng: &mut Rng) {
+        let origin = origin + FloatVector::new(self.offset_x, self.offset_y);
+        for _ in 0..count {
+            if self.particles.len() >= self.max_particles {
+                return;
+            }
+            let angle = self.angle.sample(rng).to_radians();
+            let speed = self.speed.sample(rng);
+            self.particles.push(Particle {
+                position: origin,
+                velocity: FloatVector::new(angle.cos(), angle.sin()) * speed,
+                age: 0,
+                lifetime: self.lifetime.sample(rng).max(0.0) as u32,
+            });
+        }
+    }
+
+    // origin is the center of the game object. statics are the rectangles
+    // of the static bodies, used when collide is set.
+    pub fn update(&mut self, origin: FloatVector, dt: u32, rng: &mut Rng, statics: &[Rect]) {
+        let seconds = dt as f32 / 1000.0;
+        let gravity = FloatVector::new(self.gravity_x, self.gravity_y);
+        for particle in self.particles.iter_mut() {
+            particle.age += dt;
+            particle.velocity = particle.velocity + gravity * seconds;
+            let step = particle.velocity * seconds;
+            if self.collide {
+                move_and_bounce(particle, step, self.bounce, statics);
+            } else {
+                particle.position = particle.position + step;
+            }
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        if self.is_finished() {
+            return;
+        }
+        let count = self.emission_count(dt);
+        self.emit(count, origin, rng);
+    }
+
+    // Particles due in the next dt milliseconds, moving the emission time.
+    fn emission_count(&mut self, dt: u32) -> u32 {
+        let start = self.elapsed;
+        let mut end = start + dt;
+        let mut count = 0;
+
+        if self.duration > 0 && end >= self.duration {
+            // Bursts at the very end are emitted before looping.
+            count += self.bursts_between(start, self.duration + 1);
+            if self.looping {
+                end %= self.duration;
+                count += self.bursts_between(0, end);
+            } else {
+                end = self.duration;
+            }
+        } else {
+            count += self.bursts_between(start, end);
+        }
+        self.elapsed = end;
+
+        self.pending += self.rate.max(0.0) * dt as f32 / 1000.0;
+        let whole = self.pending.floor();
+        self.pending -= whole;
+        count + whole as u32
+    }
+
+    // Bursts with a time in [start, end).
+    fn bursts_between(&self, start: u32, end: u32) -> u32 {
+        self.bursts.iter()
+            .filter(|b| b.time >= start && b.time < end)
+            .map(|b| b.count)
+            .sum()
+    }
+
+    // World rectangle (x, y, w, h) around all the particles, None without
+    // any.
+    pub fn bounds(&self) -> Option<(f32, f32, f32, f32)> {
+        let first = self.particles.first()?;
+        let (mut x0, mut y0, mut x1, mut y1) = (first.position.x, first.position.y, first.position.x, first.position.y);
+        let mut size: f32 = 0.0;
+        for particle in self.particles.iter() {
+            x0 = x0.min(particle.position.x);
+            y0 = y0.min(particle.position.y);
+            x1 = x1.max(particle.position.x);
+            y1 = y1.max(particle.position.y);
+            size = size.max(self.size.sample(particle.progress()).unwrap_or(0.0));
+        }
+        let half = size / 2.0;
+        Some((x0 - half, y0 - half, x1 - x0 + size, y1 - y0 + size))
+    }
+
+    // Returns the number of draw calls.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &View, assets: &mut AssetManager) -> u32 {
+        if self.particles.is_empty() {
+            return 0;
+        }
+        // The asset manager reports the error.
+        let texture = match &self.texture {
+            Some(name) => assets.find_texture(name),
+            None => assets.white_texture(),
+        };
+        let texture = match texture {
+            Ok(texture) => texture,
+            Err(_) => return 0,
+        };
+
+        let blend = texture.blend_mode();
+        texture.set_blend_mode(BlendMode::Blend);
+        let mut calls = 0;
+        for particle in self.particles.iter() {
+            let t = particle.progress();
+            let size = self.size.sample(t).unwrap_or(1.0);
+            let (r, g, b, a) = self.color.sample(t).unwrap_or((255, 255, 255, 255));
+            if size <= 0.0 || a == 0 {
+                continue;
+            }
+            let dst = view.world_rect(particle.position.x - size / 2.0, particle.position.y - size / 2.0, size, size);
+            texture.set_color_mod(r, g, b);
+            texture.set_alpha_mod(a);
+            canvas.copy_ex(texture, None, Some(dst), view.angle(), None, false, false).expect("Particle render failed");
+            calls += 1;
+        }
+        // The texture may be shared with sprites.
+        texture.set_color_mod(255, 255, 255);
+        texture.set_alpha_mod(255);
+        texture.set_blend_mode(blend);
+        calls
+    }
+}
+
+// Moves one axis at a time so that a particle hitting a wall keeps sliding
+// along it, and reverses the blocked axis.
+fn move_and_bounce(particle: &mut Particle, step: FloatVector, bounce: f32, statics: &[Rect]) {
+    let inside = |p: FloatVector| statics.iter().any(|r| {
+        p.x >= r.x() as f32 && p.x < r.right() as f32 && p.y >= r.y() as f32 && p.y < r.bottom() as f32
+    });
+
+    let next = FloatVector::new(particle.position.x + step.x, particle.position.y);
+    if inside(next) {
+        particle.velocity.x = -particle.velocity.x * bounce;
+    } else {
+        particle.position = next;
+    }
+
+    let next = FloatVector::new(particle.position.x, particle.position.y + step.y);
+    if inside(next) {
+        particle.velocity.y = -particle.velocity.y * bounce;
+    } else {
+        particle.position = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn emitter() -> ParticleEmitter {
+        let mut emitter = ParticleEmitter::new();
+        emitter.lifetime = Range::new(1000.0, 1000.0);
+        emitter.speed = Range::new(0.0, 0.0);
+        emitter
+    }
+
+    #[test]
+    fn rate_and_bursts() {
+        let mut rng = Rng::new(1);
+        let origin = FloatVector::new(0.0, 0.0);
+        let mut emitter = emitter();
+        emitter.rate = 10.0;
+        emitter.bursts = vec![Burst { time: 0, count: 5 }, Burst { time: 250, count: 3 }];
+        emitter.duration = 500;
+
+        // Half a particle from the rate, the first burst.
+        emitter.update(origin, 50, &mut rng, &[]);
+        assert_eq!(emitter.particle_count(), 5);
+        emitter.update(origin, 250, &mut rng, &[]);
+        assert_eq!(emitter.particle_count(), 11);
+
+        // Looping: the first burst again.
+        emitter.update(origin, 300, &mut rng, &[]);
+        assert_eq!(emitter.particle_count(), 19);
+
+        // Once over, no more particles and the old ones die.
+        emitter.looping = false;
+        emitter.update(origin, 500, &mut rng, &[]);
+        assert!(emitter.is_finished());
+        emitter.update(origin, 1000, &mut rng, &[]);
+        assert_eq!(emitter.particle_count(), 0);
+
+        emitter.reset();
+        emitter.max_particles = 4;
+        emitter.update(origin, 10, &mut rng, &[]);
+        assert_eq!(emitter.particle_count(), 4);
+    }
+
+    #[test]
+    fn velocity_and_gravity() {
+        let mut rng = Rng::new(2);
+        let mut emitter = emitter();
+        emitter.rate = 0.0;
+        emitter.speed = Range::new(100.0, 100.0);
+        emitter.angle = Range::new(0.0, 0.0);
+        emitter.gravity_y = 200.0;
+        emitter.offset_y = -10.0;
+
+        emitter.emit(1, FloatVector::new(5.0, 5.0), &mut rng);
+        emitter.update(FloatVector::new(5.0, 5.0), 500, &mut rng, &[]);
+        let particle = &emitter.particles[0];
+        assert_eq_delta!(particle.position.x, 55.0, 0.001);
+        assert_eq_delta!(particle.position.y, 45.0, 0.001);
+        assert_eq!(particle.progress(), 0.5);
+
+        // Size 4 shrinking to 1: 2.5 at half of the lifetime.
+        let (x, y, w, h) = emitter.bounds().unwrap();
+        assert_eq_delta!(x, 53.75, 0.001);
+        assert_eq_delta!(y, 43.75, 0.001);
+        assert_eq!((w, h), (2.5, 2.5));
+    }
+
+    #[test]
+    fn bounce_off_statics() {
+        let mut rng = Rng::new(3);
+        let mut emitter = emitter();
+        emitter.rate = 0.0;
+        emitter.speed = Range::new(100.0, 100.0);
+        emitter.angle = Range::new(90.0, 90.0);
+        emitter.collide = true;
+        let floor = [Rect::new(-50, 20, 100, 10)];
+
+        emitter.emit(1, FloatVector::new(0.0, 0.0), &mut rng);
+        for _ in 0..3 {
+            emitter.update(FloatVector::new(0.0, 0.0), 100, &mut rng, &floor);
+        }
+        // Blocked on the second step, going back up on the third.
+        let particle = &emitter.particles[0];
+        assert_eq_delta!(particle.position.y, 5.0, 0.001);
+        assert_eq_delta!(particle.velocity.y, -50.0, 0.001);
+    }
+
+    #[test]
+    fn settings_defaults() {
+        let emitter: ParticleEmitter = serde_json::from_str(r#"{"rate": 5, "texture": "spark.bmp"}"#).unwrap();
+        assert_eq!(emitter.rate, 5.0);
+        assert_eq!(emitter.texture, Some("spark.bmp".to_string()));
+        assert_eq!(emitter.lifetime, default_lifetime());
+        assert!(emitter.looping);
+        assert_eq!(emitter.size.sample(0.0), Some(4.0));
+    }
+}
@@ -0,0 +1,266 @@
+// Off-screen rendering. The frame is drawn into a target texture at a fixed
+// resolution, then composited on the canvas: scaled, letterboxed, tinted,
+// with scanlines and a fade on top. Everything is done with plain copies and
+// filled rectangles so it works with the software renderer too.
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+use serde_derive::{Serialize, Deserialize};
+
+use super::math::Vector2d;
+
+fn white() -> (u8, u8, u8) {
+    (255, 255, 255)
+}
+
+fn default_scale() -> ScaleMode {
+    ScaleMode::Fit
+}
+
+fn default_spacing() -> u32 {
+    2
+}
+
+fn default_intensity() -> u8 {
+    80
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ScaleMode {
+    // Fills the output, the image may be distorted.
+    Stretch,
+    // As large as possible keeping the aspect ratio, with bars around.
+    Fit,
+    // Largest whole multiple of the resolution, for crisp pixel art.
+    Integer,
+    // Scale of Fit, with a larger resolution instead of the bars. The
+    // target of a pipeline has a fixed size so it is the same as Fit there.
+    Expand,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scanlines {
+    // One dark line every spacing rows of the output.
+    #[serde(default = "default_spacing")]
+    pub spacing: u32,
+    // Alpha of the lines.
+    #[serde(default = "default_intensity")]
+    pub intensity: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    // Resolution of the off-screen target.
+    pub width: u32,
+    pub height: u32,
+    #[serde(default = "default_scale")]
+    pub scale: ScaleMode,
+    // Color of the bars around the image.
+    #[serde(default)]
+    pub letterbox: (u8, u8, u8),
+    #[serde(default = "white")]
+    pub tint: (u8, u8, u8),
+    #[serde(default)]
+    pub scanlines: Option<Scanlines>,
+    // Duration in milliseconds of the fade from fade_color when the
+    // pipeline starts.
+    #[serde(default)]
+    pub fade_in: u32,
+    #[serde(default)]
+    pub fade_color: (u8, u8, u8),
+}
+
+impl PipelineConfig {
+
+    pub fn new(width: u32, height: u32) -> PipelineConfig {
+        PipelineConfig {
+            width,
+            height,
+            scale: default_scale(),
+            letterbox: (0, 0, 0),
+            tint: white(),
+            scanlines: None,
+            fade_in: 0,
+            fade_color: (0, 0, 0),
+        }
+    }
+
+    // Position in the target of a point of an output of out_w x out_h, on
+    // which the target is drawn scaled.
+    pub fn output_to_target(&self, p: Vector2d<f32>, out_w: u32, out_h: u32) -> Vector2d<f32> {
+        let dst = destination(self.scale, self.width, self.height, out_w, out_h);
+        Vector2d::new((p.x - dst.x() as f32) * self.width as f32 / dst.width() as f32,
+                      (p.y - dst.y() as f32) * self.height as f32 / dst.height() as f32)
+    }
+}
+
+// Opacity of the fade color going from `from` to `to` in `duration` ms.
+#[derive(Debug, Copy, Clone)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: u32,
+    elapsed: u32,
+}
+
+impl Fade {
+
+    fn amount(&self) -> f32 {
+        if self.elapsed >= self.duration {
+            return self.to;
+        }
+        let t = self.elapsed as f32 / self.duration as f32;
+        self.from + (self.to - self.from) * t
+    }
+}
+
+pub struct Pipeline<'a> {
+    pub config: PipelineConfig,
+    target: Texture<'a>,
+    fade: Fade,
+}
+
+impl<'a> Pipeline<'a> {
+
+    pub fn new<T>(config: PipelineConfig, texture_creator: &'a TextureCreator<T>) -> Result<Pipeline<'a>, String> {
+        let target = texture_creator.create_texture_target(None, config.width, config.height)
+            .map_err(|e| e.to_string())?;
+        let fade = Fade { from: 1.0, to: 0.0, duration: config.fade_in, elapsed: 0 };
+        Ok(Pipeline { config, target, fade })
+    }
+
+    // To the fade color, from the current amount. The fade in is the one of
+    // the config when the pipeline starts.
+    pub fn fade_out(&mut self, duration: u32) {
+        self.fade = Fade { from: self.fade.amount(), to: 1.0, duration, elapsed: 0 };
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.elapsed < self.fade.duration
+    }
+
+    pub fn update(&mut self, dt: u32) {
+        self.fade.elapsed = (self.fade.elapsed + dt).min(self.fade.duration);
+    }
+
+    // draw renders the frame into the target, which has the size of the
+    // config. The canvas is cleared but not presented.
+    pub fn render<T: RenderTarget, F>(&mut self, canvas: &mut Canvas<T>, draw: F) -> Result<(), String>
+        where F: FnOnce(&mut Canvas<T>) {
+        canvas.with_texture_canvas(&mut self.target, draw).map_err(|e| e.to_string())?;
+
+        // The viewport is the logical area when a resolution is applied.
+        let (out_w, out_h) = canvas.viewport().size();
+        let (r, g, b) = self.config.letterbox;
+        canvas.set_draw_color(Color::RGB(r, g, b));
+        canvas.clear();
+
+        // Copies are nearest neighbour unless the scale quality hint says
+        // otherwise, so pixel art stays sharp.
+        let dst = destination(self.config.scale, self.config.width, self.config.height, out_w, out_h);
+        let (r, g, b) = self.config.tint;
+        self.target.set_color_mod(r, g, b);
+        canvas.copy(&self.target, None, Some(dst))?;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        if let Some(scanlines) = &self.config.scanlines {
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, scanlines.intensity));
+            canvas.fill_rects(&scanline_rects(dst, scanlines.spacing))?;
+        }
+
+        let alpha = (self.fade.amount() * 255.0).round() as u8;
+        if alpha > 0 {
+            let (r, g, b) = self.config.fade_color;
+            canvas.set_draw_color(Color::RGBA(r, g, b, alpha));
+            canvas.fill_rect(None)?;
+        }
+        canvas.set_blend_mode(BlendMode::None);
+        Ok(())
+    }
+}
+
+// Where an image of src_w x src_h is drawn on an output of out_w x out_h.
+pub fn destination(mode: ScaleMode, src_w: u32, src_h: u32, out_w: u32, out_h: u32) -> Rect {
+    let (w, h) = match mode {
+        ScaleMode::Stretch => (out_w, out_h),
+        ScaleMode::Fit | ScaleMode::Expand => {
+            // Compare out_w / out_h with src_w / src_h without rounding.
+            if out_w as u64 * src_h as u64 > out_h as u64 * src_w as u64 {
+                ((src_w as u64 * out_h as u64 / src_h.max(1) as u64) as u32, out_h)
+            } else {
+                (out_w, (src_h as u64 * out_w as u64 / src_w.max(1) as u64) as u32)
+            }
+        },
+        ScaleMode::Integer => {
+            // The output may be smaller than the resolution, then the image
+            // is cropped rather than scaled down.
+            let scale = (out_w / src_w.max(1)).min(out_h / src_h.max(1)).max(1);
+            (src_w * scale, src_h * scale)
+        },
+    };
+    Rect::new((out_w as i32 - w as i32) / 2, (out_h as i32 - h as i32) / 2, w.max(1), h.max(1))
+}
+
+// Last row of every group of spacing rows of the image.
+fn scanline_rects(dst: Rect, spacing: u32) -> Vec<Rect> {
+    let spacing = spacing.max(2);
+    (1..=dst.height() / spacing)
+        .map(|i| Rect::new(dst.x(), dst.y() + (i * spacing) as i32 - 1, dst.width(), 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn scale_modes() {
+        assert_eq!(destination(ScaleMode::Stretch, 320, 180, 800, 600), Rect::new(0, 0, 800, 600));
+        // Bars above and below.
+        assert_eq!(destination(ScaleMode::Fit, 320, 180, 800, 600), Rect::new(0, 75, 800, 450));
+        // Bars on the sides.
+        assert_eq!(destination(ScaleMode::Fit, 320, 240, 1000, 600), Rect::new(100, 0, 800, 600));
+        assert_eq!(destination(ScaleMode::Integer, 320, 180, 800, 600), Rect::new(80, 120, 640, 360));
+        // Too small for the resolution: cropped, centered.
+        assert_eq!(destination(ScaleMode::Integer, 320, 180, 200, 100), Rect::new(-60, -40, 320, 180));
+    }
+
+    #[test]
+    fn output_to_target() {
+        // Drawn at (0, 75, 800, 450).
+        let config = PipelineConfig::new(320, 180);
+        assert_eq!(config.output_to_target(Vector2d::new(0.0, 75.0), 800, 600), Vector2d::new(0.0, 0.0));
+        assert_eq!(config.output_to_target(Vector2d::new(400.0, 300.0), 800, 600), Vector2d::new(160.0, 90.0));
+        assert_eq!(config.output_to_target(Vector2d::new(800.0, 0.0), 800, 600), Vector2d::new(320.0, -30.0));
+    }
+
+    #[test]
+    fn scanlines() {
+        let rects = scanline_rects(Rect::new(10, 20, 100, 9), 3);
+        assert_eq!(rects, vec![Rect::new(10, 22, 100, 1), Rect::new(10, 25, 100, 1), Rect::new(10, 28, 100, 1)]);
+    }
+
+    #[test]
+    fn fades() {
+        let mut fade = Fade { from: 1.0, to: 0.0, duration: 200, elapsed: 0 };
+        assert_eq!(fade.amount(), 1.0);
+        fade.elapsed = 50;
+        assert_eq!(fade.amount(), 0.75);
+        fade.elapsed = 200;
+        assert_eq!(fade.amount(), 0.0);
+
+        // No duration: already at the end.
+        let fade = Fade { from: 0.0, to: 1.0, duration: 0, elapsed: 0 };
+        assert_eq!(fade.amount(), 1.0);
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: PipelineConfig = serde_json::from_str(r#"{"width": 320, "height": 180, "scanlines": {}}"#).unwrap();
+        assert_eq!(config.scale, ScaleMode::Fit);
+        assert_eq!(config.tint, (255, 255, 255));
+        assert_eq!(config.scanlines, Some(Scanlines { spacing: 2, intensity: 80 }));
+        assert_eq!(config.fade_in, 0);
+    }
+}
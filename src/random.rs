@@ -0,0 +1,236 @@
+use std::f32::consts::PI;
+use serde_derive::{Serialize, Deserialize};
+
+use super::math::Vector2d;
+
+type FloatVector = Vector2d<f32>;
+
+// Small deterministic PRNG (PCG-XSH-RR 32). The same seed gives the same
+// sequence on every machine, which is what we want for level generation and
+// replays. Not suitable for anything related to security.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+const PCG_MULTIPLIER: u64 = 6364136223846793005;
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Rng {
+        let mut rng = Rng { state: 0, inc: (seed << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    // Uniform in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u32() & 1 == 1
+    }
+
+    // True with the given probability.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    // Uniform in [0, bound). Uses rejection to avoid the modulo bias.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            return 0;
+        }
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let r = self.next_u32();
+            if r >= threshold {
+                return r % bound;
+            }
+        }
+    }
+
+    // Uniform in [min, max] (both inclusive).
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        let span = (max as i64 - min as i64 + 1) as u64;
+        if span > u32::MAX as u64 {
+            return (min as i64 + (self.next_u64() % span) as i64) as i32;
+        }
+        (min as i64 + self.below(span as u32) as i64) as i32
+    }
+
+    // Uniform in [min, max).
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(&items[self.below(items.len() as u32) as usize])
+        }
+    }
+
+    // Fisher-Yates.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u32 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    // Index picked with a probability proportional to its weight. Negative
+    // weights count as zero. Returns None if all weights are zero.
+    pub fn weighted_index(&mut self, weights: &[f32]) -> Option<usize> {
+        let total: f32 = weights.iter().map(|w| w.max(0.0)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut pick = self.next_f32() * total;
+        let mut last = None;
+        for (idx, w) in weights.iter().enumerate() {
+            if *w <= 0.0 {
+                continue;
+            }
+            if pick < *w {
+                return Some(idx);
+            }
+            pick -= *w;
+            last = Some(idx);
+        }
+        // Float rounding can leave us just past the end.
+        last
+    }
+
+    pub fn weighted_choice<'a, T>(&mut self, items: &'a [(T, f32)]) -> Option<&'a T> {
+        let weights: Vec<f32> = items.iter().map(|(_, w)| *w).collect();
+        self.weighted_index(&weights).map(|idx| &items[idx].0)
+    }
+
+    // Unit vector with a uniformly distributed angle.
+    pub fn direction(&mut self) -> FloatVector {
+        self.direction_in_arc(0.0, 2.0 * PI)
+    }
+
+    // Unit vector with an angle (radians) in [min_angle, max_angle).
+    pub fn direction_in_arc(&mut self, min_angle: f32, max_angle: f32) -> FloatVector {
+        let angle = self.range_f32(min_angle, max_angle);
+        FloatVector::new(angle.cos(), angle.sin())
+    }
+
+    pub fn point_in_rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> FloatVector {
+        FloatVector::new(self.range_f32(x, x + w), self.range_f32(y, y + h))
+    }
+
+    // Uniform over the disc area (not clustered at the center).
+    pub fn point_in_circle(&mut self, center: FloatVector, radius: f32) -> FloatVector {
+        self.point_in_ring(center, 0.0, radius)
+    }
+
+    pub fn point_on_circle(&mut self, center: FloatVector, radius: f32) -> FloatVector {
+        center + self.direction() * radius
+    }
+
+    pub fn point_in_ring(&mut self, center: FloatVector, inner_radius: f32, outer_radius: f32) -> FloatVector {
+        let inner2 = inner_radius * inner_radius;
+        let outer2 = outer_radius * outer_radius;
+        let r = self.range_f32(inner2, outer2).sqrt();
+        center + self.direction() * r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let seq_a: Vec<u32> = (0..10).map(|_| a.next_u32()).collect();
+        let seq_b: Vec<u32> = (0..10).map(|_| b.next_u32()).collect();
+        let seq_c: Vec<u32> = (0..10).map(|_| c.next_u32()).collect();
+        assert_eq!(seq_a, seq_b);
+        assert_ne!(seq_a, seq_c);
+    }
+
+    #[test]
+    fn ranges_are_respected() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let i = rng.range_i32(-3, 3);
+            assert!((-3..=3).contains(&i));
+            let f = rng.range_f32(1.0, 2.0);
+            assert!((1.0..2.0).contains(&f));
+        }
+        assert_eq!(rng.range_i32(5, 5), 5);
+        // Full range must not overflow.
+        rng.range_i32(i32::MIN, i32::MAX);
+    }
+
+    #[test]
+    fn shuffle_is_a_permutation() {
+        let mut rng = Rng::new(1);
+        let mut items: Vec<u32> = (0..20).collect();
+        rng.shuffle(&mut items);
+        assert_ne!(items, (0..20).collect::<Vec<u32>>());
+        items.sort();
+        assert_eq!(items, (0..20).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn weighted_pick_skips_zero_weights() {
+        let mut rng = Rng::new(3);
+        let weights = [0.0, 1.0, 0.0, 3.0];
+        let mut counts = [0; 4];
+        for _ in 0..4000 {
+            counts[rng.weighted_index(&weights).unwrap()] += 1;
+        }
+        assert_eq!(counts[0], 0);
+        assert_eq!(counts[2], 0);
+        assert!(counts[3] > 2 * counts[1]);
+        assert_eq!(rng.weighted_index(&[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn points_stay_in_shapes() {
+        let mut rng = Rng::new(11);
+        let center = FloatVector::new(10.0, -5.0);
+        for _ in 0..500 {
+            let p = rng.point_in_circle(center, 3.0);
+            assert!(p.distance(center) <= 3.0001);
+
+            let p = rng.point_in_ring(center, 1.0, 2.0);
+            let d = p.distance(center);
+            assert!((0.9999..=2.0001).contains(&d));
+
+            let p = rng.point_in_rect(0.0, 0.0, 4.0, 2.0);
+            assert!(p.x >= 0.0 && p.x < 4.0 && p.y >= 0.0 && p.y < 2.0);
+
+            assert_eq_delta!(rng.direction().length(), 1.0, 0.0001);
+        }
+    }
+}
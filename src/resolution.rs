@@ -0,0 +1,134 @@
+// Logical resolution. The game draws in logical pixels and the canvas scale
+// and viewport map them onto the window, whatever its size.
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use serde_derive::{Serialize, Deserialize};
+
+use super::math::Vector2d;
+use super::pipeline::{destination, ScaleMode};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+    pub mode: ScaleMode,
+
+    // Current size of the window, updated by resize events.
+    #[serde(skip)]
+    window: (u32, u32),
+}
+
+impl Resolution {
+
+    // The window starts at the logical size.
+    pub fn new(width: u32, height: u32, mode: ScaleMode) -> Resolution {
+        Resolution { width, height, mode, window: (width, height) }
+    }
+
+    pub fn resize(&mut self, window_w: u32, window_h: u32) {
+        self.window = (window_w.max(1), window_h.max(1));
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window
+    }
+
+    // Expand keeps the scale of Fit and shows more of the world instead of
+    // bars, the other modes always have the configured size.
+    pub fn logical_size(&self) -> (u32, u32) {
+        match self.mode {
+            ScaleMode::Expand => {
+                let scale = self.fit_scale();
+                ((self.window.0 as f32 / scale).floor().max(1.0) as u32,
+                 (self.window.1 as f32 / scale).floor().max(1.0) as u32)
+            },
+            _ => (self.width, self.height),
+        }
+    }
+
+    fn fit_scale(&self) -> f32 {
+        (self.window.0 as f32 / self.width.max(1) as f32).min(self.window.1 as f32 / self.height.max(1) as f32)
+    }
+
+    // Where the logical area is drawn, in window pixels.
+    pub fn area(&self) -> Rect {
+        match self.mode {
+            ScaleMode::Expand => {
+                let scale = self.fit_scale();
+                let (w, h) = self.logical_size();
+                let (w, h) = ((w as f32 * scale).round() as u32, (h as f32 * scale).round() as u32);
+                Rect::new((self.window.0 as i32 - w as i32) / 2, (self.window.1 as i32 - h as i32) / 2, w, h)
+            },
+            _ => destination(self.mode, self.width, self.height, self.window.0, self.window.1),
+        }
+    }
+
+    // Window pixels per logical pixel, on each axis.
+    pub fn scale(&self) -> (f32, f32) {
+        let area = self.area();
+        let (w, h) = self.logical_size();
+        (area.width() as f32 / w as f32, area.height() as f32 / h as f32)
+    }
+
+    // Mouse positions are in window pixels.
+    pub fn window_to_logical(&self, x: i32, y: i32) -> Vector2d<f32> {
+        let area = self.area();
+        let (sx, sy) = self.scale();
+        Vector2d::new((x - area.x()) as f32 / sx, (y - area.y()) as f32 / sy)
+    }
+
+    // Sets the scale and viewport of the canvas so that (0, 0) is the top
+    // left of the logical area. Clear the canvas before, SDL clears the
+    // whole window and not the viewport.
+    pub fn apply<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
+        let (sx, sy) = self.scale();
+        let area = self.area();
+        let (w, h) = self.logical_size();
+        canvas.set_scale(sx, sy).expect("Could not set the render scale");
+        // The viewport is in scaled coordinates.
+        canvas.set_viewport(Rect::new((area.x() as f32 / sx).round() as i32, (area.y() as f32 / sy).round() as i32, w, h));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn scaling_modes() {
+        let mut resolution = Resolution::new(320, 180, ScaleMode::Stretch);
+        resolution.resize(640, 480);
+        assert_eq!(resolution.area(), Rect::new(0, 0, 640, 480));
+        assert_eq!(resolution.scale(), (2.0, 480.0 / 180.0));
+
+        resolution.mode = ScaleMode::Fit;
+        assert_eq!(resolution.area(), Rect::new(0, 60, 640, 360));
+        assert_eq!(resolution.scale(), (2.0, 2.0));
+
+        resolution.resize(1000, 600);
+        resolution.mode = ScaleMode::Integer;
+        assert_eq!(resolution.area(), Rect::new(20, 30, 960, 540));
+        assert_eq!(resolution.logical_size(), (320, 180));
+
+        // Same scale as Fit, the extra space shows more of the world.
+        resolution.mode = ScaleMode::Expand;
+        resolution.resize(640, 480);
+        assert_eq!(resolution.logical_size(), (320, 240));
+        assert_eq!(resolution.area(), Rect::new(0, 0, 640, 480));
+    }
+
+    #[test]
+    fn mouse_to_logical() {
+        let mut resolution = Resolution::new(320, 180, ScaleMode::Fit);
+        resolution.resize(640, 480);
+        assert_eq!(resolution.window_to_logical(0, 60), Vector2d::new(0.0, 0.0));
+        assert_eq!(resolution.window_to_logical(320, 240), Vector2d::new(160.0, 90.0));
+        // In the bars: outside of the logical area.
+        assert_eq!(resolution.window_to_logical(0, 0).y, -30.0);
+
+        // Before any resize the window has the logical size.
+        let resolution = Resolution::new(800, 600, ScaleMode::Integer);
+        assert_eq!(resolution.window_to_logical(10, 20), Vector2d::new(10.0, 20.0));
+    }
+}
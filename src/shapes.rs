@@ -0,0 +1,438 @@
+// Vector shapes drawn in software: every shape is turned into a polygon in
+// screen space, rasterized into horizontal spans and filled with rectangles,
+// so it works with any renderer and follows the zoom and rotation of the
+// camera.
+use std::f32::consts::PI;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use serde_derive::{Serialize, Deserialize};
+
+use super::core::View;
+use super::math::Vector2d;
+use super::tween::Interpolate;
+
+type FloatVector = Vector2d<f32>;
+
+fn default_true() -> bool {
+    true
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ShapeKind {
+    // Ellipse filling the box of the object, a circle when it is square.
+    Circle,
+    RoundedRect { radius: f32 },
+    // Closed, relative to the top left of the object.
+    Polygon { points: Vec<(f32, f32)> },
+    // Open, relative to the top left of the object. Drawn with the fill.
+    Line { points: Vec<(f32, f32)>, thickness: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Paint {
+    Solid((u8, u8, u8, u8)),
+    // From one color to the other across the box of the object, in the
+    // direction given in degrees: 0 is left to right, 90 top to bottom.
+    Linear { from: (u8, u8, u8, u8), to: (u8, u8, u8, u8), angle: f32 },
+    // From the center of the box to its sides.
+    Radial { inner: (u8, u8, u8, u8), outer: (u8, u8, u8, u8) },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outline {
+    pub color: (u8, u8, u8, u8),
+    // In world units.
+    #[serde(default = "one")]
+    pub thickness: f32,
+}
+
+// Drawn instead of the rectangle of a game object, in its box. The physics
+// still sees the box.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorShape {
+    pub kind: ShapeKind,
+    // The color of the object when not set.
+    #[serde(default)]
+    pub fill: Option<Paint>,
+    #[serde(default = "default_true")]
+    pub filled: bool,
+    #[serde(default)]
+    pub outline: Option<Outline>,
+}
+
+// Row y of pixels from x0 included to x1 excluded.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    pub y: i32,
+    pub x0: i32,
+    pub x1: i32,
+}
+
+impl VectorShape {
+
+    pub fn new(kind: ShapeKind) -> VectorShape {
+        VectorShape { kind, fill: None, filled: true, outline: None }
+    }
+
+    // Contour of the shape in world units, for a box at (x, y). Arcs get
+    // more segments as they get bigger on screen, zoom is the scale.
+    fn contour(&self, x: f32, y: f32, w: f32, h: f32, zoom: f32) -> Vec<FloatVector> {
+        let local = |points: &[(f32, f32)]| points.iter().map(|&(px, py)| FloatVector::new(x + px, y + py)).collect();
+        match &self.kind {
+            ShapeKind::Circle => {
+                let (rx, ry) = (w / 2.0, h / 2.0);
+                ellipse(FloatVector::new(x + rx, y + ry), rx, ry, arc_segments(rx.max(ry) * zoom) * 4)
+            },
+            ShapeKind::RoundedRect { radius } => rounded_rect(x, y, w, h, *radius, arc_segments(radius * zoom)),
+            ShapeKind::Polygon { points } | ShapeKind::Line { points, .. } => local(points),
+        }
+    }
+
+    // World rectangle (x, y, w, h) drawn for a box at (x, y), outline
+    // included.
+    pub fn bounds(&self, x: f32, y: f32, w: f32, h: f32) -> (f32, f32, f32, f32) {
+        let contour = self.contour(x, y, w, h, 1.0);
+        if contour.is_empty() {
+            return (x, y, w, h);
+        }
+        let line = match &self.kind {
+            ShapeKind::Line { thickness, .. } => *thickness,
+            _ => 0.0,
+        };
+        let margin = self.outline.as_ref().map(|o| o.thickness).unwrap_or(0.0).max(line) / 2.0;
+        let (mut x0, mut y0, mut x1, mut y1) = (contour[0].x, contour[0].y, contour[0].x, contour[0].y);
+        for p in contour.iter() {
+            x0 = x0.min(p.x);
+            y0 = y0.min(p.y);
+            x1 = x1.max(p.x);
+            y1 = y1.max(p.y);
+        }
+        (x0 - margin, y0 - margin, x1 - x0 + 2.0 * margin, y1 - y0 + 2.0 * margin)
+    }
+
+    // area is the box (x, y, w, h) of the object.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &View, area: (f32, f32, f32, f32),
+                                   color: Color) {
+        let (x, y, w, h) = area;
+        let contour: Vec<FloatVector> = self.contour(x, y, w, h, view.zoom).into_iter()
+            .map(|p| view.world_to_screen(p))
+            .collect();
+        let paint = self.fill.clone().unwrap_or(Paint::Solid((color.r, color.g, color.b, color.a)));
+
+        let blend = canvas.blend_mode();
+        canvas.set_blend_mode(BlendMode::Blend);
+        match &self.kind {
+            ShapeKind::Line { thickness, .. } => {
+                let spans = stroke_spans(&contour, thickness * view.zoom, false, view.viewport);
+                fill_spans(canvas, &spans, &paint, view, area);
+            },
+            _ => if self.filled {
+                fill_spans(canvas, &polygon_spans(&contour, view.viewport), &paint, view, area);
+            },
+        }
+        if let Some(outline) = &self.outline {
+            let closed = !matches!(self.kind, ShapeKind::Line { .. });
+            let spans = stroke_spans(&contour, outline.thickness * view.zoom, closed, view.viewport);
+            fill_spans(canvas, &spans, &Paint::Solid(outline.color), view, area);
+        }
+        canvas.set_blend_mode(blend);
+    }
+}
+
+impl Paint {
+
+    // Color at the world position p, for an object with the box area.
+    fn color_at(&self, p: FloatVector, area: (f32, f32, f32, f32)) -> Color {
+        let (x, y, w, h) = area;
+        let (from, to, t) = match *self {
+            Paint::Solid((r, g, b, a)) => return Color::RGBA(r, g, b, a),
+            Paint::Linear { from, to, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                // Projections of the corners give the extent of the box
+                // along the gradient.
+                let along = |px: f32, py: f32| px * cos + py * sin;
+                let corners = [along(x, y), along(x + w, y), along(x, y + h), along(x + w, y + h)];
+                let min = corners.iter().cloned().fold(f32::MAX, f32::min);
+                let max = corners.iter().cloned().fold(f32::MIN, f32::max);
+                (from, to, (along(p.x, p.y) - min) / (max - min).max(0.0001))
+            },
+            Paint::Radial { inner, outer } => {
+                let (rx, ry) = ((w / 2.0).max(0.0001), (h / 2.0).max(0.0001));
+                let (dx, dy) = ((p.x - x - rx) / rx, (p.y - y - ry) / ry);
+                (inner, outer, (dx * dx + dy * dy).sqrt())
+            },
+        };
+        let (r, g, b, a) = from.interpolate(&to, t.clamp(0.0, 1.0));
+        Color::RGBA(r, g, b, a)
+    }
+}
+
+// Enough segments for a quarter of a circle of this radius in pixels to
+// look round.
+fn arc_segments(radius: f32) -> usize {
+    (radius.max(0.0).sqrt() * 1.5).ceil().clamp(2.0, 32.0) as usize
+}
+
+fn ellipse(center: FloatVector, rx: f32, ry: f32, segments: usize) -> Vec<FloatVector> {
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 * 2.0 * PI / segments as f32;
+            center + FloatVector::new(angle.cos() * rx, angle.sin() * ry)
+        })
+        .collect()
+}
+
+// Clockwise from the top left corner, radius limited to half of the
+// smallest side.
+fn rounded_rect(x: f32, y: f32, w: f32, h: f32, radius: f32, segments: usize) -> Vec<FloatVector> {
+    let r = radius.max(0.0).min(w / 2.0).min(h / 2.0);
+    if r <= 0.0 {
+        return vec![FloatVector::new(x, y), FloatVector::new(x + w, y),
+                    FloatVector::new(x + w, y + h), FloatVector::new(x, y + h)];
+    }
+    // Corner centers, with the angle where their arc starts.
+    let corners = [(x + r, y + r, PI), (x + w - r, y + r, 1.5 * PI),
+                   (x + w - r, y + h - r, 0.0), (x + r, y + h - r, 0.5 * PI)];
+    corners.iter()
+        .flat_map(|&(cx, cy, start)| (0..=segments).map(move |i| {
+            let angle = start + i as f32 * 0.5 * PI / segments as f32;
+            FloatVector::new(cx + angle.cos() * r, cy + angle.sin() * r)
+        }))
+        .collect()
+}
+
+// Pixels with their center inside the polygon, using the even-odd rule so
+// that concave and crossing polygons work. Only the pixels inside clip are
+// kept, so that a huge shape costs no more than the screen.
+pub fn polygon_spans(points: &[FloatVector], clip: Rect) -> Vec<Span> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    let min_y = points.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+    let max_y = points.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+    let first_row = ((min_y - 0.5).ceil() as i32).max(clip.top());
+    let last_row = ((max_y - 0.5).ceil() as i32).min(clip.bottom());
+
+    let mut spans = Vec::new();
+    let mut crossings = Vec::new();
+    for y in first_row..last_row {
+        let center = y as f32 + 0.5;
+        crossings.clear();
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            // Half open so that a vertex shared by two edges counts once.
+            if (a.y <= center) != (b.y <= center) {
+                crossings.push(a.x + (center - a.y) * (b.x - a.x) / (b.y - a.y));
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for pair in crossings.chunks_exact(2) {
+            let x0 = ((pair[0] - 0.5).ceil() as i32).max(clip.left());
+            let x1 = ((pair[1] - 0.5).ceil() as i32).min(clip.right());
+            if x1 > x0 {
+                spans.push(Span { y, x0, x1 });
+            }
+        }
+    }
+    spans
+}
+
+// Sorted by row, overlapping spans joined, so that every pixel is drawn
+// once even with transparency.
+fn merge_spans(mut spans: Vec<Span>) -> Vec<Span> {
+    spans.sort_by_key(|s| (s.y, s.x0));
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.y == span.y && span.x0 <= last.x1 => last.x1 = last.x1.max(span.x1),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+// Line of the given thickness through the points, with round joins, clipped
+// like polygon_spans.
+pub fn stroke_spans(points: &[FloatVector], thickness: f32, closed: bool, clip: Rect) -> Vec<Span> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+    let half = thickness.max(1.0) / 2.0;
+    let count = if closed { points.len() } else { points.len() - 1 };
+    let mut spans = Vec::new();
+    for i in 0..count {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        let dir = (b - a).normalized();
+        let side = FloatVector::new(-dir.y, dir.x) * half;
+        spans.extend(polygon_spans(&[a + side, b + side, b - side, a - side], clip));
+    }
+    if half >= 1.5 {
+        let joins = if closed { points } else { &points[1..points.len() - 1] };
+        for p in joins {
+            spans.extend(polygon_spans(&ellipse(*p, half, half, arc_segments(half) * 4), clip));
+        }
+    }
+    merge_spans(spans)
+}
+
+// Solid paints are one call, gradients one call per color.
+fn fill_spans<T: RenderTarget>(canvas: &mut Canvas<T>, spans: &[Span], paint: &Paint, view: &View,
+                               area: (f32, f32, f32, f32)) {
+    if spans.is_empty() {
+        return;
+    }
+    if let Paint::Solid((r, g, b, a)) = *paint {
+        let rects: Vec<Rect> = spans.iter().map(|s| Rect::new(s.x0, s.y, (s.x1 - s.x0) as u32, 1)).collect();
+        canvas.set_draw_color(Color::RGBA(r, g, b, a));
+        canvas.fill_rects(&rects).expect("Shape render failed");
+        return;
+    }
+
+    // Runs of pixels of the same color.
+    let mut runs: Vec<(u32, Rect)> = Vec::new();
+    for span in spans {
+        let mut start = span.x0;
+        let mut current = None;
+        for x in span.x0..=span.x1 {
+            let color = if x < span.x1 {
+                let p = view.screen_to_world(FloatVector::new(x as f32 + 0.5, span.y as f32 + 0.5));
+                Some(paint.color_at(p, area))
+            } else {
+                None
+            };
+            if color != current {
+                if let Some(c) = current {
+                    runs.push((color_key(c), Rect::new(start, span.y, (x - start) as u32, 1)));
+                }
+                start = x;
+                current = color;
+            }
+        }
+    }
+    runs.sort_by_key(|run| run.0);
+    let mut start = 0;
+    while start < runs.len() {
+        let key = runs[start].0;
+        let end = runs[start..].iter().position(|run| run.0 != key).map(|n| start + n).unwrap_or(runs.len());
+        let rects: Vec<Rect> = runs[start..end].iter().map(|run| run.1).collect();
+        let c = key.to_be_bytes();
+        canvas.set_draw_color(Color::RGBA(c[0], c[1], c[2], c[3]));
+        canvas.fill_rects(&rects).expect("Shape render failed");
+        start = end;
+    }
+}
+
+fn color_key(c: Color) -> u32 {
+    u32::from_be_bytes([c.r, c.g, c.b, c.a])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Vec<FloatVector> {
+        vec![FloatVector::new(x, y), FloatVector::new(x + size, y),
+             FloatVector::new(x + size, y + size), FloatVector::new(x, y + size)]
+    }
+
+    fn everywhere() -> Rect {
+        Rect::new(-1000, -1000, 2000, 2000)
+    }
+
+    #[test]
+    fn rasterize_polygons() {
+        let spans = polygon_spans(&square(0.0, 0.0, 4.0), everywhere());
+        assert_eq!(spans.len(), 4);
+        assert!(spans.iter().all(|s| s.x0 == 0 && s.x1 == 4));
+
+        // Pixel centers on the top and left edges are inside, on the bottom
+        // and right edges outside, so that shapes sharing an edge do not
+        // overlap.
+        let spans = polygon_spans(&square(0.5, 0.5, 2.0), everywhere());
+        assert_eq!(spans, vec![Span { y: 0, x0: 0, x1: 2 }, Span { y: 1, x0: 0, x1: 2 }]);
+
+        // Concave: a U has two spans on its upper rows.
+        let u = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (4.0, 2.0), (4.0, 0.0), (6.0, 0.0), (6.0, 4.0), (0.0, 4.0)];
+        let u: Vec<FloatVector> = u.iter().map(|&(x, y)| FloatVector::new(x, y)).collect();
+        let spans = polygon_spans(&u, everywhere());
+        assert_eq!(spans[0], Span { y: 0, x0: 0, x1: 2 });
+        assert_eq!(spans[1], Span { y: 0, x0: 4, x1: 6 });
+        assert_eq!(spans.last(), Some(&Span { y: 3, x0: 0, x1: 6 }));
+
+        assert!(polygon_spans(&u[..2], everywhere()).is_empty());
+    }
+
+    #[test]
+    fn clipped_spans() {
+        let huge = square(-1.0e6, -1.0e6, 2.0e6);
+        let spans = polygon_spans(&huge, Rect::new(10, 20, 30, 2));
+        assert_eq!(spans, vec![Span { y: 20, x0: 10, x1: 40 }, Span { y: 21, x0: 10, x1: 40 }]);
+
+        // Outside of the clip.
+        assert!(polygon_spans(&square(0.0, 0.0, 4.0), Rect::new(10, 0, 10, 10)).is_empty());
+        let line = [FloatVector::new(-50.0, 5.0), FloatVector::new(50.0, 5.0)];
+        let spans = stroke_spans(&line, 4.0, false, Rect::new(0, 0, 10, 10));
+        assert!(spans.iter().all(|s| s.x0 == 0 && s.x1 == 10));
+    }
+
+    #[test]
+    fn circle_area() {
+        let shape = VectorShape::new(ShapeKind::Circle);
+        let contour = shape.contour(0.0, 0.0, 40.0, 40.0, 1.0);
+        let area: i32 = polygon_spans(&contour, everywhere()).iter().map(|s| s.x1 - s.x0).sum();
+        let expected = PI * 20.0 * 20.0;
+        assert!((area as f32 - expected).abs() < expected * 0.02);
+        let (x, y, w, h) = shape.bounds(0.0, 0.0, 40.0, 40.0);
+        assert_eq_delta!(x, 0.0, 0.001);
+        assert_eq_delta!(y, 0.0, 0.001);
+        assert_eq_delta!(w, 40.0, 0.001);
+        assert_eq_delta!(h, 40.0, 0.001);
+    }
+
+    #[test]
+    fn rounded_corners() {
+        let contour = rounded_rect(0.0, 0.0, 20.0, 10.0, 50.0, 4);
+        // Radius limited to 5: the corners are cut, the middle is full.
+        let spans = polygon_spans(&contour, everywhere());
+        assert!(spans[0].x0 > 0 && spans[0].x1 < 20);
+        assert_eq!(spans[5], Span { y: 5, x0: 0, x1: 20 });
+        assert_eq!(rounded_rect(0.0, 0.0, 20.0, 10.0, 0.0, 4).len(), 4);
+    }
+
+    #[test]
+    fn thick_strokes() {
+        let line = [FloatVector::new(0.0, 5.0), FloatVector::new(10.0, 5.0)];
+        let spans = stroke_spans(&line, 4.0, false, everywhere());
+        assert_eq!(spans.len(), 4);
+        assert!(spans.iter().all(|s| s.x0 == 0 && s.x1 == 10));
+
+        // The outline of a square is a frame: two spans on the middle rows,
+        // none of them overlapping.
+        let spans = stroke_spans(&square(0.0, 0.0, 10.0), 2.0, true, everywhere());
+        let middle: Vec<&Span> = spans.iter().filter(|s| s.y == 5).collect();
+        assert_eq!(middle, vec![&Span { y: 5, x0: -1, x1: 1 }, &Span { y: 5, x0: 9, x1: 11 }]);
+    }
+
+    #[test]
+    fn gradients() {
+        let area = (0.0, 0.0, 100.0, 50.0);
+        let linear = Paint::Linear { from: (0, 0, 0, 255), to: (200, 100, 0, 255), angle: 0.0 };
+        assert_eq!(linear.color_at(FloatVector::new(0.0, 10.0), area), Color::RGBA(0, 0, 0, 255));
+        assert_eq!(linear.color_at(FloatVector::new(50.0, 40.0), area), Color::RGBA(100, 50, 0, 255));
+        assert_eq!(linear.color_at(FloatVector::new(150.0, 0.0), area), Color::RGBA(200, 100, 0, 255));
+
+        let vertical = Paint::Linear { from: (0, 0, 0, 0), to: (0, 0, 0, 255), angle: 90.0 };
+        assert_eq!(vertical.color_at(FloatVector::new(70.0, 30.0), area).a, 153);
+
+        let radial = Paint::Radial { inner: (255, 255, 255, 255), outer: (0, 0, 0, 255) };
+        assert_eq!(radial.color_at(FloatVector::new(50.0, 25.0), area), Color::RGBA(255, 255, 255, 255));
+        assert_eq!(radial.color_at(FloatVector::new(100.0, 25.0), area), Color::RGBA(0, 0, 0, 255));
+    }
+}
@@ -0,0 +1,309 @@
+// Golden image testing. Render something with a headless context, turn the
+// surface into a Snapshot and compare it with the image stored under
+// tests/golden. On mismatch the actual image and a diff image are written
+// under target/snapshots so they can be inspected.
+//
+// Run the tests with UPDATE_SNAPSHOTS=1 to (re)write the golden images.
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::surface::{Surface, SurfaceRef};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::context::{Context, HeadlessContext};
+use super::assets::AssetManager;
+use super::core::Scene;
+use super::debug_draw;
+use super::editor::Editor;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<Color>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Tolerance {
+    // Max difference allowed on each channel before a pixel is a mismatch.
+    pub channel: u8,
+    // Number of mismatching pixels allowed.
+    pub max_mismatched: usize,
+}
+
+impl Tolerance {
+    pub fn exact() -> Tolerance {
+        Tolerance { channel: 0, max_mismatched: 0 }
+    }
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance { channel: 2, max_mismatched: 0 }
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(String),
+    SizeMismatch { expected: (u32, u32), actual: (u32, u32) },
+    Mismatch { mismatched: usize, total: usize, diff_path: Option<PathBuf> },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot io error: {}", e),
+            SnapshotError::SizeMismatch { expected, actual } =>
+                write!(f, "snapshot is {}x{} but golden image is {}x{}",
+                       actual.0, actual.1, expected.0, expected.1),
+            SnapshotError::Mismatch { mismatched, total, diff_path } => {
+                write!(f, "{} of {} pixels differ from the golden image", mismatched, total)?;
+                if let Some(path) = diff_path {
+                    write!(f, " (diff written to {})", path.display())?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Snapshot {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Snapshot { width, height, pixels }
+    }
+
+    pub fn from_surface(surface: &SurfaceRef) -> Snapshot {
+        let format = surface.pixel_format();
+        let bpp = surface.pixel_format_enum().byte_size_per_pixel();
+        let (width, height, pitch) = (surface.width(), surface.height(), surface.pitch());
+
+        let pixels = surface.with_lock(|data| {
+            let mut pixels = Vec::with_capacity((width * height) as usize);
+            for y in 0..height {
+                for x in 0..width {
+                    let offset = (y * pitch) as usize + x as usize * bpp;
+                    let mut bytes = [0; 4];
+                    bytes[..bpp].copy_from_slice(&data[offset..offset + bpp]);
+                    let raw = if bpp == 4 { u32::from_ne_bytes(bytes) } else { u32::from_le_bytes(bytes) };
+                    pixels.push(Color::from_u32(&format, raw));
+                }
+            }
+            pixels
+        });
+
+        Snapshot { width, height, pixels }
+    }
+
+    // What has been rendered so far.
+    pub fn of_context(context: &HeadlessContext) -> Snapshot {
+        Snapshot::from_surface(context.canvas.surface())
+    }
+
+    // Renders the scene on a black background.
+    pub fn of_scene(scene: &Scene, width: u32, height: u32) -> Snapshot {
+        let mut context = Context::headless(width, height).expect("Could not create headless context");
+        let texture_creator = context.canvas.texture_creator();
+        let mut assets = AssetManager::new(manifest_dir(), &texture_creator, &context.font_context);
+
+        context.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        context.canvas.clear();
+        scene.render(&mut context.canvas, &mut assets);
+        context.canvas.present();
+        Snapshot::of_context(&context)
+    }
+
+    // Renders a frame of the editor at the window size of its resolution.
+    // No font is set so text is drawn with the missing glyph boxes, which
+    // keeps the golden images independent of the FreeType version.
+    pub fn of_editor(editor: &Editor) -> Snapshot {
+        let (width, height) = editor.resolution.window_size();
+        let mut context = Context::headless(width, height).expect("Could not create headless context");
+        let texture_creator = context.canvas.texture_creator();
+        let mut assets = AssetManager::new(manifest_dir(), &texture_creator, &context.font_context);
+
+        // Left by other tests of this thread.
+        debug_draw::clear();
+        editor.render(&mut context.canvas, &mut assets);
+        Snapshot::of_context(&context)
+    }
+
+    pub fn load_bmp<P: AsRef<Path>>(path: P) -> Result<Snapshot, String> {
+        let surface = Surface::load_bmp(path)?;
+        Ok(Snapshot::from_surface(&surface))
+    }
+
+    pub fn save_bmp<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let mut surface = Surface::new(self.width, self.height, PixelFormatEnum::RGBA8888)?;
+        let format = surface.pixel_format();
+        let pitch = surface.pitch() as usize;
+        let width = self.width as usize;
+
+        surface.with_lock_mut(|data| {
+            for (idx, color) in self.pixels.iter().enumerate() {
+                let offset = (idx / width) * pitch + (idx % width) * 4;
+                data[offset..offset + 4].copy_from_slice(&color.to_u32(&format).to_ne_bytes());
+            }
+        });
+        surface.save_bmp(path)
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    // Compares with the expected image. Returns the number of mismatched
+    // pixels and an image where they are red, matching pixels are a darker
+    // version of the expected image.
+    pub fn diff(&self, expected: &Snapshot, channel_tolerance: u8) -> Result<(usize, Snapshot), SnapshotError> {
+        if self.width != expected.width || self.height != expected.height {
+            return Err(SnapshotError::SizeMismatch {
+                expected: (expected.width, expected.height),
+                actual: (self.width, self.height),
+            });
+        }
+
+        let mut mismatched = 0;
+        let delta = |a: u8, b: u8| (a as i16 - b as i16).unsigned_abs() as u8;
+        let pixels = self.pixels.iter().zip(expected.pixels.iter()).map(|(a, e)| {
+            let max_delta = delta(a.r, e.r).max(delta(a.g, e.g)).max(delta(a.b, e.b)).max(delta(a.a, e.a));
+            if max_delta > channel_tolerance {
+                mismatched += 1;
+                Color::RGB(255, 0, 0)
+            } else {
+                Color::RGB(e.r / 3, e.g / 3, e.b / 3)
+            }
+        }).collect();
+
+        Ok((mismatched, Snapshot::new(self.width, self.height, pixels)))
+    }
+
+    pub fn compare(&self, golden: &Path, tolerance: Tolerance) -> Result<(), SnapshotError> {
+        let expected = Snapshot::load_bmp(golden).map_err(SnapshotError::Io)?;
+        let (mismatched, diff) = self.diff(&expected, tolerance.channel)?;
+        if mismatched <= tolerance.max_mismatched {
+            return Ok(());
+        }
+
+        let name = golden.file_stem().unwrap().to_string_lossy().into_owned();
+        let out_dir = manifest_dir().join("target").join("snapshots");
+        let diff_path = out_dir.join(format!("{}.diff.bmp", name));
+        let written = fs::create_dir_all(&out_dir).is_ok()
+            && self.save_bmp(out_dir.join(format!("{}.actual.bmp", name))).is_ok()
+            && diff.save_bmp(&diff_path).is_ok();
+
+        Err(SnapshotError::Mismatch {
+            mismatched,
+            total: self.pixels.len(),
+            diff_path: if written { Some(diff_path) } else { None },
+        })
+    }
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+pub fn golden_path(name: &str) -> PathBuf {
+    manifest_dir().join("tests").join("golden").join(format!("{}.bmp", name))
+}
+
+// Panics if the snapshot does not match tests/golden/<name>.bmp.
+pub fn assert_snapshot(snapshot: &Snapshot, name: &str, tolerance: Tolerance) {
+    let golden = golden_path(name);
+    if env::var("UPDATE_SNAPSHOTS").is_ok() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        snapshot.save_bmp(&golden).unwrap();
+        return;
+    }
+
+    if !golden.exists() {
+        panic!("no golden image for '{}', run with UPDATE_SNAPSHOTS=1 to create {}",
+               name, golden.display());
+    }
+
+    if let Err(e) = snapshot.compare(&golden, tolerance) {
+        panic!("snapshot '{}': {}", name, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::core::Text;
+    use crate::pipeline::ScaleMode;
+    use crate::resolution::Resolution;
+
+    fn solid(width: u32, height: u32, color: Color) -> Snapshot {
+        Snapshot::new(width, height, vec![color; (width * height) as usize])
+    }
+
+    #[test]
+    fn diff_with_tolerance() {
+        let expected = solid(4, 4, Color::RGB(100, 100, 100));
+        let mut actual = expected.clone();
+        actual.pixels[5] = Color::RGB(102, 100, 100);
+        actual.pixels[6] = Color::RGB(100, 110, 100);
+
+        let (mismatched, diff) = actual.diff(&expected, 2).unwrap();
+        assert_eq!(mismatched, 1);
+        assert_eq!(diff.pixel(2, 1), Color::RGB(255, 0, 0));
+        assert_eq!(diff.pixel(1, 1), Color::RGB(33, 33, 33));
+
+        let (mismatched, _) = actual.diff(&expected, 0).unwrap();
+        assert_eq!(mismatched, 2);
+    }
+
+    #[test]
+    fn diff_size_mismatch() {
+        let a = solid(4, 4, Color::RGB(0, 0, 0));
+        let b = solid(4, 3, Color::RGB(0, 0, 0));
+        match a.diff(&b, 0) {
+            Err(SnapshotError::SizeMismatch { expected: (4, 3), actual: (4, 4) }) => {},
+            other => panic!("unexpected {:?}", other.map(|r| r.0)),
+        }
+    }
+
+    #[test]
+    fn scene_matches_golden() {
+        let mut scene = Scene::new();
+        scene.add_static(10.0, 8.0, 20, 12, Color::RGB(200, 50, 50));
+        scene.add_rect(40.0, 30.0, 10, 10, Color::RGB(50, 50, 200));
+        scene.camera.position.x = 4.0;
+        scene.camera.position.y = 2.0;
+
+        let snapshot = Snapshot::of_scene(&scene, 64, 48);
+        assert_snapshot(&snapshot, "scene_rects", Tolerance::default());
+    }
+
+    #[test]
+    fn text_matches_golden() {
+        let mut context = Context::headless(48, 20).unwrap();
+        let texture_creator = context.canvas.texture_creator();
+        let mut assets = AssetManager::new(manifest_dir(), &texture_creator, &context.font_context);
+
+        // Without fonts every character is the 8x16 missing glyph box.
+        let text = Text { content: "ab!?".to_string(), color: Color::RGB(255, 200, 0) };
+        context.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        context.canvas.clear();
+        text.render(&mut context.canvas, &mut assets, 3, 2);
+        context.canvas.present();
+        assert_snapshot(&Snapshot::of_context(&context), "text_missing_glyphs", Tolerance::exact());
+    }
+
+    #[test]
+    fn editor_frame_matches_golden() {
+        let mut editor = Editor::new();
+        editor.resolution = Resolution::new(500, 60, ScaleMode::Expand);
+        editor.current_scene.add_static(60.0, 30.0, 40, 20, Color::RGB(0, 200, 0));
+
+        let snapshot = Snapshot::of_editor(&editor);
+        assert_snapshot(&snapshot, "editor_frame", Tolerance::exact());
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+// Uniform grid over the world. Each cell lists the items overlapping it so
+// that finding what is inside a rectangle only looks at the nearby cells.
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    // Cells overlapped by each item, see cell_range.
+    ranges: HashMap<usize, (i32, i32, i32, i32)>,
+}
+
+impl SpatialGrid {
+
+    pub fn new(cell_size: f32) -> SpatialGrid {
+        SpatialGrid { cell_size: cell_size.max(1.0), cells: HashMap::new(), ranges: HashMap::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.ranges.clear();
+    }
+
+    // Cells overlapping the rectangle: (first col, first row, last col, last
+    // row), inclusive.
+    fn cell_range(&self, x: f32, y: f32, w: f32, h: f32) -> (i32, i32, i32, i32) {
+        let cell = |v: f32| (v / self.cell_size).floor() as i32;
+        // A rectangle ending exactly on a cell border does not overlap the
+        // next cell.
+        let last = |start: f32, size: f32| if size > 0.0 { cell(start + size - 0.001) } else { cell(start) };
+        (cell(x), cell(y), last(x, w).max(cell(x)), last(y, h).max(cell(y)))
+    }
+
+    // Adds the item, or moves it if it is already in the grid. Nothing changes
+    // while it stays in the same cells, so moving items can be inserted
+    // again every frame.
+    pub fn insert(&mut self, item: usize, x: f32, y: f32, w: f32, h: f32) {
+        let range = self.cell_range(x, y, w, h);
+        match self.ranges.insert(item, range) {
+            Some(previous) if previous == range => return,
+            Some((c0, r0, c1, r1)) => {
+                for row in r0..=r1 {
+                    for col in c0..=c1 {
+                        if let Some(cell) = self.cells.get_mut(&(col, row)) {
+                            cell.retain(|&i| i != item);
+                        }
+                    }
+                }
+            },
+            None => {},
+        }
+
+        let (c0, r0, c1, r1) = range;
+        for row in r0..=r1 {
+            for col in c0..=c1 {
+                self.cells.entry((col, row)).or_default().push(item);
+            }
+        }
+    }
+
+    // Items in the cells overlapping the rectangle, sorted and without
+    // duplicates. They may not overlap the rectangle itself.
+    pub fn query(&self, x: f32, y: f32, w: f32, h: f32) -> Vec<usize> {
+        let (c0, r0, c1, r1) = self.cell_range(x, y, w, h);
+        let mut items = Vec::new();
+        for row in r0..=r1 {
+            for col in c0..=c1 {
+                if let Some(cell) = self.cells.get(&(col, row)) {
+                    items.extend_from_slice(cell);
+                }
+            }
+        }
+        items.sort_unstable();
+        items.dedup();
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn query_nearby_cells() {
+        let mut grid = SpatialGrid::new(100.0);
+        grid.insert(0, 10.0, 10.0, 20.0, 20.0);
+        // Spans four cells.
+        grid.insert(1, 90.0, 90.0, 20.0, 20.0);
+        grid.insert(2, 1000.0, 1000.0, 10.0, 10.0);
+        grid.insert(3, -50.0, 0.0, 50.0, 10.0);
+
+        assert_eq!(grid.query(0.0, 0.0, 100.0, 100.0), vec![0, 1]);
+        assert_eq!(grid.query(150.0, 150.0, 10.0, 10.0), vec![1]);
+        assert_eq!(grid.query(-10.0, 0.0, 5.0, 5.0), vec![3]);
+        assert_eq!(grid.query(900.0, 900.0, 200.0, 200.0), vec![2]);
+        assert!(grid.query(400.0, 400.0, 100.0, 100.0).is_empty());
+
+        // Moved out of the first cell.
+        grid.insert(0, 150.0, 10.0, 20.0, 20.0);
+        assert_eq!(grid.query(0.0, 0.0, 50.0, 50.0), vec![1]);
+        assert_eq!(grid.query(150.0, 10.0, 10.0, 10.0), vec![0, 1]);
+
+        grid.clear();
+        assert!(grid.query(0.0, 0.0, 100.0, 100.0).is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{Canvas, RenderTarget, Texture};
+use std::collections::HashMap;
+use serde_derive::{Serialize, Deserialize};
+
+use super::core::rotate_around;
+
+// Part of a texture, in pixels.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Region {
+
+    pub fn new(x: i32, y: i32, w: u32, h: u32) -> Region {
+        Region { x, y, w, h }
+    }
+
+    pub fn to_rect(self) -> Rect {
+        Rect::new(self.x, self.y, self.w, self.h)
+    }
+}
+
+// Packers remove the transparent border of the images (trimming) and can
+// store them rotated 90 degrees clockwise to save space. This is what is
+// needed to draw the region back at the right place in the original image.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct Trim {
+    // Position of the trimmed region in the original image.
+    pub x: i32,
+    pub y: i32,
+    // Size of the original image.
+    pub source_w: u32,
+    pub source_h: u32,
+    #[serde(default)]
+    pub rotated: bool,
+}
+
+// Named rectangle drawn in the editor of the artist, usually a hitbox or a
+// 9-slice definition.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Slice {
+    pub bounds: Region,
+    // Center part for 9-slices, relative to the bounds.
+    #[serde(default)]
+    pub center: Option<Region>,
+    #[serde(default)]
+    pub pivot: Option<(i32, i32)>,
+}
+
+// Named regions of a single texture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Atlas {
+    pub texture: String,
+    pub regions: HashMap<String, Region>,
+    // Only for the regions that are trimmed or rotated.
+    #[serde(default)]
+    pub trims: HashMap<String, Trim>,
+    #[serde(default)]
+    pub slices: HashMap<String, Slice>,
+}
+
+impl Atlas {
+
+    pub fn new(texture: &str) -> Atlas {
+        Atlas {
+            texture: texture.to_string(),
+            regions: HashMap::new(),
+            trims: HashMap::new(),
+            slices: HashMap::new(),
+        }
+    }
+
+    // Cuts the texture in a grid of cells named "<prefix><index>", row by row.
+    pub fn from_grid(texture: &str, prefix: &str, columns: u32, rows: u32, cell_w: u32, cell_h: u32) -> Atlas {
+        let mut atlas = Atlas::new(texture);
+        for row in 0..rows {
+            for col in 0..columns {
+                let name = format!("{}{}", prefix, row * columns + col);
+                let region = Region::new((col * cell_w) as i32, (row * cell_h) as i32, cell_w, cell_h);
+                atlas.regions.insert(name, region);
+            }
+        }
+        atlas
+    }
+
+    pub fn region(&self, name: &str) -> Option<Region> {
+        self.regions.get(name).cloned()
+    }
+
+    pub fn trim(&self, name: &str) -> Option<Trim> {
+        self.trims.get(name).cloned()
+    }
+
+    pub fn sprite(&self, name: &str) -> Option<Sprite> {
+        self.region(name).map(|region| {
+            let mut sprite = Sprite::new(&self.texture);
+            sprite.region = Some(region);
+            sprite.trim = self.trim(name);
+            sprite
+        })
+    }
+}
+
+fn opaque() -> u8 {
+    255
+}
+
+fn no_tint() -> (u8, u8, u8) {
+    (255, 255, 255)
+}
+
+// Image drawn in place of the colored rectangle of a GameObject. The texture
+// is the path of the image asset, the region is the part of it to draw (the
+// whole image when None).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sprite {
+    pub texture: String,
+    #[serde(default)]
+    pub region: Option<Region>,
+
+    #[serde(default = "no_tint")]
+    pub tint: (u8, u8, u8),
+    #[serde(default = "opaque")]
+    pub alpha: u8,
+    #[serde(default)]
+    pub flip_horizontal: bool,
+    #[serde(default)]
+    pub flip_vertical: bool,
+
+    // Set when the region comes from a packed atlas.
+    #[serde(default)]
+    pub trim: Option<Trim>,
+}
+
+impl Sprite {
+
+    pub fn new(texture: &str) -> Sprite {
+        Sprite {
+            texture: texture.to_string(),
+            region: None,
+            tint: no_tint(),
+            alpha: opaque(),
+            flip_horizontal: false,
+            flip_vertical: false,
+            trim: None,
+        }
+    }
+
+    // Where the trimmed region goes when the whole original image is drawn
+    // in dst, with the size of the region once rotated back.
+    fn trimmed_dst(&self, dst: Rect) -> Rect {
+        let (trim, region) = match (self.trim, self.region) {
+            (Some(trim), Some(region)) => (trim, region),
+            _ => return dst,
+        };
+
+        let (w, h) = if trim.rotated { (region.h, region.w) } else { (region.w, region.h) };
+        let sx = dst.width() as f32 / trim.source_w.max(1) as f32;
+        let sy = dst.height() as f32 / trim.source_h.max(1) as f32;
+
+        let x = if self.flip_horizontal { trim.source_w as i32 - trim.x - w as i32 } else { trim.x };
+        let y = if self.flip_vertical { trim.source_h as i32 - trim.y - h as i32 } else { trim.y };
+        Rect::new(dst.x() + (x as f32 * sx).round() as i32,
+                  dst.y() + (y as f32 * sy).round() as i32,
+                  ((w as f32 * sx).round() as u32).max(1),
+                  ((h as f32 * sy).round() as u32).max(1))
+    }
+
+    // dst is already in screen coordinates.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, texture: &mut Texture, dst: Rect) {
+        self.render_rotated(canvas, texture, dst, 0.0);
+    }
+
+    // Same as render with the whole sprite rotated by angle degrees
+    // (clockwise) around the center of dst, for rotated cameras.
+    pub fn render_rotated<T: RenderTarget>(&self, canvas: &mut Canvas<T>, texture: &mut Texture, dst: Rect, angle: f64) {
+        texture.set_color_mod(self.tint.0, self.tint.1, self.tint.2);
+        texture.set_alpha_mod(self.alpha);
+
+        let dst = rotate_around(self.trimmed_dst(dst), dst, angle);
+        let rotated = self.trim.map(|t| t.rotated).unwrap_or(false);
+        if rotated {
+            // The region is stored rotated clockwise. Draw it in a rect of
+            // the swapped size and rotate it back around the center. Flips
+            // are applied before the rotation so the axes are swapped too.
+            let center = dst.center();
+            let unrotated = Rect::from_center(center, dst.height(), dst.width());
+            canvas.copy_ex(texture,
+                           self.region.map(|r| r.to_rect()),
+                           Some(unrotated),
+                           angle - 90.0,
+                           Point::new(unrotated.width() as i32 / 2, unrotated.height() as i32 / 2),
+                           self.flip_vertical,
+                           self.flip_horizontal).expect("Sprite render failed");
+        } else {
+            canvas.copy_ex(texture,
+                           self.region.map(|r| r.to_rect()),
+                           Some(dst),
+                           angle,
+                           None,
+                           self.flip_horizontal,
+                           self.flip_vertical).expect("Sprite render failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn atlas_from_grid() {
+        let atlas = Atlas::from_grid("characters.bmp", "char", 4, 3, 32, 30);
+        assert_eq!(atlas.regions.len(), 12);
+        assert_eq!(atlas.region("char0"), Some(Region::new(0, 0, 32, 30)));
+        assert_eq!(atlas.region("char6"), Some(Region::new(64, 30, 32, 30)));
+        assert_eq!(atlas.region("char12"), None);
+
+        let sprite = atlas.sprite("char5").unwrap();
+        assert_eq!(sprite.texture, "characters.bmp");
+        assert_eq!(sprite.region, Some(Region::new(32, 30, 32, 30)));
+    }
+
+    #[test]
+    fn sprite_defaults_when_deserializing() {
+        let sprite: Sprite = serde_json::from_str(r#"{"texture": "characters.bmp"}"#).unwrap();
+        assert_eq!(sprite.region, None);
+        assert_eq!(sprite.tint, (255, 255, 255));
+        assert_eq!(sprite.alpha, 255);
+        assert!(!sprite.flip_horizontal && !sprite.flip_vertical);
+    }
+
+    #[test]
+    fn trimmed_region_position() {
+        let mut sprite = Sprite::new("sheet.bmp");
+        sprite.region = Some(Region::new(100, 0, 10, 20));
+        sprite.trim = Some(Trim { x: 4, y: 2, source_w: 32, source_h: 32, rotated: false });
+
+        // Drawn at twice the original size.
+        let dst = Rect::new(0, 0, 64, 64);
+        assert_eq!(sprite.trimmed_dst(dst), Rect::new(8, 4, 20, 40));
+
+        sprite.flip_horizontal = true;
+        assert_eq!(sprite.trimmed_dst(dst), Rect::new(36, 4, 20, 40));
+
+        // Rotated in the sheet: 20x10 in the original image.
+        sprite.flip_horizontal = false;
+        sprite.trim = Some(Trim { x: 4, y: 2, source_w: 32, source_h: 32, rotated: true });
+        assert_eq!(sprite.trimmed_dst(dst), Rect::new(8, 4, 40, 20));
+    }
+}
@@ -0,0 +1,359 @@
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use serde_derive::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use super::assets::AssetManager;
+use super::core::{default_layer, Property, View};
+use super::physics::{BodyType, RigidBody};
+use super::sprite::Region;
+
+// A tile is the id of its image in the tileset, 0 for no tile, with the same
+// flip flags as Tiled in the high bits.
+pub const FLIP_HORIZONTAL: u32 = 0x8000_0000;
+pub const FLIP_VERTICAL: u32 = 0x4000_0000;
+pub const FLIP_DIAGONAL: u32 = 0x2000_0000;
+const FLIP_MASK: u32 = FLIP_HORIZONTAL | FLIP_VERTICAL | FLIP_DIAGONAL;
+
+pub fn tile_id(tile: u32) -> u32 {
+    tile & !FLIP_MASK
+}
+
+// Image cut in tiles of the same size. Tile ids start at 1, row by row.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tileset {
+    pub texture: String,
+    pub tile_w: u32,
+    pub tile_h: u32,
+    pub columns: u32,
+    // Border around the image and space between the tiles, in pixels.
+    #[serde(default)]
+    pub margin: u32,
+    #[serde(default)]
+    pub spacing: u32,
+}
+
+impl Tileset {
+
+    pub fn new(texture: &str, tile_w: u32, tile_h: u32, columns: u32) -> Tileset {
+        Tileset { texture: texture.to_string(), tile_w, tile_h, columns, margin: 0, spacing: 0 }
+    }
+
+    pub fn region(&self, tile: u32) -> Option<Region> {
+        let id = tile_id(tile);
+        if id == 0 || self.columns == 0 {
+            return None;
+        }
+
+        let idx = id - 1;
+        let (col, row) = (idx % self.columns, idx / self.columns);
+        Some(Region::new((self.margin + col * (self.tile_w + self.spacing)) as i32,
+                         (self.margin + row * (self.tile_h + self.spacing)) as i32,
+                         self.tile_w,
+                         self.tile_h))
+    }
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileLayer {
+    pub name: String,
+    // width * height tiles, row by row.
+    pub tiles: Vec<u32>,
+    #[serde(default = "visible")]
+    pub visible: bool,
+    // All the tiles of the layer are solid.
+    #[serde(default)]
+    pub collision: bool,
+    #[serde(default)]
+    pub properties: HashMap<String, Property>,
+}
+
+// Grid of tiles drawn with a single tileset. The layers are drawn in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tilemap {
+    pub tileset: Tileset,
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<TileLayer>,
+    // World position of the top left corner.
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+
+    // Render layer of the scene, see core::RenderLayer.
+    #[serde(default = "default_layer")]
+    pub layer: String,
+    #[serde(default)]
+    pub z_index: i32,
+}
+
+impl Tilemap {
+
+    pub fn new(tileset: Tileset, width: u32, height: u32) -> Tilemap {
+        Tilemap { tileset, width, height, layers: Vec::new(), x: 0, y: 0, layer: default_layer(), z_index: 0 }
+    }
+
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            tiles: vec![0; (self.width * self.height) as usize],
+            visible: true,
+            collision: false,
+            properties: HashMap::new(),
+        });
+        self.layers.len() - 1
+    }
+
+    pub fn layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    // 0 outside of the map.
+    pub fn tile(&self, layer: usize, col: i32, row: i32) -> u32 {
+        if col < 0 || row < 0 || col >= self.width as i32 || row >= self.height as i32 {
+            return 0;
+        }
+        self.layer_tile(&self.layers[layer], col as u32, row as u32)
+    }
+
+    // Layers of hand edited scenes can have fewer tiles than the map, the
+    // missing ones are empty.
+    fn layer_tile(&self, layer: &TileLayer, col: u32, row: u32) -> u32 {
+        layer.tiles.get((row * self.width + col) as usize).cloned().unwrap_or(0)
+    }
+
+    pub fn set_tile(&mut self, layer: usize, col: u32, row: u32, tile: u32) {
+        assert!(col < self.width && row < self.height, "tile ({}, {}) outside of the map", col, row);
+        let size = (self.width * self.height) as usize;
+        let tiles = &mut self.layers[layer].tiles;
+        if tiles.len() < size {
+            tiles.resize(size, 0);
+        }
+        tiles[(row * self.width + col) as usize] = tile;
+    }
+
+    // Cell containing a world position, if it is in the map.
+    pub fn cell_at(&self, x: f32, y: f32) -> Option<(u32, u32)> {
+        let col = ((x - self.x as f32) / self.tileset.tile_w as f32).floor();
+        let row = ((y - self.y as f32) / self.tileset.tile_h as f32).floor();
+        if col < 0.0 || row < 0.0 || col >= self.width as f32 || row >= self.height as f32 {
+            None
+        } else {
+            Some((col as u32, row as u32))
+        }
+    }
+
+    // Cells overlapping a world rectangle (usually View::visible_bounds), as
+    // a (first col, first row, end col, end row) range.
+    pub fn visible_cells(&self, bounds: (f32, f32, f32, f32)) -> (u32, u32, u32, u32) {
+        let (tw, th) = (self.tileset.tile_w as f32, self.tileset.tile_h as f32);
+        let (x, y, w, h) = bounds;
+        let left = x - self.x as f32;
+        let top = y - self.y as f32;
+        let clamp = |v: f32, max: u32| v.max(0.0).min(max as f32) as u32;
+
+        let col0 = clamp((left / tw).floor(), self.width);
+        let row0 = clamp((top / th).floor(), self.height);
+        let col1 = clamp(((left + w) / tw).ceil(), self.width);
+        let row1 = clamp(((top + h) / th).ceil(), self.height);
+        (col0, row0, col1, row1)
+    }
+
+    // Returns the number of tiles drawn.
+    pub fn render<T: RenderTarget>(&self, canvas: &mut Canvas<T>, view: &View, assets: &mut AssetManager) -> u32 {
+        // Collision only maps do not need a texture.
+        if !self.layers.iter().any(|l| l.visible) {
+            return 0;
+        }
+
+        let (col0, row0, col1, row1) = self.visible_cells(view.visible_bounds());
+
+        // The asset manager reports the error.
+        let texture = match assets.find_texture(&self.tileset.texture) {
+            Ok(texture) => texture,
+            Err(_) => return 0,
+        };
+
+        let mut drawn = 0;
+        let (tw, th) = (self.tileset.tile_w, self.tileset.tile_h);
+        for layer in self.layers.iter().filter(|l| l.visible) {
+            for row in row0..row1 {
+                for col in col0..col1 {
+                    let tile = self.layer_tile(layer, col, row);
+                    let region = match self.tileset.region(tile) {
+                        Some(region) => region,
+                        None => continue,
+                    };
+
+                    let dst = view.world_rect((self.x + (col * tw) as i32) as f32, (self.y + (row * th) as i32) as f32,
+                                              tw as f32, th as f32);
+
+                    // A diagonal flip is a rotation followed by a flip, see
+                    // the Tiled documentation.
+                    let (h, v) = (tile & FLIP_HORIZONTAL != 0, tile & FLIP_VERTICAL != 0);
+                    let (angle, flip_h, flip_v) = if tile & FLIP_DIAGONAL != 0 { (90.0, v, !h) } else { (0.0, h, v) };
+                    canvas.copy_ex(texture, Some(region.to_rect()), Some(dst), angle + view.angle(), None, flip_h, flip_v)
+                        .expect("Tilemap render failed");
+                    drawn += 1;
+                }
+            }
+        }
+        drawn
+    }
+
+    // Solid cells of the collision layers merged into as few rectangles as
+    // possible, in world coordinates. Runs of cells are found on each row
+    // and grown downward while the rows below have the same run.
+    pub fn collision_rects(&self) -> Vec<Rect> {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let mut solid = vec![false; w * h];
+        for layer in self.layers.iter().filter(|l| l.collision) {
+            for (s, &tile) in solid.iter_mut().zip(layer.tiles.iter()) {
+                *s |= tile_id(tile) != 0;
+            }
+        }
+
+        let mut rects = Vec::new();
+        for row in 0..h {
+            let mut col = 0;
+            while col < w {
+                if !solid[row * w + col] {
+                    col += 1;
+                    continue;
+                }
+
+                let start = col;
+                while col < w && solid[row * w + col] {
+                    col += 1;
+                }
+
+                let mut end_row = row + 1;
+                while end_row < h && (start..col).all(|c| solid[end_row * w + c]) {
+                    end_row += 1;
+                }
+                for r in row..end_row {
+                    for c in start..col {
+                        solid[r * w + c] = false;
+                    }
+                }
+
+                let (tw, th) = (self.tileset.tile_w, self.tileset.tile_h);
+                rects.push(Rect::new(self.x + (start as u32 * tw) as i32,
+                                     self.y + (row as u32 * th) as i32,
+                                     (col - start) as u32 * tw,
+                                     (end_row - row) as u32 * th));
+            }
+        }
+        rects
+    }
+
+    pub fn collision_bodies(&self) -> Vec<RigidBody> {
+        self.collision_rects().iter()
+            .map(|r| RigidBody::new(r.x() as f32, r.y() as f32, r.width(), r.height(), BodyType::Static))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::core::Camera;
+
+    fn map_from(rows: &[&str]) -> Tilemap {
+        let mut map = Tilemap::new(Tileset::new("tiles.bmp", 16, 16, 4), rows[0].len() as u32, rows.len() as u32);
+        let layer = map.add_layer("ground");
+        map.layers[layer].collision = true;
+        for (r, line) in rows.iter().enumerate() {
+            for (c, ch) in line.chars().enumerate() {
+                if ch == '#' {
+                    map.set_tile(layer, c as u32, r as u32, 1);
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn tileset_regions() {
+        let mut tileset = Tileset::new("tiles.bmp", 16, 16, 4);
+        assert_eq!(tileset.region(0), None);
+        assert_eq!(tileset.region(1), Some(Region::new(0, 0, 16, 16)));
+        assert_eq!(tileset.region(6), Some(Region::new(16, 16, 16, 16)));
+        assert_eq!(tileset.region(6 | FLIP_HORIZONTAL), Some(Region::new(16, 16, 16, 16)));
+
+        tileset.margin = 1;
+        tileset.spacing = 2;
+        assert_eq!(tileset.region(6), Some(Region::new(19, 19, 16, 16)));
+    }
+
+    #[test]
+    fn merged_collision() {
+        let map = map_from(&[
+            "......",
+            "##..##",
+            "##..##",
+            "######",
+        ]);
+        let rects = map.collision_rects();
+        assert_eq!(rects, vec![
+            Rect::new(0, 16, 32, 48),
+            Rect::new(64, 16, 32, 48),
+            Rect::new(32, 48, 32, 16),
+        ]);
+
+        // Only the collision layers count.
+        let mut map = map;
+        map.layers[0].collision = false;
+        assert!(map.collision_bodies().is_empty());
+    }
+
+    #[test]
+    fn visible_cells_of_camera() {
+        let mut map = map_from(&["..........", "..........", "..........", ".........."]);
+        assert_eq!(map.visible_cells((0.0, 0.0, 32.0, 32.0)), (0, 0, 2, 2));
+        assert_eq!(map.visible_cells((8.0, 8.0, 32.0, 32.0)), (0, 0, 3, 3));
+        assert_eq!(map.visible_cells((-100.0, -100.0, 32.0, 32.0)), (0, 0, 0, 0));
+        assert_eq!(map.visible_cells((100.0, 0.0, 1000.0, 1000.0)), (6, 0, 10, 4));
+
+        // Zoomed out views are wider than the output.
+        let mut camera = Camera::new();
+        camera.zoom = 0.5;
+        assert_eq!(map.visible_cells(camera.view(32, 32).visible_bounds()), (0, 0, 3, 3));
+
+        map.x = 32;
+        assert_eq!(map.visible_cells((0.0, 0.0, 48.0, 16.0)), (0, 0, 1, 1));
+        assert_eq!(map.cell_at(40.0, 20.0), Some((0, 1)));
+        assert_eq!(map.cell_at(20.0, 20.0), None);
+    }
+
+    #[test]
+    fn tilemap_serialization() {
+        let map = map_from(&["#.", ".#"]);
+        let data = serde_json::to_string(&map).unwrap();
+        let map: Tilemap = serde_json::from_str(&data).unwrap();
+        assert_eq!(map.tile(0, 1, 1), 1);
+        assert_eq!(map.tile(0, 1, 0), 0);
+        assert_eq!(map.tile(0, -1, 0), 0);
+        assert!(map.layers[0].visible);
+    }
+
+    #[test]
+    fn short_layers() {
+        let mut map = map_from(&["#.", ".#"]);
+        map.layers[0].tiles.truncate(2);
+        let data = serde_json::to_string(&map).unwrap();
+        let mut map: Tilemap = serde_json::from_str(&data).unwrap();
+        assert_eq!(map.tile(0, 0, 0), 1);
+        assert_eq!(map.tile(0, 1, 1), 0);
+        assert_eq!(map.collision_rects(), vec![Rect::new(0, 0, 16, 16)]);
+
+        map.set_tile(0, 1, 1, 2);
+        assert_eq!(map.layers[0].tiles, vec![1, 0, 0, 2]);
+    }
+}
@@ -0,0 +1,497 @@
+use std::f32::consts::PI;
+use sdl2::pixels::Color;
+use serde_derive::{Serialize, Deserialize};
+
+use super::math::Vector2d;
+
+type FloatVector = Vector2d<f32>;
+
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Inverse of lerp: where does `value` sit between a and b.
+pub fn inverse_lerp(a: f32, b: f32, value: f32) -> f32 {
+    if a == b {
+        0.0
+    } else {
+        (value - a) / (b - a)
+    }
+}
+
+// Anything that can be blended between two values. t is usually in [0, 1] but
+// some easings (Back, Elastic) overshoot so implementations should not clamp.
+pub trait Interpolate {
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &f32, t: f32) -> f32 {
+        lerp(*self, *other, t)
+    }
+}
+
+impl Interpolate for FloatVector {
+    fn interpolate(&self, other: &FloatVector, t: f32) -> FloatVector {
+        FloatVector::new(lerp(self.x, other.x, t), lerp(self.y, other.y, t))
+    }
+}
+
+impl Interpolate for Color {
+    fn interpolate(&self, other: &Color, t: f32) -> Color {
+        let channel = |a: u8, b: u8| {
+            lerp(a as f32, b as f32, t).round().clamp(0.0, 255.0) as u8
+        };
+        Color::RGBA(channel(self.r, other.r),
+                    channel(self.g, other.g),
+                    channel(self.b, other.b),
+                    channel(self.a, other.a))
+    }
+}
+
+// Colors as stored in scenes: sdl2 colors cannot be serialized.
+impl Interpolate for (u8, u8, u8, u8) {
+    fn interpolate(&self, other: &(u8, u8, u8, u8), t: f32) -> (u8, u8, u8, u8) {
+        let c = Color::RGBA(self.0, self.1, self.2, self.3).interpolate(&Color::RGBA(other.0, other.1, other.2, other.3), t);
+        (c.r, c.g, c.b, c.a)
+    }
+}
+
+// Robert Penner's easing equations. See https://easings.net for the curves.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    QuartIn,
+    QuartOut,
+    QuartInOut,
+    QuintIn,
+    QuintOut,
+    QuintInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    CircIn,
+    CircOut,
+    CircInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+}
+
+const BACK_C1: f32 = 1.70158;
+const BACK_C2: f32 = BACK_C1 * 1.525;
+const BACK_C3: f32 = BACK_C1 + 1.0;
+const ELASTIC_C4: f32 = (2.0 * PI) / 3.0;
+const ELASTIC_C5: f32 = (2.0 * PI) / 4.5;
+
+fn bounce_out(t: f32) -> f32 {
+    let n1 = 7.5625;
+    let d1 = 2.75;
+
+    if t < 1.0 / d1 {
+        n1 * t * t
+    } else if t < 2.0 / d1 {
+        let t = t - 1.5 / d1;
+        n1 * t * t + 0.75
+    } else if t < 2.5 / d1 {
+        let t = t - 2.25 / d1;
+        n1 * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d1;
+        n1 * t * t + 0.984375
+    }
+}
+
+impl Easing {
+
+    // Maps a normalized time t (clamped to [0, 1]) to an eased progress.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => if t < 0.5 {
+                2.0 * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+            },
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => if t < 0.5 {
+                4.0 * t * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+            },
+            Easing::QuartIn => t.powi(4),
+            Easing::QuartOut => 1.0 - (1.0 - t).powi(4),
+            Easing::QuartInOut => if t < 0.5 {
+                8.0 * t.powi(4)
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(4) / 2.0
+            },
+            Easing::QuintIn => t.powi(5),
+            Easing::QuintOut => 1.0 - (1.0 - t).powi(5),
+            Easing::QuintInOut => if t < 0.5 {
+                16.0 * t.powi(5)
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(5) / 2.0
+            },
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Easing::ExpoIn => if t == 0.0 { 0.0 } else { 2f32.powf(10.0 * t - 10.0) },
+            Easing::ExpoOut => if t == 1.0 { 1.0 } else { 1.0 - 2f32.powf(-10.0 * t) },
+            Easing::ExpoInOut => if t == 0.0 {
+                0.0
+            } else if t == 1.0 {
+                1.0
+            } else if t < 0.5 {
+                2f32.powf(20.0 * t - 10.0) / 2.0
+            } else {
+                (2.0 - 2f32.powf(-20.0 * t + 10.0)) / 2.0
+            },
+            Easing::CircIn => 1.0 - (1.0 - t * t).sqrt(),
+            Easing::CircOut => (1.0 - (t - 1.0).powi(2)).sqrt(),
+            Easing::CircInOut => if t < 0.5 {
+                (1.0 - (1.0 - (2.0 * t).powi(2)).sqrt()) / 2.0
+            } else {
+                ((1.0 - (-2.0 * t + 2.0).powi(2)).sqrt() + 1.0) / 2.0
+            },
+            Easing::BackIn => BACK_C3 * t * t * t - BACK_C1 * t * t,
+            Easing::BackOut => 1.0 + BACK_C3 * (t - 1.0).powi(3) + BACK_C1 * (t - 1.0).powi(2),
+            Easing::BackInOut => if t < 0.5 {
+                ((2.0 * t).powi(2) * ((BACK_C2 + 1.0) * 2.0 * t - BACK_C2)) / 2.0
+            } else {
+                ((2.0 * t - 2.0).powi(2) * ((BACK_C2 + 1.0) * (t * 2.0 - 2.0) + BACK_C2) + 2.0) / 2.0
+            },
+            Easing::ElasticIn => if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                -(2f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * ELASTIC_C4).sin()
+            },
+            Easing::ElasticOut => if t == 0.0 || t == 1.0 {
+                t
+            } else {
+                2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * ELASTIC_C4).sin() + 1.0
+            },
+            Easing::ElasticInOut => if t == 0.0 || t == 1.0 {
+                t
+            } else if t < 0.5 {
+                -(2f32.powf(20.0 * t - 10.0) * ((20.0 * t - 11.125) * ELASTIC_C5).sin()) / 2.0
+            } else {
+                (2f32.powf(-20.0 * t + 10.0) * ((20.0 * t - 11.125) * ELASTIC_C5).sin()) / 2.0 + 1.0
+            },
+            Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+            Easing::BounceOut => bounce_out(t),
+            Easing::BounceInOut => if t < 0.5 {
+                (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0
+            } else {
+                (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0
+            },
+        }
+    }
+}
+
+pub fn ease<T: Interpolate>(from: &T, to: &T, t: f32, easing: Easing) -> T {
+    from.interpolate(to, easing.apply(t))
+}
+
+pub fn cubic_bezier(p0: FloatVector, p1: FloatVector, p2: FloatVector, p3: FloatVector, t: f32) -> FloatVector {
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+// Uniform Catmull-Rom segment between p1 and p2.
+pub fn catmull_rom(p0: FloatVector, p1: FloatVector, p2: FloatVector, p3: FloatVector, t: f32) -> FloatVector {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+     + (p2 - p0) * t
+     + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+     + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+// A Catmull-Rom spline going through all its control points. The first and
+// last points are duplicated so the curve starts and ends on them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spline {
+    pub points: Vec<FloatVector>,
+}
+
+impl Spline {
+
+    pub fn new(points: Vec<FloatVector>) -> Spline {
+        Spline { points }
+    }
+
+    pub fn segments(&self) -> usize {
+        if self.points.len() < 2 { 0 } else { self.points.len() - 1 }
+    }
+
+    // t goes from 0 (first point) to 1 (last point).
+    pub fn point_at(&self, t: f32) -> FloatVector {
+        match self.points.len() {
+            0 => FloatVector::new(0.0, 0.0),
+            1 => self.points[0],
+            n => {
+                let t = t.clamp(0.0, 1.0) * self.segments() as f32;
+                let segment = (t.floor() as usize).min(n - 2);
+                let local_t = t - segment as f32;
+
+                let p0 = self.points[if segment == 0 { 0 } else { segment - 1 }];
+                let p1 = self.points[segment];
+                let p2 = self.points[segment + 1];
+                let p3 = self.points[(segment + 2).min(n - 1)];
+                catmull_rom(p0, p1, p2, p3, local_t)
+            }
+        }
+    }
+}
+
+// Lookup table from distance travelled along a curve to the curve parameter,
+// so that objects can move at constant speed on a bezier or a spline.
+#[derive(Debug, Clone)]
+pub struct ArcLengthTable {
+    // (t, cumulated length) pairs, sorted.
+    samples: Vec<(f32, f32)>,
+}
+
+impl ArcLengthTable {
+
+    pub fn new<F: Fn(f32) -> FloatVector>(curve: F, resolution: usize) -> ArcLengthTable {
+        let resolution = resolution.max(1);
+        let mut samples = Vec::with_capacity(resolution + 1);
+        let mut previous = curve(0.0);
+        let mut length = 0.0;
+        samples.push((0.0, 0.0));
+
+        for i in 1..=resolution {
+            let t = i as f32 / resolution as f32;
+            let point = curve(t);
+            length += previous.distance(point);
+            samples.push((t, length));
+            previous = point;
+        }
+
+        ArcLengthTable { samples }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.samples[self.samples.len() - 1].1
+    }
+
+    // Curve parameter at the given distance from the start.
+    pub fn t_at_distance(&self, distance: f32) -> f32 {
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        if distance >= self.length() {
+            return 1.0;
+        }
+
+        let idx = match self.samples.binary_search_by(|s| s.1.partial_cmp(&distance).unwrap()) {
+            Ok(idx) => return self.samples[idx].0,
+            Err(idx) => idx,
+        };
+        let (t0, l0) = self.samples[idx - 1];
+        let (t1, l1) = self.samples[idx];
+        lerp(t0, t1, inverse_lerp(l0, l1, distance))
+    }
+
+    // Same as t_at_distance but with the distance as a fraction of the length.
+    pub fn t_at_fraction(&self, u: f32) -> f32 {
+        self.t_at_distance(u * self.length())
+    }
+}
+
+// Value changing along t in [0, 1], linear between keys (t, value). The
+// value of the first and last keys is held before and after them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Curve<T> {
+    pub keys: Vec<(f32, T)>,
+}
+
+impl<T: Interpolate + Clone> Curve<T> {
+
+    pub fn new(mut keys: Vec<(f32, T)>) -> Curve<T> {
+        keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Curve { keys }
+    }
+
+    // None without keys.
+    pub fn sample(&self, t: f32) -> Option<T> {
+        let first = self.keys.first()?;
+        if t <= first.0 {
+            return Some(first.1.clone());
+        }
+        for pair in self.keys.windows(2) {
+            let ((t0, v0), (t1, v1)) = (&pair[0], &pair[1]);
+            if t < *t1 {
+                return Some(v0.interpolate(v1, inverse_lerp(*t0, *t1, t)));
+            }
+        }
+        self.keys.last().map(|k| k.1.clone())
+    }
+}
+
+// Animates a value from `from` to `to` in `duration` milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tween<T> {
+    pub from: T,
+    pub to: T,
+    pub duration: u32,
+    pub easing: Easing,
+    elapsed: u32,
+}
+
+impl<T: Interpolate> Tween<T> {
+
+    pub fn new(from: T, to: T, duration: u32, easing: Easing) -> Tween<T> {
+        Tween { from, to, duration, easing, elapsed: 0 }
+    }
+
+    // dt in milliseconds, same as Game::update.
+    pub fn update(&mut self, dt: u32) -> T {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        self.value()
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.duration == 0 {
+            1.0
+        } else {
+            self.elapsed as f32 / self.duration as f32
+        }
+    }
+
+    pub fn value(&self) -> T {
+        ease(&self.from, &self.to, self.progress(), self.easing)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn easing_reference_values() {
+        assert_eq_delta!(Easing::Linear.apply(0.3), 0.3, 0.0001);
+        assert_eq_delta!(Easing::QuadIn.apply(0.5), 0.25, 0.0001);
+        assert_eq_delta!(Easing::QuadOut.apply(0.5), 0.75, 0.0001);
+        assert_eq_delta!(Easing::CubicOut.apply(0.5), 0.875, 0.0001);
+        assert_eq_delta!(Easing::QuartInOut.apply(0.25), 0.03125, 0.0001);
+        assert_eq_delta!(Easing::SineInOut.apply(0.5), 0.5, 0.0001);
+        assert_eq_delta!(Easing::ExpoIn.apply(0.5), 0.03125, 0.0001);
+        assert_eq_delta!(Easing::CircOut.apply(0.5), 0.8660254, 0.0001);
+        assert_eq_delta!(Easing::BackIn.apply(0.5), -0.0876975, 0.0001);
+        assert_eq_delta!(Easing::ElasticOut.apply(0.5), 1.015625, 0.0001);
+        assert_eq_delta!(Easing::BounceOut.apply(0.5), 0.765625, 0.0001);
+        assert_eq_delta!(Easing::BounceIn.apply(0.5), 0.234375, 0.0001);
+    }
+
+    #[test]
+    fn curve_keys() {
+        let curve = Curve::new(vec![(1.0, 0.0), (0.0, 4.0), (0.5, 8.0)]);
+        assert_eq!(curve.sample(-1.0), Some(4.0));
+        assert_eq!(curve.sample(0.25), Some(6.0));
+        assert_eq!(curve.sample(0.75), Some(4.0));
+        assert_eq!(curve.sample(2.0), Some(0.0));
+        assert_eq!(Curve::<f32>::new(Vec::new()).sample(0.5), None);
+
+        let fade = Curve::new(vec![(0.0, (255, 200, 0, 255)), (1.0, (255, 0, 0, 0))]);
+        assert_eq!(fade.sample(0.5), Some((255, 100, 0, 128)));
+    }
+
+    #[test]
+    fn easing_end_points() {
+        let all = [Easing::Linear, Easing::QuadInOut, Easing::CubicInOut, Easing::QuintInOut,
+                   Easing::SineIn, Easing::ExpoInOut, Easing::CircInOut, Easing::BackInOut,
+                   Easing::ElasticIn, Easing::ElasticInOut, Easing::BounceInOut];
+        for easing in all.iter() {
+            assert_eq_delta!(easing.apply(0.0), 0.0, 0.0001);
+            assert_eq_delta!(easing.apply(1.0), 1.0, 0.0001);
+        }
+    }
+
+    #[test]
+    fn interpolate_color() {
+        let c = Color::RGBA(0, 100, 200, 255).interpolate(&Color::RGBA(255, 200, 0, 255), 0.5);
+        assert_eq!(c, Color::RGBA(128, 150, 100, 255));
+    }
+
+    #[test]
+    fn bezier_and_catmull_rom_end_points() {
+        let p0 = FloatVector::new(0.0, 0.0);
+        let p1 = FloatVector::new(0.0, 10.0);
+        let p2 = FloatVector::new(10.0, 10.0);
+        let p3 = FloatVector::new(10.0, 0.0);
+
+        assert_eq!(cubic_bezier(p0, p1, p2, p3, 0.0), p0);
+        assert_eq!(cubic_bezier(p0, p1, p2, p3, 1.0), p3);
+        let mid = cubic_bezier(p0, p1, p2, p3, 0.5);
+        assert_eq_delta!(mid.x, 5.0, 0.0001);
+        assert_eq_delta!(mid.y, 7.5, 0.0001);
+
+        assert_eq!(catmull_rom(p0, p1, p2, p3, 0.0), p1);
+        assert_eq!(catmull_rom(p0, p1, p2, p3, 1.0), p2);
+        let mid = catmull_rom(p0, p1, p2, p3, 0.5);
+        assert_eq_delta!(mid.x, 5.0, 0.0001);
+        assert_eq_delta!(mid.y, 11.25, 0.0001);
+    }
+
+    #[test]
+    fn spline_goes_through_points() {
+        let spline = Spline::new(vec![FloatVector::new(0.0, 0.0),
+                                      FloatVector::new(10.0, 0.0),
+                                      FloatVector::new(20.0, 10.0)]);
+        assert_eq!(spline.point_at(0.0), FloatVector::new(0.0, 0.0));
+        assert_eq!(spline.point_at(0.5), FloatVector::new(10.0, 0.0));
+        assert_eq!(spline.point_at(1.0), FloatVector::new(20.0, 10.0));
+    }
+
+    #[test]
+    fn arc_length_of_line() {
+        let a = FloatVector::new(0.0, 0.0);
+        let b = FloatVector::new(30.0, 40.0);
+        // Quadratic parameterization: speed is not constant along t.
+        let table = ArcLengthTable::new(|t| a.interpolate(&b, t * t), 100);
+
+        assert_eq_delta!(table.length(), 50.0, 0.001);
+        assert_eq_delta!(table.t_at_fraction(0.25), 0.5, 0.01);
+        assert_eq_delta!(table.t_at_distance(50.0), 1.0, 0.0001);
+    }
+
+    #[test]
+    fn tween_reaches_target() {
+        let mut tween = Tween::new(0.0, 10.0, 100, Easing::QuadIn);
+        assert_eq_delta!(tween.update(50), 2.5, 0.0001);
+        assert!(!tween.is_finished());
+        assert_eq_delta!(tween.update(80), 10.0, 0.0001);
+        assert!(tween.is_finished());
+    }
+}